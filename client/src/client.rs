@@ -0,0 +1,435 @@
+//! # Client
+//! Connection of a player to a game, through the lobby.
+//!
+//! The client sends its hello to the lobby, joins the game of the ticket and keeps the session token.
+//! A reader thread answers pings, acknowledges states and hands every packet of the game over as an event,
+//! through a channel or a callback. When the connection is lost, it resumes the session with the token.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::marker::PhantomData;
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use blizzard_server::game::MatchPhase;
+use blizzard_server::protocol::{ClientPacket, JoinTicket, LobbyPacket, RosterEntry, ServerPacket};
+
+use crate::config::ClientConfig;
+use crate::error::ClientError;
+
+/// What the game sent, or what happened to the connection
+/// # Type definitions
+/// * K: Shared state type of the game
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent<K> {
+    /// Shared state of the game, after the server tick
+    State { tick: u64, state: K },
+    /// Match phase changed, the state after `Finished` is the final one
+    Phase(MatchPhase),
+    /// Players of the game, with the ready check of the server
+    Roster(Vec<RosterEntry>),
+    /// Message of the server operators, to show to the player
+    Notice(String),
+    /// Connection was lost, the client is resuming the session
+    Disconnected,
+    /// Session resumed after a lost connection
+    Reconnected,
+    /// Client is closed, no more events follow
+    Closed(CloseReason),
+}
+
+/// Why a client closed
+#[derive(Debug, Clone, PartialEq)]
+pub enum CloseReason {
+    /// Server shut down, with the reason of the operators
+    Shutdown(String),
+    /// Game removed the player, with the reason of the server
+    Rejected(String),
+    /// Connection was lost and could not be resumed
+    ConnectionLost,
+    /// Client was closed or dropped
+    ClientClosed,
+}
+
+/// Receives the events of the reader thread
+type Deliver<K> = Box<dyn FnMut(ClientEvent<K>) + Send>;
+
+/// Player connected to a game.
+/// Dropping the client closes the connection.
+/// # Type definitions
+/// * M: Message type of the game
+/// * K: Shared state type of the game
+/// # Example
+/// ```no_run
+/// use blizzard_client::{Client, ClientConfig, ClientEvent};
+///
+/// let config = ClientConfig::builder("127.0.0.1:8888", "player").build().unwrap();
+/// let client: Client<u32, u32> = Client::connect(config).expect("Could not join a game");
+/// client.send(1).unwrap();
+/// for event in client.events() {
+///     if let ClientEvent::State { state, .. } = event {
+///         println!("{}", state);
+///     }
+/// }
+/// ```
+pub struct Client<M, K> {
+    shared: Arc<Shared>,
+    events: Receiver<ClientEvent<K>>,
+    reader: Option<JoinHandle<()>>,
+    player_id: usize,
+    room_code: Option<String>,
+    message: PhantomData<M>,
+}
+
+/// State of the client shared with its reader thread
+struct Shared {
+    // Replaced when the session is resumed
+    stream: Mutex<TcpStream>,
+    // Last server tick received
+    tick: Mutex<u64>,
+    closing: Mutex<bool>,
+    closed: Mutex<bool>,
+}
+
+impl Shared {
+    /// Writes a packet to the game
+    fn write<P: Serialize>(&self, packet: &P) -> Result<(), ClientError> {
+        write_packet(&mut lock(&self.stream), packet)
+    }
+
+    fn is_closing(&self) -> bool {
+        *lock(&self.closing)
+    }
+}
+
+/// Session of the player in its game
+struct Session {
+    game_address: SocketAddr,
+    token: String,
+}
+
+impl<M, K> Client<M, K>
+where
+    M: Serialize,
+    K: DeserializeOwned + Send + 'static,
+{
+    /// Finds a game through the lobby and joins it, events are received with `events`
+    pub fn connect(config: ClientConfig) -> Result<Client<M, K>, ClientError> {
+        let (sender, events) = mpsc::channel();
+        let deliver = move |event| sender.send(event).unwrap_or(());
+        Client::start(config, Box::new(deliver), events)
+    }
+
+    /// Finds a game through the lobby and joins it, events are passed to the callback
+    /// The callback runs on the reader thread, `events` stays empty.
+    pub fn connect_with<F>(config: ClientConfig, callback: F) -> Result<Client<M, K>, ClientError>
+    where
+        F: FnMut(ClientEvent<K>) + Send + 'static,
+    {
+        let (_, events) = mpsc::channel();
+        Client::start(config, Box::new(callback), events)
+    }
+
+    fn start(
+        config: ClientConfig,
+        deliver: Deliver<K>,
+        events: Receiver<ClientEvent<K>>,
+    ) -> Result<Client<M, K>, ClientError> {
+        let (game_address, ticket) = find_game(&config)?;
+        let join: ClientPacket<()> = ClientPacket::Join {
+            ticket: ticket.id.clone(),
+        };
+        let (stream, reader, player_id, token) = join_game::<K>(game_address, &join, &config)?;
+
+        let shared = Arc::new(Shared {
+            stream: Mutex::new(stream),
+            tick: Mutex::new(0),
+            closing: Mutex::new(false),
+            closed: Mutex::new(false),
+        });
+        let session = Session {
+            game_address,
+            token,
+        };
+
+        let reader_shared = Arc::clone(&shared);
+        let builder = thread::Builder::new().name("Client-reader".to_string());
+        let reader = builder
+            .spawn(move || read_packets(reader_shared, reader, session, config, deliver))
+            .expect("Could not create thread");
+
+        Ok(Client {
+            shared,
+            events,
+            reader: Some(reader),
+            player_id,
+            room_code: ticket.room_code,
+            message: PhantomData,
+        })
+    }
+
+    /// Sends a game message, for the last server tick received
+    pub fn send(&self, message: M) -> Result<(), ClientError> {
+        let tick = self.tick();
+        self.send_packet(&ClientPacket::Input { tick, message })
+    }
+
+    /// Sends any packet to the game
+    pub fn send_packet(&self, packet: &ClientPacket<M>) -> Result<(), ClientError> {
+        if self.is_closed() || self.shared.is_closing() {
+            return Err(ClientError::Closed);
+        }
+        self.shared.write(packet)
+    }
+
+    /// Tells the game the player is ready, or no longer ready, for the match to start
+    pub fn ready(&self, ready: bool) -> Result<(), ClientError> {
+        self.send_packet(&ClientPacket::Ready(ready))
+    }
+
+    /// Joins a team, before the match starts
+    pub fn pick_team(&self, team: &str) -> Result<(), ClientError> {
+        self.send_packet(&ClientPacket::PickTeam(team.to_string()))
+    }
+
+    /// Picks an option, before the match starts
+    pub fn set_option(&self, key: &str, value: &str) -> Result<(), ClientError> {
+        self.send_packet(&ClientPacket::SetOption {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// Starts the match of the private room, the player must be its host
+    pub fn start_match(&self) -> Result<(), ClientError> {
+        self.send_packet(&ClientPacket::StartMatch)
+    }
+}
+
+impl<M, K> Client<M, K> {
+    /// Events of the game, the channel ends after `ClientEvent::Closed`
+    pub fn events(&self) -> &Receiver<ClientEvent<K>> {
+        &self.events
+    }
+
+    /// Id of the player in the game, kept when the session is resumed
+    pub fn player_id(&self) -> usize {
+        self.player_id
+    }
+
+    /// Join code of the private room, to share with friends
+    pub fn room_code(&self) -> Option<&str> {
+        self.room_code.as_deref()
+    }
+
+    /// Last server tick received
+    pub fn tick(&self) -> u64 {
+        *lock(&self.shared.tick)
+    }
+
+    /// Determine if the client is closed, by the server, a lost connection or `close`
+    pub fn is_closed(&self) -> bool {
+        *lock(&self.shared.closed)
+    }
+
+    /// Leaves the game, the server removes the player
+    pub fn close(&self) {
+        *lock(&self.shared.closing) = true;
+        lock(&self.shared.stream)
+            .shutdown(Shutdown::Both)
+            .unwrap_or(());
+    }
+}
+
+impl<M, K> Drop for Client<M, K> {
+    fn drop(&mut self) {
+        self.close();
+        if let Some(reader) = self.reader.take() {
+            reader.join().unwrap_or(());
+        }
+    }
+}
+
+/// Reads the packets of the game until the client closes, resuming the session when the connection is lost
+fn read_packets<K: DeserializeOwned>(
+    shared: Arc<Shared>,
+    mut reader: BufReader<TcpStream>,
+    mut session: Session,
+    config: ClientConfig,
+    mut deliver: Deliver<K>,
+) {
+    let reason = loop {
+        let packet = match read_packet(&mut reader) {
+            Ok(packet) => packet,
+            // A state of another type is the game's fault, not the connection's
+            Err(ClientError::MalformedPacket(_)) => continue,
+            Err(_) if shared.is_closing() => break CloseReason::ClientClosed,
+            Err(_) => {
+                deliver(ClientEvent::Disconnected);
+                match resume::<K>(&shared, &mut session, &config) {
+                    Some(resumed) => {
+                        reader = resumed;
+                        deliver(ClientEvent::Reconnected);
+                        continue;
+                    }
+                    None if shared.is_closing() => break CloseReason::ClientClosed,
+                    None => break CloseReason::ConnectionLost,
+                }
+            }
+        };
+
+        // Write errors show up as a lost connection on the next read
+        match packet {
+            ServerPacket::State { tick, state } => {
+                *lock(&shared.tick) = tick;
+                shared.write(&ClientPacket::<()>::Ack(tick)).unwrap_or(());
+                deliver(ClientEvent::State { tick, state });
+            }
+            ServerPacket::Ping(ping) => shared.write(&ClientPacket::<()>::Pong(ping)).unwrap_or(()),
+            ServerPacket::Phase(phase) => deliver(ClientEvent::Phase(phase)),
+            ServerPacket::Roster(roster) => deliver(ClientEvent::Roster(roster)),
+            ServerPacket::Notice(notice) => deliver(ClientEvent::Notice(notice)),
+            ServerPacket::Session { token, .. } => session.token = token,
+            ServerPacket::Rejected { reason } => break CloseReason::Rejected(reason),
+            ServerPacket::Shutdown { reason } => break CloseReason::Shutdown(reason),
+        }
+    };
+
+    *lock(&shared.closed) = true;
+    lock(&shared.stream).shutdown(Shutdown::Both).unwrap_or(());
+    deliver(ClientEvent::Closed(reason));
+}
+
+/// Resumes the session with its token, returns the reader of the new connection
+/// Gives up after the reconnect attempts of the config, when the session expired or the client is closing.
+fn resume<K: DeserializeOwned>(
+    shared: &Shared,
+    session: &mut Session,
+    config: &ClientConfig,
+) -> Option<BufReader<TcpStream>> {
+    let packet: ClientPacket<()> = ClientPacket::Resume {
+        token: session.token.clone(),
+    };
+    for _ in 0..config.reconnect_attempts {
+        thread::sleep(config.reconnect_delay);
+        if shared.is_closing() {
+            return None;
+        }
+
+        match join_game::<K>(session.game_address, &packet, config) {
+            Ok((stream, reader, _, token)) => {
+                // Closing shuts down whichever stream is current
+                let mut current = lock(&shared.stream);
+                if shared.is_closing() {
+                    stream.shutdown(Shutdown::Both).unwrap_or(());
+                    return None;
+                }
+                *current = stream;
+                session.token = token;
+                return Some(reader);
+            }
+            // The session expired or the game is gone
+            Err(ClientError::JoinRejected(_)) => return None,
+            Err(_) => continue,
+        }
+    }
+    None
+}
+
+/// Sends the hello to the lobby, returns the address of the game and its ticket
+fn find_game(config: &ClientConfig) -> Result<(SocketAddr, JoinTicket), ClientError> {
+    let mut stream = open(&config.lobby_address, config.handshake_timeout)?;
+    write_packet(&mut stream, &config.hello)?;
+    let lobby = stream.peer_addr()?;
+
+    let ticket = match read_packet(&mut BufReader::new(stream))? {
+        LobbyPacket::Welcome(ticket) => ticket,
+        LobbyPacket::Rejected(reason) => return Err(ClientError::LobbyRejected(reason)),
+    };
+
+    // Games without a public host live next to the lobby
+    let game_address = match &ticket.host {
+        Some(host) => resolve(&format!("{}:{}", host, ticket.port))?,
+        None => SocketAddr::new(lobby.ip(), ticket.port as u16),
+    };
+    Ok((game_address, ticket))
+}
+
+/// Sends the first packet to the game, returns the connection, its reader, the player id and the session token
+fn join_game<K: DeserializeOwned>(
+    address: SocketAddr,
+    packet: &ClientPacket<()>,
+    config: &ClientConfig,
+) -> Result<(TcpStream, BufReader<TcpStream>, usize, String), ClientError> {
+    let mut stream = TcpStream::connect_timeout(&address, config.handshake_timeout)?;
+    stream.set_read_timeout(Some(config.handshake_timeout))?;
+    stream.set_write_timeout(Some(config.handshake_timeout))?;
+    write_packet(&mut stream, packet)?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (player_id, token) = match read_packet::<ServerPacket<K>>(&mut reader)? {
+        ServerPacket::Session { player_id, token } => (player_id, token),
+        ServerPacket::Rejected { reason } => return Err(ClientError::JoinRejected(reason)),
+        _ => return Err(ClientError::UnexpectedPacket),
+    };
+
+    // The server pings every heartbeat interval, silence means the connection is lost
+    stream.set_read_timeout(Some(config.server_timeout))?;
+    stream.set_write_timeout(Some(config.server_timeout))?;
+    Ok((stream, reader, player_id, token))
+}
+
+/// Connects to the first address of a host that answers in time
+fn open(address: &str, timeout: Duration) -> Result<TcpStream, ClientError> {
+    let mut last_error = None;
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(last_error
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Address not found"))
+        .into())
+}
+
+/// First address of a host
+fn resolve(address: &str) -> Result<SocketAddr, ClientError> {
+    address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Address not found").into())
+}
+
+/// Writes a packet as a line of JSON
+fn write_packet<P: Serialize>(stream: &mut TcpStream, packet: &P) -> Result<(), ClientError> {
+    let mut line = serde_json::to_vec(packet).map_err(ClientError::Serialize)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    Ok(())
+}
+
+/// Reads a newline terminated JSON packet
+fn read_packet<P: DeserializeOwned>(reader: &mut BufReader<TcpStream>) -> Result<P, ClientError> {
+    let mut line = vec![];
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Err(ClientError::ConnectionClosed);
+    }
+    serde_json::from_slice(&line).map_err(ClientError::MalformedPacket)
+}
+
+/// Locks a mutex, a panic of another thread leaves its data usable
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
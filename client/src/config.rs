@@ -0,0 +1,135 @@
+//! # Config
+//! Where the client connects, who the player is and how long it waits for the server.
+
+use std::time::Duration;
+
+use blizzard_server::protocol::{Hello, RoomRequest};
+
+use crate::error::ClientError;
+
+/// Settings of a client, built with `ClientConfig::builder`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientConfig {
+    /// Address of the lobby, like `"127.0.0.1:8888"`
+    pub lobby_address: String,
+    /// Hello sent to the lobby, with the player and the room to play in
+    pub hello: Hello,
+    /// Time the lobby and the game have to answer while connecting
+    pub handshake_timeout: Duration,
+    /// Time without packets before the connection is considered lost,
+    /// longer than the heartbeat interval of the server
+    pub server_timeout: Duration,
+    /// Times a lost connection is resumed before the client closes
+    pub reconnect_attempts: u32,
+    /// Wait between attempts to resume
+    pub reconnect_delay: Duration,
+}
+
+impl ClientConfig {
+    /// Starts a config for the player of a username, playing wherever the matchmaker sends it
+    pub fn builder(lobby_address: &str, username: &str) -> ClientConfigBuilder {
+        ClientConfigBuilder {
+            config: ClientConfig {
+                lobby_address: lobby_address.to_string(),
+                hello: Hello::new(username),
+                handshake_timeout: Duration::from_secs(10),
+                server_timeout: Duration::from_secs(10),
+                reconnect_attempts: 5,
+                reconnect_delay: Duration::from_secs(1),
+            },
+        }
+    }
+}
+
+/// Builder of client configs.
+/// # Example
+/// ```
+/// use blizzard_client::ClientConfig;
+/// use std::time::Duration;
+///
+/// let config = ClientConfig::builder("127.0.0.1:8888", "player")
+///     .metadata("color", "blue")
+///     .reconnect_attempts(3)
+///     .server_timeout(Duration::from_secs(5))
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.hello.username, "player");
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientConfigBuilder {
+    config: ClientConfig,
+}
+
+impl ClientConfigBuilder {
+    /// Adds custom data of the player, passed on to the game
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.config
+            .hello
+            .metadata
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Creates a private room, the player is its host
+    pub fn create_room(mut self, password: Option<&str>) -> Self {
+        self.config.hello.room = Some(RoomRequest::Create {
+            password: password.map(str::to_string),
+        });
+        self
+    }
+
+    /// Joins the private room of a code
+    pub fn join_room(mut self, code: &str, password: Option<&str>) -> Self {
+        self.config.hello.room = Some(RoomRequest::Join {
+            code: code.to_string(),
+            password: password.map(str::to_string),
+        });
+        self
+    }
+
+    /// Secret or signed token, for the authenticator of the server
+    pub fn credentials(mut self, credentials: &str) -> Self {
+        self.config.hello.credentials = Some(credentials.to_string());
+        self
+    }
+
+    /// Time the lobby and the game have to answer while connecting
+    pub fn handshake_timeout(mut self, handshake_timeout: Duration) -> Self {
+        self.config.handshake_timeout = handshake_timeout;
+        self
+    }
+
+    /// Time without packets before the connection is lost, longer than the server heartbeat
+    pub fn server_timeout(mut self, server_timeout: Duration) -> Self {
+        self.config.server_timeout = server_timeout;
+        self
+    }
+
+    /// Zero closes the client as soon as the connection is lost
+    pub fn reconnect_attempts(mut self, reconnect_attempts: u32) -> Self {
+        self.config.reconnect_attempts = reconnect_attempts;
+        self
+    }
+
+    /// Wait before each attempt to resume
+    pub fn reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self.config.reconnect_delay = reconnect_delay;
+        self
+    }
+
+    /// Checks the values and builds the config
+    pub fn build(self) -> Result<ClientConfig, ClientError> {
+        let config = self.config;
+        if config.handshake_timeout == Duration::from_secs(0) {
+            return Err(ClientError::InvalidConfig(
+                "handshake_timeout must be longer than zero".into(),
+            ));
+        }
+        if config.server_timeout == Duration::from_secs(0) {
+            return Err(ClientError::InvalidConfig(
+                "server_timeout must be longer than zero".into(),
+            ));
+        }
+        Ok(config)
+    }
+}
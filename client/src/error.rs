@@ -0,0 +1,65 @@
+//! # Error
+//! Errors of the client, while connecting or talking to a game.
+
+use std::fmt;
+use std::io;
+
+use blizzard_server::protocol::RejectReason;
+
+/// Client error definition
+#[derive(Debug)]
+pub enum ClientError {
+    /// Connecting, reading or writing failed
+    Io(io::Error),
+    /// The server sent a packet that could not be deserialized
+    MalformedPacket(serde_json::Error),
+    /// A packet of the client could not be serialized
+    Serialize(serde_json::Error),
+    /// The lobby found no game for the client
+    LobbyRejected(RejectReason),
+    /// The game did not take the player, with the reason of the server
+    JoinRejected(String),
+    /// The server sent a packet that is not valid at this point of the protocol
+    UnexpectedPacket,
+    /// The server closed the connection
+    ConnectionClosed,
+    /// A value of the config is out of range
+    InvalidConfig(String),
+    /// The client is closed, nothing more can be sent
+    Closed,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(error) => write!(f, "I/O error: {}", error),
+            ClientError::MalformedPacket(error) => write!(f, "Malformed packet: {}", error),
+            ClientError::Serialize(error) => write!(f, "Could not serialize packet: {}", error),
+            ClientError::LobbyRejected(reason) => {
+                write!(f, "Lobby rejected the client: {:?}", reason)
+            }
+            ClientError::JoinRejected(reason) => write!(f, "Could not join: {}", reason),
+            ClientError::UnexpectedPacket => write!(f, "Unexpected packet"),
+            ClientError::ConnectionClosed => write!(f, "Server closed the connection"),
+            ClientError::InvalidConfig(reason) => write!(f, "Invalid config: {}", reason),
+            ClientError::Closed => write!(f, "Client is closed"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Io(error) => Some(error),
+            ClientError::MalformedPacket(error) => Some(error),
+            ClientError::Serialize(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(error: io::Error) -> Self {
+        ClientError::Io(error)
+    }
+}
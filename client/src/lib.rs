@@ -0,0 +1,34 @@
+//! # Blizzard Client
+//!
+//! Client library for games running on the Blizzard Server Engine.
+//! A `Client` finds a game through the lobby, joins it, answers the heartbeats of the server,
+//! and resumes the session when the connection drops. Games only send their messages and handle the states.
+//!
+//! ```no_run
+//! use blizzard_client::{Client, ClientConfig, ClientEvent};
+//!
+//! let config = ClientConfig::builder("127.0.0.1:8888", "player")
+//!     .build()
+//!     .unwrap();
+//! let client: Client<u32, u32> = Client::connect_with(config, |event| match event {
+//!     ClientEvent::State { state, .. } => println!("State: {}", state),
+//!     ClientEvent::Closed(reason) => println!("Closed: {:?}", reason),
+//!     _ => {}
+//! })
+//! .expect("Could not join a game");
+//! client.send(1).unwrap();
+//! ```
+
+extern crate blizzard_server;
+extern crate serde;
+extern crate serde_json;
+
+pub mod client;
+pub mod config;
+pub mod error;
+
+pub use blizzard_server::game::MatchPhase;
+pub use blizzard_server::protocol::{RejectReason, RosterEntry};
+pub use client::{Client, ClientEvent, CloseReason};
+pub use config::{ClientConfig, ClientConfigBuilder};
+pub use error::ClientError;
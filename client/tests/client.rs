@@ -0,0 +1,235 @@
+extern crate blizzard_client;
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+use blizzard_client::{Client, ClientConfig, ClientError, ClientEvent, CloseReason, RejectReason};
+use blizzard_engine::game::Game;
+use blizzard_server::game::PlayerEvent;
+use blizzard_server::protocol::{ClientPacket, JoinTicket, LobbyPacket, ServerPacket};
+use blizzard_server::server::{Server, ServerConfig};
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Counts every message received from clients
+#[derive(Clone)]
+struct CountGame {}
+
+impl Game<u32, u32> for CountGame {
+    fn world_config(&mut self) {}
+    fn update(&mut self, input: u32, shared_state: Arc<Mutex<u32>>) {
+        *shared_state.lock().unwrap() += input;
+    }
+    fn reset_input(&mut self, input: Arc<Mutex<u32>>) {
+        *input.lock().unwrap() = 0;
+    }
+    fn render(&mut self) {}
+    fn end_game(&self) -> bool {
+        false
+    }
+}
+
+fn handle_input(receiver: Receiver<(PlayerEvent<u32>, usize)>, input: Arc<Mutex<u32>>) -> u32 {
+    for (event, _) in receiver {
+        if let PlayerEvent::Message { message, .. } = event {
+            *input.lock().unwrap() += message;
+        }
+    }
+    0
+}
+
+/// Reads a packet of the client
+fn receive(reader: &mut BufReader<TcpStream>) -> ClientPacket<u32> {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    serde_json::from_str(&line).unwrap()
+}
+
+fn send<P: serde::Serialize>(stream: &mut TcpStream, packet: &P) {
+    let line = serde_json::to_string(packet).unwrap() + "\n";
+    stream.write_all(line.as_bytes()).unwrap();
+}
+
+/// Waits for an event, skipping the others
+fn wait_for(events: &Receiver<ClientEvent<u32>>, expected: ClientEvent<u32>) {
+    loop {
+        let event = events.recv_timeout(TIMEOUT).unwrap();
+        if event == expected {
+            return;
+        }
+        if let ClientEvent::Closed(_) = event {
+            panic!("Closed before {:?}", expected);
+        }
+    }
+}
+
+#[test]
+fn clients_play_until_the_server_shuts_down() {
+    let config = ServerConfig::builder()
+        .bind_address("127.0.0.1".parse().unwrap())
+        .lobby_port(39301)
+        .ephemeral_game_ports()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .shutdown_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let server = Server::start(config, CountGame {}, 0, 0, &handle_input);
+
+    let config = ClientConfig::builder("127.0.0.1:39301", "player")
+        .build()
+        .unwrap();
+    let client: Client<u32, u32> = Client::connect(config).unwrap();
+    client.send(2).unwrap();
+    loop {
+        match client.events().recv_timeout(TIMEOUT).unwrap() {
+            ClientEvent::State { state: 2, .. } => break,
+            ClientEvent::Closed(reason) => panic!("Closed: {:?}", reason),
+            _ => continue,
+        }
+    }
+    assert!(client.tick() > 0);
+
+    server.shutdown("Maintenance");
+    wait_for(
+        client.events(),
+        ClientEvent::Closed(CloseReason::Shutdown("Maintenance".into())),
+    );
+    assert!(client.is_closed());
+    match client.send(1) {
+        Err(ClientError::Closed) => {}
+        other => panic!("Sent to a closed game: {:?}", other),
+    }
+}
+
+#[test]
+fn lost_connections_resume_the_session() {
+    let lobby = TcpListener::bind("127.0.0.1:0").unwrap();
+    let game = TcpListener::bind("127.0.0.1:0").unwrap();
+    let lobby_address = lobby.local_addr().unwrap().to_string();
+    let port = game.local_addr().unwrap().port() as i32;
+
+    // Lobby and game that drop the player once, then check its heartbeats and acks
+    let server = thread::spawn(move || {
+        let (mut stream, _) = lobby.accept().unwrap();
+        BufReader::new(&stream)
+            .read_line(&mut String::new())
+            .unwrap();
+        let ticket = JoinTicket {
+            id: "ticket".into(),
+            host: None,
+            port,
+            room_code: None,
+        };
+        send(&mut stream, &LobbyPacket::Welcome(ticket));
+
+        let (mut stream, _) = game.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert_eq!(
+            receive(&mut reader),
+            ClientPacket::Join {
+                ticket: "ticket".into()
+            }
+        );
+        let session: ServerPacket<u32> = ServerPacket::Session {
+            player_id: 3,
+            token: "token".into(),
+        };
+        send(&mut stream, &session);
+        drop(reader);
+        drop(stream);
+
+        let (mut stream, _) = game.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert_eq!(
+            receive(&mut reader),
+            ClientPacket::Resume {
+                token: "token".into()
+            }
+        );
+        send(&mut stream, &session);
+        send(&mut stream, &ServerPacket::<u32>::Ping(7));
+        assert_eq!(receive(&mut reader), ClientPacket::Pong(7));
+        send(&mut stream, &ServerPacket::State { tick: 5, state: 9 });
+        assert_eq!(receive(&mut reader), ClientPacket::Ack(5));
+        assert_eq!(
+            receive(&mut reader),
+            ClientPacket::Input {
+                tick: 5,
+                message: 1
+            }
+        );
+        let shutdown = ServerPacket::<u32>::Shutdown {
+            reason: "Done".into(),
+        };
+        send(&mut stream, &shutdown);
+    });
+
+    let (events, received) = mpsc::channel();
+    let config = ClientConfig::builder(&lobby_address, "player")
+        .reconnect_delay(Duration::from_millis(10))
+        .build()
+        .unwrap();
+    let client: Client<u32, u32> =
+        Client::connect_with(config, move |event| events.send(event).unwrap()).unwrap();
+    assert_eq!(client.player_id(), 3);
+
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        ClientEvent::Disconnected
+    );
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        ClientEvent::Reconnected
+    );
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        ClientEvent::State { tick: 5, state: 9 }
+    );
+    client.send(1).unwrap();
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        ClientEvent::Closed(CloseReason::Shutdown("Done".into()))
+    );
+    server.join().unwrap();
+}
+
+#[test]
+fn lobby_rejections_are_returned() {
+    let lobby = TcpListener::bind("127.0.0.1:0").unwrap();
+    let lobby_address = lobby.local_addr().unwrap().to_string();
+    thread::spawn(move || {
+        let (mut stream, _) = lobby.accept().unwrap();
+        BufReader::new(&stream)
+            .read_line(&mut String::new())
+            .unwrap();
+        send(&mut stream, &LobbyPacket::Rejected(RejectReason::Full));
+    });
+
+    let config = ClientConfig::builder(&lobby_address, "player")
+        .build()
+        .unwrap();
+    match Client::<u32, u32>::connect(config) {
+        Err(ClientError::LobbyRejected(RejectReason::Full)) => {}
+        Err(error) => panic!("Unexpected error: {}", error),
+        Ok(_) => panic!("Joined without a game"),
+    }
+}
+
+#[test]
+fn zero_timeouts_are_rejected() {
+    let config = ClientConfig::builder("127.0.0.1:8888", "player")
+        .server_timeout(Duration::from_secs(0))
+        .build();
+    match config {
+        Err(ClientError::InvalidConfig(_)) => {}
+        other => panic!("Built an invalid config: {:?}", other),
+    }
+}
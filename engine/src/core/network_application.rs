@@ -10,6 +10,29 @@ use std::time::{Duration, Instant};
 use crate::core::logger::initialize_logging;
 use crate::game::Game;
 
+/// Time the game spent in its updates, since the app started
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UpdateTimes {
+    /// Updates played
+    pub count: u64,
+    /// Time of every update added up
+    pub total: Duration,
+    /// Time of the last update
+    pub last: Duration,
+    /// Time of the slowest update
+    pub max: Duration,
+}
+
+impl UpdateTimes {
+    /// Adds the time of an update
+    pub fn record(&mut self, time: Duration) {
+        self.count += 1;
+        self.total += time;
+        self.last = time;
+        self.max = self.max.max(time);
+    }
+}
+
 /// Application that runs the app.
 /// App will handle all engine features.
 /// Missing:
@@ -26,7 +49,10 @@ use crate::game::Game;
 pub struct Application<T: Game<K, I>, K, I> {
     pub is_running: bool,
     pub shared_state: Arc<Mutex<K>>,
-    is_suspended: bool,
+    stopped: Arc<Mutex<bool>>,
+    tick: Arc<Mutex<u64>>,
+    suspended: Arc<Mutex<bool>>,
+    update_times: Arc<Mutex<UpdateTimes>>,
     last_time: Instant,
     frames_per_second: Duration,
     input: Arc<Mutex<I>>,
@@ -43,7 +69,10 @@ where
         Application {
             is_running: self.is_running,
             shared_state: Arc::new(Mutex::new(*self.shared_state.lock().unwrap())),
-            is_suspended: self.is_suspended,
+            stopped: Arc::new(Mutex::new(false)),
+            tick: Arc::new(Mutex::new(0)),
+            suspended: Arc::new(Mutex::new(false)),
+            update_times: Arc::new(Mutex::new(UpdateTimes::default())),
             last_time: self.last_time,
             frames_per_second: self.frames_per_second,
             input: Arc::new(Mutex::new(*self.input.lock().unwrap())),
@@ -62,17 +91,43 @@ impl<T: Game<K, I>, K, I> Application<T, K, I> {
         // Return app
         Application {
             is_running: false,
-            is_suspended: false,
+            suspended: Arc::new(Mutex::new(false)),
+            update_times: Arc::new(Mutex::new(UpdateTimes::default())),
             last_time: Instant::now(),
             frames_per_second: Duration::from_millis((1000 / game_update_rate) as u64), // 1000 / millis = frames per sec
             input: Arc::new(Mutex::new(input)),
             shared_state: Arc::new(Mutex::new(shared_state)),
+            stopped: Arc::new(Mutex::new(false)),
+            tick: Arc::new(Mutex::new(0)),
             game,
         }
     }
 
+    /// Flag that stops the game loop from another thread, when set to true
+    pub fn stop_handle(&self) -> Arc<Mutex<bool>> {
+        Arc::clone(&self.stopped)
+    }
+
+    /// Flag that pauses the game loop from another thread while set to true, the tick stays the same
+    pub fn suspend_handle(&self) -> Arc<Mutex<bool>> {
+        Arc::clone(&self.suspended)
+    }
+
+    /// Time spent in updates, to find slow games
+    pub fn update_times_handle(&self) -> Arc<Mutex<UpdateTimes>> {
+        Arc::clone(&self.update_times)
+    }
+
+    /// Number of updates played so far, held by the app through every update
+    /// Lock it before the shared state, and keep it while reading the state, to get the tick of that state.
+    pub fn tick_handle(&self) -> Arc<Mutex<u64>> {
+        Arc::clone(&self.tick)
+    }
+
     /// Run the app
     /// Starts the game loop
+    /// The input handler runs in a thread of its own, until every sender of the receiver is dropped,
+    /// so it may outlive the game loop.
     pub fn start<M>(
         &mut self,
         receiver: Receiver<M>,
@@ -82,6 +137,7 @@ impl<T: Game<K, I>, K, I> Application<T, K, I> {
         I: Send + Copy,
     {
         self.is_running = true;
+        self.last_time = Instant::now();
 
         // game configuration
         self.game.world_config();
@@ -94,17 +150,34 @@ impl<T: Game<K, I>, K, I> Application<T, K, I> {
         });
 
         // game loop
-        while self.is_running {
-            // self.last_time += 1.0;
-            let sleep_time = self.frames_per_second - self.last_time.elapsed();
+        while self.is_running && !*self.stopped.lock().unwrap() {
+            // Slow frames don't sleep, instead of underflowing
+            let sleep_time = self
+                .frames_per_second
+                .checked_sub(self.last_time.elapsed())
+                .unwrap_or_else(|| Duration::from_millis(0));
             thread::sleep(sleep_time);
 
             // initial time
             self.last_time = Instant::now();
 
+            // Suspended games wait, without updating
+            if *self.suspended.lock().unwrap() {
+                continue;
+            }
+
             // update
-            self.game
-                .update(*self.input.lock().unwrap(), Arc::clone(&self.shared_state));
+            // The tick is held through the update, so the state only changes together with its tick
+            let input = *self.input.lock().unwrap();
+            {
+                let mut tick = self.tick.lock().unwrap();
+                self.game.update(input, Arc::clone(&self.shared_state));
+                *tick += 1;
+            }
+            self.update_times
+                .lock()
+                .unwrap()
+                .record(self.last_time.elapsed());
 
             // Reset input
             self.game.reset_input(Arc::clone(&self.input));
@@ -1,143 +1,71 @@
+extern crate blizzard_client;
 extern crate example;
 
+use blizzard_client::{Client, ClientConfig, ClientEvent, CloseReason};
 use example::Message;
 use example::SharedState;
-use std::io::{self, BufRead, BufReader, Write};
-use std::net::{Shutdown, TcpStream};
-use std::str;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::io;
 
-struct Client {}
+fn main() {
+    println!("Enter your username: ");
+
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .expect("Failed to read from stdin");
+
+    println!("Finding an available lobby...");
+
+    let config = ClientConfig::builder("127.0.0.1:8888", input.trim())
+        .build()
+        .expect("Invalid client config");
+
+    // The client answers heartbeats and resumes the session, we only print what the game sends
+    let client: Client<Message, SharedState> =
+        match Client::connect_with(config, |event| match event {
+            ClientEvent::State { state, .. } => println!("{:?}", state),
+            ClientEvent::Phase(phase) => println!("Match phase: {:?}", phase),
+            ClientEvent::Roster(roster) => {
+                for entry in roster {
+                    println!("{} ready: {}", entry.username, entry.setup.ready)
+                }
+            }
+            ClientEvent::Notice(notice) => println!("Server: {}", notice),
+            ClientEvent::Disconnected => println!("Connection lost, reconnecting..."),
+            ClientEvent::Reconnected => println!("Reconnected"),
+            ClientEvent::Closed(CloseReason::Shutdown(reason)) => {
+                println!("Server shut down: {}", reason)
+            }
+            ClientEvent::Closed(reason) => println!("Disconnected: {:?}", reason),
+        }) {
+            Ok(client) => client,
+            Err(error) => {
+                println!("Could not join a game: {}", error);
+                return;
+            }
+        };
 
-impl Client {
-    fn start() {
-        let mut stream = TcpStream::connect("0.0.0.0:8888").expect("Could not connect to server");
-        let port: i32;
+    println!("Joined as player {}", client.player_id());
 
+    // User Input
+    loop {
         let mut input = String::new();
-        let mut buffer: Vec<u8> = Vec::new();
-
-        println!("Enter your username: ");
-
         io::stdin()
             .read_line(&mut input)
             .expect("Failed to read from stdin");
 
-        println!("Finding an available lobby...");
-
-        stream
-            .write(input.as_bytes())
-            .expect("Failed to write to server");
-
-        let mut reader = BufReader::new(&stream);
-
-        reader
-            .read_until(b'\n', &mut buffer)
-            .expect("Could not read into buffer");
-
-        println!("Lobby found");
-
-        println!(
-            "{}",
-            str::from_utf8(&buffer).expect("Could not write buffer as string")
-        );
-
-        port = str::from_utf8(&buffer)
-            .expect("Could not write buffer as string")
-            .replace("\n", "")
-            .parse()
-            .expect("Could not parse port");
-
-        if port == 0 {
-            println!("No game available, please try again later");
+        let message = match input.trim() {
+            "w" => Message::W,
+            "a" => Message::A,
+            "s" => Message::S,
+            "d" => Message::D,
+            // Server removes the player when the connection closes
+            "close" => return,
+            _ => Message::None,
+        };
+
+        if client.send(message).is_err() {
             return;
         }
-
-        stream
-            .shutdown(Shutdown::Both)
-            .expect("Could not disconnect from original server");
-
-        let tcp = format!("0.0.0.0:{}", port);
-
-        Client::run_game(tcp);
-    }
-
-    fn run_game(tcp: String) {
-        let mut stream = TcpStream::connect(tcp).expect("Could not connect to server");
-        let stream_clone = stream.try_clone().unwrap();
-
-        let data = Message::AddPlayer;
-        let json = serde_json::to_string(&data).unwrap() + "\n";
-
-        let should_close = Arc::new(Mutex::new(false));
-        let should_close_copy = Arc::clone(&should_close);
-
-        stream
-            .write(json.as_bytes())
-            .expect("Failed to write to server");
-        println!("data written");
-
-        // User Input
-        thread::spawn(move || {
-            let shoud_close = should_close_copy;
-
-            loop {
-                let mut input = String::new();
-
-                io::stdin()
-                    .read_line(&mut input)
-                    .expect("Failed to read from stdin");
-
-                let input = input.trim();
-
-                let mut data = Message::None;
-
-                if input == "w" {
-                    data = Message::W;
-                } else if input == "a" {
-                    data = Message::A;
-                } else if input == "s" {
-                    data = Message::S;
-                } else if input == "d" {
-                    data = Message::D;
-                } else if input == "close" {
-                    data = Message::RemovePlayer;
-                    *shoud_close.lock().unwrap() = true;
-                }
-
-                let json = serde_json::to_string(&data).unwrap() + "\n";
-
-                stream
-                    .write(json.as_bytes())
-                    .expect("Failed to write to server");
-            }
-        });
-
-        // Stream Reader
-        thread::spawn(move || {
-            let stream = stream_clone;
-            loop {
-                let mut buffer: Vec<u8> = Vec::new();
-                let mut reader = BufReader::new(&stream);
-                reader
-                    .read_until(b'\n', &mut buffer)
-                    .expect("Could not read into buffer");
-                let json = str::from_utf8(&buffer).unwrap();
-                let state: SharedState = serde_json::from_str(&json).unwrap();
-                println!("{:?}", state);
-            }
-        });
-
-        // Keep thread alive, so TCP connection on other threads doesn't reset
-        loop {
-            if *should_close.lock().unwrap() {
-                return;
-            }
-        }
     }
 }
-
-fn main() {
-    Client::start();
-}
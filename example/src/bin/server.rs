@@ -5,9 +5,11 @@ extern crate example;
 use blizzard_engine::ecs::{ComponentRegistry, EntityManager, World};
 use blizzard_engine::game::Game;
 use blizzard_engine_derive::ComponentRegistry;
-use blizzard_server::server::Server;
+use blizzard_server::game::PlayerEvent;
+use blizzard_server::server::{Server, ServerConfig};
 
 use std::collections::HashMap;
+use std::env;
 use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
 
@@ -196,19 +198,54 @@ impl Input {
 }
 
 // Handle client messages
-fn handle_client_message(receiver: Receiver<(Message, usize)>, input: Arc<Mutex<Input>>) -> Input {
-    for (message, id) in receiver {
-        println!("Player {} called {:?}", id, message);
+// Players are added and removed on the server's join and leave events, not on client requests
+fn handle_client_message(
+    receiver: Receiver<(PlayerEvent<Message>, usize)>,
+    input: Arc<Mutex<Input>>,
+) -> Input {
+    for (event, id) in receiver {
+        println!("Player {} called {:?}", id, event);
+        let message = match event {
+            PlayerEvent::Joined(info) => {
+                println!("{} joined as player {}", info.username, id);
+                Message::AddPlayer
+            }
+            PlayerEvent::Left => Message::RemovePlayer,
+            // Entity stays while the player may come back
+            PlayerEvent::Disconnected
+            | PlayerEvent::Reconnected
+            | PlayerEvent::Setup(_)
+            | PlayerEvent::Latency(_) => continue,
+            PlayerEvent::Message {
+                message: Message::AddPlayer,
+                ..
+            }
+            | PlayerEvent::Message {
+                message: Message::RemovePlayer,
+                ..
+            } => continue,
+            PlayerEvent::Message { message, .. } => message,
+        };
         *input.lock().unwrap() = Input::from(message, id);
     }
     Input::default()
 }
 
 // Main function
+// Pass a TOML file to override the configuration: cargo run --bin server -- server.toml
 fn main() {
-    let port = 8888;
-    let max_games = 4;
-    let max_players = 2;
+    let config = match env::args().nth(1) {
+        Some(path) => ServerConfig::from_toml_file(path).expect("Could not load config"),
+        None => ServerConfig::builder()
+            .lobby_port(8888)
+            .max_games(4)
+            .max_players(2)
+            .send_data_rate(1) // Send data to clients once per second
+            .game_update_rate(2) // 2 times per second
+            .build()
+            .expect("Invalid config"),
+    };
+
     let world = MyWorld::new();
     let shared_state = SharedState::new();
     let game = new_game(world);
@@ -217,20 +254,10 @@ fn main() {
     let input_type = Input::default();
     let hanlde_input = &handle_client_message;
 
-    // Engine speeds
-    let send_data_from_server_rate = 1;
-    let server_game_update_rate = 2; // 2 times per second
-
-    // Start server + games
-    Server::new(
-        port,
-        max_games,
-        max_players,
-        game,
-        shared_state,
-        input_type,
-        hanlde_input,
-        send_data_from_server_rate,
-        server_game_update_rate,
-    );
+    // Start server + games, until Ctrl-C shuts it down
+    let server = Server::start(config, game, shared_state, input_type, hanlde_input);
+    server
+        .shutdown_on_signals()
+        .expect("Could not handle signals");
+    server.wait();
 }
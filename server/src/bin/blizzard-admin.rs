@@ -0,0 +1,136 @@
+//! # Blizzard admin
+//! Sends a command to the admin interface of a running server and prints the reply.
+//!
+//! Usage: `blizzard-admin <address> <command> [arguments]`,
+//! where the address is `127.0.0.1:9999` or `unix:/path/to/admin.sock`.
+
+use std::env;
+use std::process;
+
+use blizzard_server::server::admin::{AdminClient, AdminCommand, AdminReply};
+use blizzard_server::server::config::AdminAddress;
+
+const USAGE: &str = "Usage: blizzard-admin <address> <command> [arguments]
+
+Commands:
+  games                        List the running games
+  players <port>               List the players of a game
+  kick <port> <player_id>      Remove a player from a game
+  ban <entry>                  Ban user:<username>, account:<account id> or addr:<ip>
+                               and kick its players
+  unban <entry>                Lift a ban, written the same way
+  broadcast [--port <port>] <message>
+                               Show a notice to the players of a game, or of every game
+  pause <port>                 Pause the match of a game
+  unpause <port>               Resume a paused match
+  shutdown <port>              Close every connection of a game and retire it";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (address, command) = match parse(&args) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let reply = AdminClient::connect(&address)
+        .map_err(|error| error.to_string())
+        .and_then(|mut admin| admin.send(&command).map_err(|error| error.to_string()));
+
+    match reply {
+        Ok(AdminReply::Games(games)) => {
+            for game in games {
+                println!(
+                    "{}\t{:?}\t{} players\t{} free{}{}",
+                    game.port,
+                    game.phase,
+                    game.players,
+                    game.free_slots,
+                    game.room_code
+                        .map(|code| format!("\troom {}", code))
+                        .unwrap_or_default(),
+                    if game.paused { "\tpaused" } else { "" },
+                );
+            }
+        }
+        Ok(AdminReply::Players(players)) => {
+            for player in players {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\ttick {}",
+                    player.player_id,
+                    player.username,
+                    player.account_id.unwrap_or_else(|| "-".to_string()),
+                    if player.connected {
+                        "connected"
+                    } else {
+                        "away"
+                    },
+                    player
+                        .rtt_ms
+                        .map(|rtt| format!("{} ms", rtt))
+                        .unwrap_or_else(|| "-".to_string()),
+                    player.acked_tick,
+                );
+            }
+        }
+        Ok(AdminReply::Done) => println!("Done"),
+        Ok(AdminReply::Error(error)) | Err(error) => {
+            eprintln!("Error: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+/// Reads the address and the command of the arguments
+fn parse(args: &[String]) -> Result<(AdminAddress, AdminCommand), String> {
+    let address = args.first().ok_or("Missing address")?.parse()?;
+    let name = args.get(1).ok_or("Missing command")?;
+    let rest = &args[2..];
+
+    let port = |index: usize| -> Result<i32, String> {
+        let port = rest.get(index).ok_or("Missing port")?;
+        port.parse().map_err(|_| format!("{} is not a port", port))
+    };
+    let text = |index: usize| -> Result<String, String> {
+        rest.get(index)
+            .cloned()
+            .ok_or_else(|| format!("Missing argument of {}", name))
+    };
+
+    let command = match name.as_str() {
+        "games" => AdminCommand::Games,
+        "players" => AdminCommand::Players { port: port(0)? },
+        "kick" => {
+            let player_id = text(1)?;
+            AdminCommand::Kick {
+                port: port(0)?,
+                player_id: player_id
+                    .parse()
+                    .map_err(|_| format!("{} is not a player id", player_id))?,
+            }
+        }
+        "ban" => AdminCommand::Ban {
+            entry: text(0)?.parse()?,
+        },
+        "unban" => AdminCommand::Unban {
+            entry: text(0)?.parse()?,
+        },
+        "broadcast" if rest.first().map(|arg| arg.as_str()) == Some("--port") => {
+            AdminCommand::Broadcast {
+                port: Some(port(1)?),
+                message: rest[2..].join(" "),
+            }
+        }
+        "broadcast" => AdminCommand::Broadcast {
+            port: None,
+            message: rest.join(" "),
+        },
+        "pause" => AdminCommand::Pause { port: port(0)? },
+        "unpause" => AdminCommand::Unpause { port: port(0)? },
+        "shutdown" => AdminCommand::Shutdown { port: port(0)? },
+        _ => return Err(format!("Unknown command {}", name)),
+    };
+    Ok((address, command))
+}
@@ -0,0 +1,34 @@
+//! # Event
+//!
+//! Events the server delivers to the app, tagged with the player id.
+//! Join and leave events are generated by the server, so games don't rely on clients announcing themselves.
+
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+use crate::game::{Latency, PlayerInfo, PlayerSetup};
+
+/// Event sent from the server to the app
+/// # Type definitions
+/// * M: Message type sent by clients
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent<M> {
+    /// Player connected to the game, spawn its entities
+    Joined(PlayerInfo),
+    /// Player lost its connection, its slot is held for the reconnection grace period
+    Disconnected,
+    /// Player resumed its session within the grace period
+    Reconnected,
+    /// Player left for good, despawn its entities
+    Left,
+    /// Message sent by the player, for the server tick it targets
+    Message { tick: u64, message: M },
+    /// Team and options the player picked, sent for every player right before the match starts
+    Setup(PlayerSetup),
+    /// Round-trip time of the player changed, after it answered a ping
+    Latency(Latency),
+}
+
+/// Function that turns player events into game input.
+/// Receives `(event, player id)` pairs until the game closes.
+pub type InputHandler<M, I> = dyn Fn(Receiver<(PlayerEvent<M>, usize)>, Arc<Mutex<I>>) -> I + Sync;
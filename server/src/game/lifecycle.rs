@@ -0,0 +1,78 @@
+//! # Lifecycle
+//!
+//! Phases of a match and the report of a finished match.
+//! A game waits for players, counts down, runs until `Game::end_game`, then is reset from the prototype game.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::game::PlayerInfo;
+
+/// Phase of the match of a game, sent to clients when it changes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MatchPhase {
+    /// Waiting for the min amount of players
+    Waiting,
+    /// Enough players, the match starts after the countdown
+    Countdown { duration_ms: u64 },
+    /// Game is running
+    Running,
+    /// Game ended, clients get the final state
+    Finished,
+    /// Players are removed and a new game is created
+    Resetting,
+}
+
+impl MatchPhase {
+    /// Determine if players can join the game in this phase
+    pub fn is_joinable(&self) -> bool {
+        match self {
+            MatchPhase::Waiting | MatchPhase::Countdown { .. } | MatchPhase::Running => true,
+            MatchPhase::Finished | MatchPhase::Resetting => false,
+        }
+    }
+}
+
+/// Results of a finished match, or of a match stopped before its end
+#[derive(Debug, Clone)]
+pub struct MatchReport {
+    /// Port of the game
+    pub port: i32,
+    /// Players in the game when it ended, with their ids
+    pub players: Vec<(usize, PlayerInfo)>,
+    /// Final shared state
+    pub result: serde_json::Value,
+    /// Time the match was running
+    pub duration: Duration,
+    /// The match was stopped before the game ended, by a shutdown of the game or the server
+    pub interrupted: bool,
+}
+
+/// Signature of match end callbacks
+type MatchEndCallback = dyn Fn(&MatchReport) + Send + Sync;
+
+/// Callback for finished matches, set with `ServerConfigBuilder::on_match_end`.
+#[derive(Clone)]
+pub struct MatchEndHandler(Arc<MatchEndCallback>);
+
+impl MatchEndHandler {
+    /// Wraps a callback
+    pub fn new<F>(callback: F) -> MatchEndHandler
+    where
+        F: Fn(&MatchReport) + Send + Sync + 'static,
+    {
+        MatchEndHandler(Arc::new(callback))
+    }
+
+    /// Calls the callback
+    pub fn report(&self, report: &MatchReport) {
+        (self.0)(report);
+    }
+}
+
+impl fmt::Debug for MatchEndHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MatchEndHandler")
+    }
+}
@@ -2,5 +2,10 @@
 //!
 //! This module is for standard game properties.
 
+mod event;
+mod lifecycle;
 mod player;
-pub use player::Player;
+pub use event::{InputHandler, PlayerEvent};
+pub use lifecycle::{MatchEndHandler, MatchPhase, MatchReport};
+pub(crate) use player::random_token;
+pub use player::{Identity, Latency, Player, PlayerInfo, PlayerSetup};
@@ -1,17 +1,130 @@
 //! # Player
 //!
 //! The player is what a controller / connector handles as a connection.
-//! Each player has a unique id.
+//! Each player has a unique id and a session token to reconnect with.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use rand::Rng;
+
+/// What a player told the lobby about itself
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PlayerInfo {
+    pub username: String,
+    pub metadata: HashMap<String, String>,
+    /// Account checked by the authenticator of the config, `None` without one
+    #[serde(default)]
+    pub identity: Option<Identity>,
+}
+
+impl PlayerInfo {
+    /// Id of the account of the player, `None` without one
+    pub fn account_id(&self) -> Option<&str> {
+        self.identity.as_ref().and_then(|i| i.account_id.as_deref())
+    }
+}
+
+/// Account of a player, the same on every connection
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Identity {
+    /// Id of the account, given by the authenticator
+    /// `None` when the credentials don't tell accounts apart, like a shared secret.
+    pub account_id: Option<String>,
+    /// What the player may do, like `admin` or `moderator`
+    pub roles: Vec<String>,
+}
+
+impl Identity {
+    /// Creates the identity of an account with its roles
+    pub fn new(account_id: &str, roles: &[&str]) -> Identity {
+        Identity {
+            account_id: Some(account_id.to_string()),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        }
+    }
+
+    /// True if the player has the role
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|own| own == role)
+    }
+}
+
+/// What a player picked before the match, with the ready check of the config
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PlayerSetup {
+    /// Player is ready for the match to start
+    pub ready: bool,
+    pub team: Option<String>,
+    /// Custom choices of the player, like a character or a map vote
+    pub options: HashMap<String, String>,
+}
+
+/// Round-trip time of a player, measured with the pings of the server
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Latency {
+    /// Smoothed round-trip time
+    pub rtt: Duration,
+    /// Smoothed difference between round-trip times
+    pub jitter: Duration,
+}
+
+impl Latency {
+    /// Adds a round-trip time to the previous measure, smoothed like TCP does
+    pub(crate) fn measure(previous: Option<Latency>, sample: Duration) -> Latency {
+        match previous {
+            None => Latency {
+                rtt: sample,
+                jitter: Duration::from_secs(0),
+            },
+            Some(previous) => {
+                let difference = sample
+                    .checked_sub(previous.rtt)
+                    .unwrap_or_else(|| previous.rtt - sample);
+                Latency {
+                    rtt: previous.rtt * 7 / 8 + sample / 8,
+                    jitter: previous.jitter * 3 / 4 + difference / 4,
+                }
+            }
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 /// Player struct for client connection identification
 pub struct Player {
     pub id: usize,
+    pub info: PlayerInfo,
+    pub setup: PlayerSetup,
+    /// Secret the client sends to resume its session
+    pub token: String,
+    /// False while the player is away, within the reconnection grace period
+    pub connected: bool,
+    /// Counts the connections of the session, stale connections can't remove the player
+    pub connection: u32,
+    /// Last server tick the client acknowledged
+    pub acked_tick: u64,
+    /// Round-trip time, once the player answered a ping
+    pub latency: Option<Latency>,
 }
 
 impl Player {
-    /// Create a new player
-    pub fn new(id: usize) -> Player {
-        Player { id }
+    /// Create a new player with a random session token
+    pub fn new(id: usize, info: PlayerInfo) -> Player {
+        Player {
+            id,
+            info,
+            setup: PlayerSetup::default(),
+            token: random_token(),
+            connected: true,
+            connection: 0,
+            acked_tick: 0,
+            latency: None,
+        }
     }
 }
+
+/// Random hexadecimal secret, for session tokens and join tickets
+pub(crate) fn random_token() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
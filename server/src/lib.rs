@@ -17,9 +17,12 @@ extern crate serde_derive;
 extern crate blizzard_engine;
 extern crate blizzard_engine_derive;
 extern crate blizzard_id;
+extern crate rand;
 extern crate serde;
 extern crate serde_json;
+extern crate toml;
 
 // Entry point is in server
-mod game;
+pub mod game;
+pub mod protocol;
 pub mod server;
@@ -0,0 +1,202 @@
+//! # Protocol
+//!
+//! Packets exchanged between clients, the lobby and game ports.
+//! Every packet is a line of JSON, terminated by a newline.
+//!
+//! 1. The client sends `Hello` to the lobby.
+//! 2. The lobby answers `Welcome` with a join ticket and the game address, or `Rejected` with a reason.
+//! 3. The client connects to the game and sends `Join` with the ticket, or `Resume` with the token of a previous session.
+//! 4. The game answers `Session` with the player id and the session token, or `Rejected`.
+//! 5. The client sends `Input` packets, the game sends `State` packets, and `Phase` when the match phase changes.
+//!
+//! Every state carries the server tick it was computed at, and is sent at most once.
+//! Inputs name the tick they target, and clients `Ack` the last tick they received.
+//! Clients answer every `Ping` with a `Pong`, which keeps them connected and measures their round-trip time.
+//!
+//! With the ready check of the config, players see the `Roster` of the game before the match,
+//! pick their team and options, and send `Ready` once they want to play.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::str;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::game::{MatchPhase, PlayerSetup};
+use crate::server::error::ServerError;
+
+/// Version of the protocol, the lobby rejects clients with a different version
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// First packet a client sends to the lobby
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hello {
+    pub protocol_version: u32,
+    pub username: String,
+    /// Custom data of the player, passed on to the game
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// Private room to create or join, `None` lets the matchmaker pick a game
+    #[serde(default)]
+    pub room: Option<RoomRequest>,
+    /// Secret or signed token, for the authenticator of the server
+    #[serde(default)]
+    pub credentials: Option<String>,
+}
+
+/// Private room part of a hello
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RoomRequest {
+    /// Creates a room, the creator is its host
+    Create { password: Option<String> },
+    /// Joins the room of a code
+    Join {
+        code: String,
+        password: Option<String>,
+    },
+}
+
+impl Hello {
+    /// Creates a hello for the current protocol version
+    pub fn new(username: &str) -> Hello {
+        Hello {
+            protocol_version: PROTOCOL_VERSION,
+            username: username.to_string(),
+            metadata: HashMap::new(),
+            room: None,
+            credentials: None,
+        }
+    }
+}
+
+/// Lets a client join the game it was sent to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JoinTicket {
+    /// Secret the client hands to the game with `ClientPacket::Join`
+    pub id: String,
+    /// Host of the game, `None` means the host of the lobby
+    pub host: Option<String>,
+    /// Port of the game
+    pub port: i32,
+    /// Join code of the private room, to share with friends
+    #[serde(default)]
+    pub room_code: Option<String>,
+}
+
+/// Why the lobby did not send a client to a game
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    /// Every game is full
+    Full,
+    /// Client speaks another protocol version
+    VersionMismatch { server_version: u32 },
+    /// Player or address is banned
+    Banned,
+    /// Hello could not be read
+    Malformed,
+    /// No private room with the code
+    RoomNotFound,
+    /// Password of the private room does not match
+    WrongPassword,
+    /// Authenticator of the server refused the credentials
+    Unauthorized,
+    /// Server is shutting down and starts no more games
+    ShuttingDown,
+}
+
+/// Packet sent from the lobby to a client, the lobby closes the connection after it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum LobbyPacket {
+    /// Game found, connect to it with the ticket
+    Welcome(JoinTicket),
+    /// No game for the client
+    Rejected(RejectReason),
+}
+
+/// Packet sent from a client to a game
+/// # Type definitions
+/// * M: Message type of the game
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ClientPacket<M> {
+    /// First packet of a new player, with the ticket from the lobby
+    Join { ticket: String },
+    /// First packet of a returning player, resumes the session of the token
+    Resume { token: String },
+    /// Game message, for the server tick it targets
+    Input {
+        #[serde(default)]
+        tick: u64,
+        message: M,
+    },
+    /// Last server tick the client received
+    Ack(u64),
+    /// Answer to the ping with the same number
+    Pong(u64),
+    /// Host of a private room starts the match
+    StartMatch,
+    /// Player is ready, or no longer ready, for the match to start
+    Ready(bool),
+    /// Player joins a team, before the match starts
+    PickTeam(String),
+    /// Player picks an option, before the match starts
+    SetOption { key: String, value: String },
+}
+
+/// Packet sent from a game to a client
+/// # Type definitions
+/// * K: Shared state type of the game
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ServerPacket<K> {
+    /// Player joined or resumed, keep the token to reconnect
+    Session { player_id: usize, token: String },
+    /// Player could not join, the connection is closed after this packet
+    Rejected { reason: String },
+    /// Shared state of the game, after the server tick
+    State { tick: u64, state: K },
+    /// Match phase changed, the state after `Finished` is the final one
+    Phase(MatchPhase),
+    /// Players of the game, sent when it changes with the ready check of the config
+    Roster(Vec<RosterEntry>),
+    /// Heartbeat, answer it with a `Pong` of the same number
+    Ping(u64),
+    /// Message of the server operators, to show to the player
+    Notice(String),
+    /// Server is shutting down, the connection is closed after this packet
+    Shutdown { reason: String },
+}
+
+/// A player of the roster
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RosterEntry {
+    pub player_id: usize,
+    pub username: String,
+    /// Player is the host of the private room
+    pub host: bool,
+    /// False while the player is away, within the reconnection grace period
+    pub connected: bool,
+    pub setup: PlayerSetup,
+}
+
+/// Parses a newline terminated JSON packet
+pub(crate) fn parse_packet<P: DeserializeOwned>(buffer: &[u8]) -> Result<P, ServerError> {
+    let json = str::from_utf8(buffer)?;
+    serde_json::from_str(json.trim()).map_err(ServerError::MalformedMessage)
+}
+
+/// Writes a packet as a line of JSON
+pub(crate) fn write_packet<W: Write, P: Serialize>(
+    stream: &mut W,
+    packet: &P,
+) -> Result<(), ServerError> {
+    stream.write_all(&encode_packet(packet)?)?;
+    Ok(())
+}
+
+/// Encodes a packet as a line of JSON, to be sent to many clients
+pub(crate) fn encode_packet<P: Serialize>(packet: &P) -> Result<Arc<Vec<u8>>, ServerError> {
+    let mut line = serde_json::to_vec(packet).map_err(ServerError::Serialize)?;
+    line.push(b'\n');
+    Ok(Arc::new(line))
+}
@@ -0,0 +1,412 @@
+//! # Admin
+//! Operators inspect and control a running server through the admin interface.
+//!
+//! The interface listens on the `admin` address of the config, a local TCP address or a Unix socket.
+//! Every line is an `AdminCommand` in JSON, answered with a line holding an `AdminReply`.
+//! Commands are not authenticated, so the address must only be reachable by operators.
+//! The `blizzard-admin` binary sends commands from the command line.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use crate::game::MatchPhase;
+use crate::protocol::{parse_packet, write_packet};
+use crate::server::bans::{BanEntry, BanList};
+use crate::server::config::{AdminAddress, ServerConfig};
+use crate::server::error::{lock, ErrorSource, ServerError};
+use crate::server::handle::Service;
+use crate::server::pool::Pool;
+
+/// Command of an operator.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    /// Lists the running games
+    Games,
+    /// Lists the players of a game
+    Players { port: i32 },
+    /// Removes a player from a game, it can't resume its session
+    Kick { port: i32, player_id: usize },
+    /// Bans a username, account or address, and kicks the players it matches
+    Ban { entry: BanEntry },
+    /// Lifts a ban
+    Unban { entry: BanEntry },
+    /// Shows a notice to the players of a game, or of every game without port
+    Broadcast { port: Option<i32>, message: String },
+    /// Stops updating the current match of a game
+    Pause { port: i32 },
+    /// Updates a paused match again
+    Unpause { port: i32 },
+    /// Closes every connection of a game and retires it
+    Shutdown { port: i32 },
+}
+
+/// Answer of the server to a command.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AdminReply {
+    /// The running games, answer to `Games`
+    Games(Vec<GameStatus>),
+    /// The players of a game, answer to `Players`
+    Players(Vec<PlayerStatus>),
+    /// The command was carried out
+    Done,
+    /// The command failed, with the reason
+    Error(String),
+}
+
+/// A running game.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameStatus {
+    pub port: i32,
+    pub phase: MatchPhase,
+    /// Players, with the slots reserved for them
+    pub players: usize,
+    pub free_slots: usize,
+    /// Join code of private rooms
+    pub room_code: Option<String>,
+    /// Given by the matchmaker when the game started
+    pub tags: HashMap<String, String>,
+    pub paused: bool,
+}
+
+/// A player of a game.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerStatus {
+    pub player_id: usize,
+    pub username: String,
+    /// Account of the authenticator, `None` without one
+    pub account_id: Option<String>,
+    /// False while the player is away, within the reconnection grace period
+    pub connected: bool,
+    /// Smoothed round-trip time, `None` until the first pong
+    pub rtt_ms: Option<u64>,
+    /// Last tick the player acknowledged
+    pub acked_tick: u64,
+}
+
+/// Connection to the admin interface of a server.
+/// # Example
+/// ```no_run
+/// use blizzard_server::server::admin::{AdminClient, AdminCommand, AdminReply};
+///
+/// let address = "127.0.0.1:9999".parse().unwrap();
+/// let mut admin = AdminClient::connect(&address).unwrap();
+/// if let AdminReply::Games(games) = admin.send(&AdminCommand::Games).unwrap() {
+///     for game in games {
+///         println!("{}: {} players", game.port, game.players);
+///     }
+/// }
+/// ```
+pub struct AdminClient {
+    reader: BufReader<AdminStream>,
+    writer: AdminStream,
+}
+
+impl AdminClient {
+    /// Connects to the admin interface at the address
+    pub fn connect(address: &AdminAddress) -> io::Result<AdminClient> {
+        let writer = AdminStream::connect(address)?;
+        Ok(AdminClient {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+        })
+    }
+
+    /// Sends a command and waits for its reply
+    pub fn send(&mut self, command: &AdminCommand) -> Result<AdminReply, ServerError> {
+        write_packet(&mut self.writer, command)?;
+        let mut line = vec![];
+        if self.reader.read_until(b'\n', &mut line)? == 0 {
+            let closed = io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Admin interface closed the connection",
+            );
+            return Err(ServerError::Io(closed));
+        }
+        parse_packet(&line)
+    }
+}
+
+/// Opens the admin interface and answers commands in the background, until the stop flag is set
+/// Fails if the address can't be bound.
+pub(crate) fn serve(
+    address: &AdminAddress,
+    pool: Arc<Pool>,
+    bans: BanList,
+    config: Arc<ServerConfig>,
+    stopping: Arc<Mutex<bool>>,
+) -> io::Result<Service> {
+    let listener = AdminListener::bind(address)?;
+    println!("Admin interface listening on {}", address);
+
+    let builder = thread::Builder::new().name("Admin".to_string());
+    let thread = builder
+        .spawn(move || {
+            // Connections of operators, closed and waited for once the interface stops
+            let open = Arc::new(Mutex::new(HashMap::new()));
+            let mut threads = vec![];
+            let mut next_id = 0;
+
+            loop {
+                match listener.accept() {
+                    Err(e) => config.report_error(&ServerError::Io(e), ErrorSource::Admin),
+                    // The server wakes the interface up with a connection, to stop accepting
+                    Ok(_) if *lock(&stopping) => break,
+                    Ok(stream) => {
+                        let closer = match stream.try_clone() {
+                            Ok(closer) => closer,
+                            Err(e) => {
+                                config.report_error(&ServerError::Io(e), ErrorSource::Admin);
+                                continue;
+                            }
+                        };
+                        let id = next_id;
+                        next_id += 1;
+                        lock(&open).insert(id, closer);
+
+                        let pool = Arc::clone(&pool);
+                        let bans = bans.clone();
+                        let config = Arc::clone(&config);
+                        let connection_open = Arc::clone(&open);
+
+                        // Operators may keep their connection open, each one has its own thread
+                        let builder = thread::Builder::new().name("Admin-connection".to_string());
+                        let thread = builder
+                            .spawn(move || {
+                                handle_connection(stream, &pool, &bans).unwrap_or_else(|error| {
+                                    config.report_error(&error, ErrorSource::Admin)
+                                });
+                                lock(&connection_open).remove(&id);
+                            })
+                            .expect("Could not create thread");
+                        threads.push((id, thread));
+
+                        // Threads of closed connections are done
+                        let (done, running) = {
+                            let open = lock(&open);
+                            threads
+                                .drain(..)
+                                .partition(|(id, _)| !open.contains_key(id))
+                        };
+                        threads = running;
+                        for (_, thread) in done {
+                            thread.join().unwrap_or(());
+                        }
+                    }
+                }
+            }
+
+            for stream in lock(&open).values() {
+                stream.shutdown();
+            }
+            for (_, thread) in threads {
+                thread.join().unwrap_or(());
+            }
+        })
+        .expect("Could not create thread");
+
+    let address = address.clone();
+    Ok(Service::new(thread, move || {
+        AdminStream::connect(&address).map(|_| ()).unwrap_or(());
+    }))
+}
+
+/// Answers the commands of an operator until it disconnects
+fn handle_connection(stream: AdminStream, pool: &Pool, bans: &BanList) -> Result<(), ServerError> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = parse_packet::<AdminCommand>(line.as_bytes())
+            .map_err(|error| error.to_string())
+            .and_then(|command| execute(command, pool, bans))
+            .unwrap_or_else(AdminReply::Error);
+        write_packet(&mut writer, &reply)?;
+    }
+    Ok(())
+}
+
+/// Carries out a command, errors are the reason it failed
+fn execute(command: AdminCommand, pool: &Pool, bans: &BanList) -> Result<AdminReply, String> {
+    let game = |port: i32| {
+        pool.controller(port)
+            .ok_or_else(|| format!("No game in port {}", port))
+    };
+
+    match command {
+        AdminCommand::Games => Ok(AdminReply::Games(pool.games())),
+        AdminCommand::Players { port } => {
+            let controller = game(port)?;
+            let players = lock(&controller).player_statuses();
+            Ok(AdminReply::Players(players))
+        }
+        AdminCommand::Kick { port, player_id } => {
+            let controller = game(port)?;
+            let kicked = lock(&controller).kick(player_id);
+            if !kicked {
+                return Err(format!("No player {} in port {}", player_id, port));
+            }
+            Ok(AdminReply::Done)
+        }
+        AdminCommand::Ban { entry } => {
+            bans.ban(&entry);
+            for controller in pool.controllers() {
+                lock(&controller).kick_banned(&entry);
+            }
+            Ok(AdminReply::Done)
+        }
+        AdminCommand::Unban { entry } => {
+            if !bans.unban(&entry) {
+                return Err(format!("{} is not banned", entry));
+            }
+            Ok(AdminReply::Done)
+        }
+        AdminCommand::Broadcast { port, message } => {
+            let controllers = match port {
+                Some(port) => vec![game(port)?],
+                None => pool.controllers(),
+            };
+            for controller in controllers {
+                lock(&controller)
+                    .notify(&message)
+                    .map_err(|error| error.to_string())?;
+            }
+            Ok(AdminReply::Done)
+        }
+        AdminCommand::Pause { port } => {
+            let controller = game(port)?;
+            lock(&controller).set_paused(true);
+            Ok(AdminReply::Done)
+        }
+        AdminCommand::Unpause { port } => {
+            let controller = game(port)?;
+            lock(&controller).set_paused(false);
+            Ok(AdminReply::Done)
+        }
+        AdminCommand::Shutdown { port } => {
+            if !pool.shutdown_game(port) {
+                return Err(format!("No game in port {}", port));
+            }
+            Ok(AdminReply::Done)
+        }
+    }
+}
+
+/// Listener of the admin address
+enum AdminListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl AdminListener {
+    fn bind(address: &AdminAddress) -> io::Result<AdminListener> {
+        match address {
+            AdminAddress::Tcp(address) => TcpListener::bind(address).map(AdminListener::Tcp),
+            #[cfg(unix)]
+            AdminAddress::Unix(path) => {
+                // A socket left by a previous run fails the bind, other files are kept
+                if let Ok(metadata) = fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        fs::remove_file(path)?;
+                    }
+                }
+                UnixListener::bind(path).map(AdminListener::Unix)
+            }
+            #[cfg(not(unix))]
+            AdminAddress::Unix(_) => Err(no_unix_sockets()),
+        }
+    }
+
+    fn accept(&self) -> io::Result<AdminStream> {
+        match self {
+            AdminListener::Tcp(listener) => listener
+                .accept()
+                .map(|(stream, _)| AdminStream::Tcp(stream)),
+            #[cfg(unix)]
+            AdminListener::Unix(listener) => listener
+                .accept()
+                .map(|(stream, _)| AdminStream::Unix(stream)),
+        }
+    }
+}
+
+/// Connection of an operator
+enum AdminStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl AdminStream {
+    fn connect(address: &AdminAddress) -> io::Result<AdminStream> {
+        match address {
+            AdminAddress::Tcp(address) => TcpStream::connect(address).map(AdminStream::Tcp),
+            #[cfg(unix)]
+            AdminAddress::Unix(path) => UnixStream::connect(path).map(AdminStream::Unix),
+            #[cfg(not(unix))]
+            AdminAddress::Unix(_) => Err(no_unix_sockets()),
+        }
+    }
+
+    /// Ends the connection, the thread reading it stops
+    fn shutdown(&self) {
+        match self {
+            AdminStream::Tcp(stream) => stream.shutdown(Shutdown::Both).unwrap_or(()),
+            #[cfg(unix)]
+            AdminStream::Unix(stream) => stream.shutdown(Shutdown::Both).unwrap_or(()),
+        }
+    }
+
+    fn try_clone(&self) -> io::Result<AdminStream> {
+        match self {
+            AdminStream::Tcp(stream) => stream.try_clone().map(AdminStream::Tcp),
+            #[cfg(unix)]
+            AdminStream::Unix(stream) => stream.try_clone().map(AdminStream::Unix),
+        }
+    }
+}
+
+impl Read for AdminStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            AdminStream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            AdminStream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for AdminStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            AdminStream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            AdminStream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            AdminStream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            AdminStream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn no_unix_sockets() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "Unix sockets need a unix system")
+}
@@ -0,0 +1,261 @@
+//! # Authentication
+//! The authenticator decides who a client is during the lobby handshake.
+//!
+//! It is called with the client hello, which carries the credentials of the client,
+//! and returns the account of the player, or `None` to reject the client as unauthorized.
+//! The account is attached to the `PlayerInfo` of the player, which games get with `PlayerEvent::Joined`.
+//! * `SharedSecret`: every client knows the same secret, for private servers. Its players have no account id.
+//! * `SignedToken`: a backend signs tokens with a key it shares with the server.
+//! * `AllowList`: only the listed usernames get in, each with its own secret.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::game::Identity;
+use crate::protocol::Hello;
+
+/// Checks who clients are.
+/// # Example
+/// ```
+/// use blizzard_server::game::Identity;
+/// use blizzard_server::protocol::Hello;
+/// use blizzard_server::server::auth::Authenticator;
+///
+/// // Lets in guests, without roles
+/// #[derive(Debug)]
+/// struct Guests;
+///
+/// impl Authenticator for Guests {
+///     fn authenticate(&self, hello: &Hello, _peer: &str) -> Option<Identity> {
+///         Some(Identity::new(&format!("guest-{}", hello.username), &[]))
+///     }
+/// }
+/// ```
+pub trait Authenticator: Send + Sync + fmt::Debug {
+    /// Returns the account of the client, `None` rejects it
+    fn authenticate(&self, hello: &Hello, peer: &str) -> Option<Identity>;
+}
+
+/// Lets in clients that send the secret of the server.
+///
+/// Anyone with the secret may claim any username, so there is no account behind a player:
+/// identities have no account id, and bans of players go by username or address.
+#[derive(Clone)]
+pub struct SharedSecret {
+    secret: String,
+}
+
+impl SharedSecret {
+    /// Lets in clients that send this secret
+    pub fn new(secret: &str) -> SharedSecret {
+        SharedSecret {
+            secret: secret.to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedSecret")
+    }
+}
+
+impl Authenticator for SharedSecret {
+    fn authenticate(&self, hello: &Hello, _peer: &str) -> Option<Identity> {
+        let credentials = hello.credentials.as_deref()?;
+        if !same_bytes(credentials.as_bytes(), self.secret.as_bytes()) {
+            return None;
+        }
+        Some(Identity {
+            account_id: None,
+            roles: vec![],
+        })
+    }
+}
+
+/// Lets in clients with a token signed by the key of the server.
+///
+/// Tokens are `account:role,role:expires:signature`, where `expires` is a unix time in seconds
+/// and `signature` the hex HMAC-SHA256 of the rest of the token.
+/// # Example
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use blizzard_server::protocol::Hello;
+/// use blizzard_server::server::auth::{Authenticator, SignedToken};
+///
+/// let tokens = SignedToken::new(b"key shared with the backend");
+/// let mut hello = Hello::new("alice");
+/// let expires = SystemTime::now() + Duration::from_secs(3600);
+/// hello.credentials = Some(tokens.sign("account-42", &["admin"], expires).unwrap());
+///
+/// let identity = tokens.authenticate(&hello, "203.0.113.7:5000").unwrap();
+/// assert_eq!(identity.account_id.as_deref(), Some("account-42"));
+/// assert!(identity.has_role("admin"));
+/// ```
+#[derive(Clone)]
+pub struct SignedToken {
+    key: Vec<u8>,
+}
+
+impl SignedToken {
+    /// Checks tokens signed with this key
+    pub fn new(key: &[u8]) -> SignedToken {
+        SignedToken { key: key.to_vec() }
+    }
+
+    /// Creates a token for an account, valid until `expires`
+    /// Accounts and roles can't hold `:` or `,`, which separate the claims, and roles can't be empty.
+    pub fn sign(
+        &self,
+        account_id: &str,
+        roles: &[&str],
+        expires: SystemTime,
+    ) -> Result<String, InvalidClaims> {
+        let separated = |claim: &str| claim.contains(':') || claim.contains(',');
+        if separated(account_id) || roles.iter().any(|role| role.is_empty() || separated(role)) {
+            return Err(InvalidClaims);
+        }
+
+        let expires = expires
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+        let claims = format!("{}:{}:{}", account_id, roles.join(","), expires);
+        let signature = to_hex(&self.mac(&claims).finalize().into_bytes());
+        Ok(format!("{}:{}", claims, signature))
+    }
+
+    fn mac(&self, claims: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("Keys of any size work");
+        mac.update(claims.as_bytes());
+        mac
+    }
+}
+
+impl fmt::Debug for SignedToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SignedToken")
+    }
+}
+
+/// Account or roles of a token hold the separators of its claims
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidClaims;
+
+impl fmt::Display for InvalidClaims {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Accounts and roles can't hold ':' or ',', and roles can't be empty")
+    }
+}
+
+impl std::error::Error for InvalidClaims {}
+
+impl Authenticator for SignedToken {
+    fn authenticate(&self, hello: &Hello, _peer: &str) -> Option<Identity> {
+        let token = hello.credentials.as_deref()?;
+
+        // Signatures are hex, the claims split on the last colons
+        let (claims, signature) = token.rsplit_once(':')?;
+        let signature = from_hex(signature)?;
+        self.mac(claims).verify_slice(&signature).ok()?;
+
+        let mut parts = claims.rsplitn(3, ':');
+        let expires: u64 = parts.next()?.parse().ok()?;
+        let roles = parts.next()?;
+        let account_id = parts.next()?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        if now.as_secs() >= expires {
+            return None;
+        }
+        let roles: Vec<&str> = roles.split(',').filter(|role| !role.is_empty()).collect();
+        Some(Identity::new(account_id, &roles))
+    }
+}
+
+/// Secret and roles of a listed account
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct AllowedAccount {
+    pub secret: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// Lets in the listed usernames only, when they send their own secret, with their roles
+#[derive(Clone, Default)]
+pub struct AllowList {
+    accounts: HashMap<String, AllowedAccount>,
+}
+
+impl AllowList {
+    /// Creates an empty list, which lets nobody in
+    pub fn new() -> AllowList {
+        AllowList::default()
+    }
+
+    /// Allows a username with its secret and roles
+    pub fn allow(mut self, username: &str, secret: &str, roles: &[&str]) -> Self {
+        let account = AllowedAccount {
+            secret: secret.to_string(),
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        };
+        self.accounts.insert(username.to_string(), account);
+        self
+    }
+}
+
+impl From<HashMap<String, AllowedAccount>> for AllowList {
+    fn from(accounts: HashMap<String, AllowedAccount>) -> AllowList {
+        AllowList { accounts }
+    }
+}
+
+// Secrets stay out of logs
+impl fmt::Debug for AllowList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("AllowList")
+            .field(&self.accounts.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Authenticator for AllowList {
+    fn authenticate(&self, hello: &Hello, _peer: &str) -> Option<Identity> {
+        let account = self.accounts.get(&hello.username)?;
+        let credentials = hello.credentials.as_deref()?;
+        if !same_bytes(credentials.as_bytes(), account.secret.as_bytes()) {
+            return None;
+        }
+        Some(Identity {
+            account_id: Some(hello.username.clone()),
+            roles: account.roles.clone(),
+        })
+    }
+}
+
+/// Compares secrets in constant time, so timing doesn't leak how much of a guess was right
+fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some((hex_digit(*high)? << 4) | hex_digit(*low)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
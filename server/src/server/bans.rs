@@ -0,0 +1,138 @@
+//! # Bans
+//! Usernames, accounts and IP addresses the lobby turns away.
+//!
+//! Entries are written with the kind of name they ban, `user:cheater`, `account:42` or `addr:203.0.113.7`,
+//! so a username is never mistaken for an account or an address.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::server::error::lock;
+
+/// What a ban applies to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub enum BanEntry {
+    /// Players with this username, `user:<username>`
+    Username(String),
+    /// Players authenticated with this account, `account:<account id>`
+    Account(String),
+    /// Clients connecting from this IP address, `addr:<ip>`
+    Address(String),
+}
+
+impl FromStr for BanEntry {
+    type Err = String;
+
+    fn from_str(entry: &str) -> Result<BanEntry, String> {
+        let (kind, name) = entry
+            .split_once(':')
+            .ok_or_else(|| format!("{} needs a user:, account: or addr: prefix", entry))?;
+        if name.is_empty() {
+            return Err(format!("{} bans nobody", entry));
+        }
+        match kind {
+            "user" => Ok(BanEntry::Username(name.to_string())),
+            "account" => Ok(BanEntry::Account(name.to_string())),
+            "addr" => Ok(BanEntry::Address(name.to_string())),
+            _ => Err(format!("{} needs a user:, account: or addr: prefix", entry)),
+        }
+    }
+}
+
+impl TryFrom<String> for BanEntry {
+    type Error = String;
+
+    fn try_from(entry: String) -> Result<BanEntry, String> {
+        entry.parse()
+    }
+}
+
+impl fmt::Display for BanEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BanEntry::Username(username) => write!(f, "user:{}", username),
+            BanEntry::Account(account) => write!(f, "account:{}", account),
+            BanEntry::Address(address) => write!(f, "addr:{}", address),
+        }
+    }
+}
+
+impl From<BanEntry> for String {
+    fn from(entry: BanEntry) -> String {
+        entry.to_string()
+    }
+}
+
+/// Shared list of banned usernames, accounts and addresses.
+/// Clones share the same list, so a ban applies to every clone right away.
+#[derive(Clone, Debug)]
+pub struct BanList {
+    banned: Arc<Mutex<Banned>>,
+}
+
+/// Banned names, one set per kind
+#[derive(Debug, Default)]
+struct Banned {
+    usernames: HashSet<String>,
+    accounts: HashSet<String>,
+    addresses: HashSet<String>,
+}
+
+impl Banned {
+    /// Set and name of the entry
+    fn set<'a>(&mut self, entry: &'a BanEntry) -> (&mut HashSet<String>, &'a str) {
+        match entry {
+            BanEntry::Username(username) => (&mut self.usernames, username),
+            BanEntry::Account(account) => (&mut self.accounts, account),
+            BanEntry::Address(address) => (&mut self.addresses, address),
+        }
+    }
+}
+
+impl BanList {
+    /// Creates a list with the given entries
+    pub fn new(entries: &[BanEntry]) -> BanList {
+        let list = BanList {
+            banned: Arc::new(Mutex::new(Banned::default())),
+        };
+        for entry in entries {
+            list.ban(entry);
+        }
+        list
+    }
+
+    /// Bans a username, an account or an address
+    pub fn ban(&self, entry: &BanEntry) {
+        let mut banned = lock(&self.banned);
+        let (set, name) = banned.set(entry);
+        set.insert(name.to_string());
+    }
+
+    /// Lifts a ban, returns if the entry was banned
+    pub fn unban(&self, entry: &BanEntry) -> bool {
+        let mut banned = lock(&self.banned);
+        let (set, name) = banned.set(entry);
+        set.remove(name)
+    }
+
+    /// Determine if the username, the account or the address of the peer is banned
+    pub fn is_banned(&self, username: &str, account: Option<&str>, peer: &str) -> bool {
+        let banned = lock(&self.banned);
+        banned.usernames.contains(username)
+            || matches!(account, Some(account) if banned.accounts.contains(account))
+            || banned.addresses.contains(&address_of(peer))
+    }
+}
+
+/// Address bans apply to, peers of sockets are `ip:port` and bans are on the ip
+pub(crate) fn address_of(peer: &str) -> String {
+    match peer.parse::<SocketAddr>() {
+        Ok(address) => address.ip().to_string(),
+        Err(_) => peer.to_string(),
+    }
+}
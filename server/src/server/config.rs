@@ -0,0 +1,973 @@
+//! # Config
+//! The server configuration: addresses, ports, rates, player caps and timeouts.
+//!
+//! Built in code with `ServerConfig::builder()` or loaded from a TOML file,
+//! so deployments can change ports and rates without recompiling.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::game::{MatchEndHandler, MatchReport};
+use crate::server::auth::{AllowList, AllowedAccount, Authenticator, SharedSecret, SignedToken};
+use crate::server::bans::BanEntry;
+use crate::server::error::{
+    ErrorHandler, ErrorSource, FloodPolicy, MalformedInputPolicy, ServerError, SlowClientPolicy,
+};
+use crate::server::matchmaker::{
+    BalancedLoad, FillMostFull, FirstFit, Matchmaker, Parties, RatingBuckets, Regions,
+};
+use crate::server::rate_limit::RateLimit;
+
+/// Ports used by the games of the pool.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GamePorts {
+    /// Inclusive range of ports, one per game
+    Range { first: i32, last: i32 },
+    /// Let the transport pick any free port for each game
+    Ephemeral,
+}
+
+impl GamePorts {
+    /// Port of the game at `index`, `0` for ephemeral ports
+    pub fn port(&self, index: i32) -> i32 {
+        match self {
+            GamePorts::Range { first, .. } => first + index,
+            GamePorts::Ephemeral => 0,
+        }
+    }
+
+    /// Max amount of games the ports can hold
+    fn capacity(&self) -> Option<i32> {
+        match self {
+            GamePorts::Range { first, last } => Some(last - first + 1),
+            GamePorts::Ephemeral => None,
+        }
+    }
+}
+
+/// Certificate and key of the TLS listeners, PEM files.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub struct TlsFiles {
+    /// Certificate chain, the server certificate first
+    pub certificate: PathBuf,
+    /// Private key of the server certificate
+    pub key: PathBuf,
+}
+
+/// Address of the admin interface.
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminAddress {
+    /// TCP address, must be a loopback address as commands are not authenticated
+    Tcp(SocketAddr),
+    /// Path of a Unix socket, on unix systems
+    Unix(PathBuf),
+}
+
+/// Parses `unix:<path>` or a TCP address like `127.0.0.1:9999`
+impl FromStr for AdminAddress {
+    type Err = String;
+
+    fn from_str(address: &str) -> Result<AdminAddress, String> {
+        if let Some(path) = address.strip_prefix("unix:") {
+            return Ok(AdminAddress::Unix(PathBuf::from(path)));
+        }
+        address
+            .parse()
+            .map(AdminAddress::Tcp)
+            .map_err(|_| format!("{} is not an admin address", address))
+    }
+}
+
+impl fmt::Display for AdminAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdminAddress::Tcp(address) => write!(f, "{}", address),
+            AdminAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Server configuration.
+/// # Example
+/// ```
+/// use blizzard_server::server::config::ServerConfig;
+///
+/// let config = ServerConfig::builder()
+///     .lobby_port(9000)
+///     .max_games(8)
+///     .max_players(4)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(config.lobby_port, 9000);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Address the lobby and the games bind to, IPv4 or IPv6
+    pub bind_address: IpAddr,
+    /// Port where clients ask for a game
+    pub lobby_port: i32,
+    /// Ports of the games
+    pub game_ports: GamePorts,
+    /// Max amount of games running at once
+    pub max_games: i32,
+    /// Games kept running even when idle
+    pub min_games: i32,
+    /// Time an empty game keeps running before it is shut down
+    pub idle_timeout: Duration,
+    /// Players per game
+    pub max_players: i32,
+    /// Players needed to start a match
+    pub min_players: i32,
+    /// Time between enough players joining and the match starting
+    pub countdown: Duration,
+    /// Players pick their team and options before the match, which starts once all of them are ready
+    pub ready_check: bool,
+    /// Times per second the shared state is sent to clients
+    pub send_data_rate: i32,
+    /// Times per second the games update
+    pub game_update_rate: i32,
+    /// Max time the lobby and the game ports wait for the first message of a client
+    pub lobby_timeout: Duration,
+    /// Clients the lobby serves at once, extra ones are disconnected right away
+    pub max_lobby_connections: usize,
+    /// Time a disconnected player keeps its slot, to resume its session
+    pub reconnect_grace: Duration,
+    /// Time between pings to every player, which measure its round-trip time
+    pub heartbeat_interval: Duration,
+    /// Players that send nothing for this long are disconnected, pongs included
+    pub client_timeout: Duration,
+    /// Time the slot of a join ticket is reserved, before the client joins the game
+    pub reservation_timeout: Duration,
+    /// Time running matches have to finish when the server shuts down, before they are stopped
+    pub shutdown_timeout: Duration,
+    /// What to do with client messages that can't be read
+    pub malformed_input: MalformedInputPolicy,
+    /// Max bytes of a client message, with its newline, longer messages remove the client
+    pub max_message_size: usize,
+    /// Messages a client may send, `None` doesn't limit them
+    pub message_limit: Option<RateLimit>,
+    /// Bytes a client may send, `None` doesn't limit them
+    pub byte_limit: Option<RateLimit>,
+    /// What to do with clients over their message or byte limit
+    pub flood_policy: FloodPolicy,
+    /// Accepted messages of each player the validator sees
+    pub history_size: usize,
+    /// Suspicion points every player loses per second
+    pub suspicion_decay: u32,
+    /// Suspicion score that kicks a player, `None` never kicks
+    pub kick_suspicion: Option<u32>,
+    /// Suspicion score that bans the username and address of a player, `None` never bans
+    pub ban_suspicion: Option<u32>,
+    /// Packets queued for a client before it counts as slow
+    pub send_queue_limit: usize,
+    /// What to do with clients that read slower than the game sends
+    pub slow_client: SlowClientPolicy,
+    /// Host clients connect to for games, `None` sends them to the host of the lobby
+    pub public_host: Option<String>,
+    /// Usernames, accounts and IP addresses the lobby rejects
+    pub banned: Vec<BanEntry>,
+    /// Encrypts the lobby and game connections with TLS, needs the `tls` feature
+    pub tls: Option<TlsFiles>,
+    /// Where the admin interface listens, `None` disables it
+    pub admin: Option<AdminAddress>,
+    /// Where the Prometheus metrics are served over HTTP, `None` disables them
+    pub metrics_address: Option<SocketAddr>,
+    /// Chooses the game of every player
+    pub matchmaker: Arc<dyn Matchmaker>,
+    /// Checks the credentials of every client, `None` lets anyone in
+    pub authenticator: Option<Arc<dyn Authenticator>>,
+    /// Called on every lobby, game and player connection error
+    pub on_error: Option<ErrorHandler>,
+    /// Called with the results of every finished match
+    pub on_match_end: Option<MatchEndHandler>,
+}
+
+impl ServerConfig {
+    /// Creates a builder with the default configuration
+    pub fn builder() -> ServerConfigBuilder {
+        ServerConfigBuilder {
+            config: ServerConfig {
+                bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                lobby_port: 8888,
+                game_ports: GamePorts::Range {
+                    first: 7000,
+                    last: 7999,
+                },
+                max_games: 4,
+                min_games: 1,
+                idle_timeout: Duration::from_secs(60),
+                max_players: 2,
+                min_players: 1,
+                countdown: Duration::from_secs(0),
+                ready_check: false,
+                send_data_rate: 1,
+                game_update_rate: 2,
+                lobby_timeout: Duration::from_secs(10),
+                max_lobby_connections: 128,
+                reconnect_grace: Duration::from_secs(10),
+                heartbeat_interval: Duration::from_secs(1),
+                client_timeout: Duration::from_secs(10),
+                reservation_timeout: Duration::from_secs(10),
+                shutdown_timeout: Duration::from_secs(10),
+                malformed_input: MalformedInputPolicy::Disconnect,
+                max_message_size: 64 * 1024,
+                message_limit: None,
+                byte_limit: None,
+                flood_policy: FloodPolicy::Drop,
+                history_size: 32,
+                suspicion_decay: 1,
+                kick_suspicion: Some(100),
+                ban_suspicion: None,
+                send_queue_limit: 32,
+                slow_client: SlowClientPolicy::Drop,
+                public_host: None,
+                banned: vec![],
+                tls: None,
+                admin: None,
+                metrics_address: None,
+                matchmaker: Arc::new(FirstFit),
+                authenticator: None,
+                on_error: None,
+                on_match_end: None,
+            },
+        }
+    }
+
+    /// Loads a configuration from a TOML file, missing keys keep their default
+    pub fn from_toml_file<P: AsRef<Path>>(path: P) -> Result<ServerConfig, ConfigError> {
+        let contents = fs::read_to_string(path)?;
+        ServerConfig::from_toml_str(&contents)
+    }
+
+    /// Loads a configuration from a TOML string, missing keys keep their default
+    /// # Keys
+    /// ```toml
+    /// bind_address = "::"
+    /// lobby_port = 8888
+    /// game_ports = { range = { first = 7000, last = 7003 } } # or "ephemeral"
+    /// max_games = 4
+    /// min_games = 1
+    /// idle_timeout_ms = 60000
+    /// max_players = 2
+    /// min_players = 1
+    /// countdown_ms = 0
+    /// ready_check = false
+    /// send_data_rate = 1
+    /// game_update_rate = 2
+    /// lobby_timeout_ms = 10000
+    /// max_lobby_connections = 128
+    /// reconnect_grace_ms = 10000
+    /// heartbeat_interval_ms = 1000
+    /// client_timeout_ms = 10000
+    /// reservation_timeout_ms = 10000
+    /// shutdown_timeout_ms = 10000
+    /// malformed_input = "disconnect" # or "ignore", or { tolerate = 3 }
+    /// max_message_size = 65536
+    /// message_limit = { per_second = 60, burst = 120 }
+    /// byte_limit = { per_second = 65536, burst = 131072 }
+    /// flood_policy = "drop" # or "throttle", "kick"
+    /// history_size = 32
+    /// suspicion_decay = 1
+    /// kick_suspicion = 100 # or "off"
+    /// ban_suspicion = 500 # or "off"
+    /// send_queue_limit = 32
+    /// slow_client = "drop" # or "disconnect"
+    /// public_host = "games.example.com"
+    /// banned = ["user:cheater", "account:42", "addr:203.0.113.7"]
+    /// tls = { certificate = "cert.pem", key = "key.pem" }
+    /// admin = { tcp = "127.0.0.1:9999" } # or { unix = "/run/blizzard/admin.sock" }
+    /// metrics_address = "127.0.0.1:9100"
+    /// matchmaker = "first_fit" # or "fill_most_full", "balanced_load", "parties", "regions",
+    ///                          # or { rating_buckets = { bucket_size = 100 } }
+    /// authenticator = { shared_secret = "secret" } # or { signed_token = { key = "key" } },
+    ///                                              # or { allow_list = { alice = { secret = "s3cret", roles = ["admin"] } } }
+    /// ```
+    pub fn from_toml_str(contents: &str) -> Result<ServerConfig, ConfigError> {
+        let file: ConfigFile = toml::from_str(contents)?;
+        file.apply(ServerConfig::builder()).build()
+    }
+
+    /// Logs an error and passes it to the error handler
+    pub(crate) fn report_error(&self, error: &ServerError, source: ErrorSource) {
+        eprintln!("{:?}: {}", source, error);
+        if let Some(handler) = &self.on_error {
+            handler.report(error, source);
+        }
+    }
+}
+
+/// Builds and validates a `ServerConfig`.
+#[derive(Clone, Debug)]
+pub struct ServerConfigBuilder {
+    config: ServerConfig,
+}
+
+impl ServerConfigBuilder {
+    /// Address to bind, e.g. `0.0.0.0`, `::` or `127.0.0.1`
+    pub fn bind_address(mut self, bind_address: IpAddr) -> Self {
+        self.config.bind_address = bind_address;
+        self
+    }
+
+    /// Lobby port
+    pub fn lobby_port(mut self, lobby_port: i32) -> Self {
+        self.config.lobby_port = lobby_port;
+        self
+    }
+
+    /// Inclusive range of game ports
+    pub fn game_port_range(mut self, first: i32, last: i32) -> Self {
+        self.config.game_ports = GamePorts::Range { first, last };
+        self
+    }
+
+    /// Games bind any free port, avoids port clashes
+    pub fn ephemeral_game_ports(mut self) -> Self {
+        self.config.game_ports = GamePorts::Ephemeral;
+        self
+    }
+
+    /// Max amount of games running at once, more games start as the running ones fill up
+    pub fn max_games(mut self, max_games: i32) -> Self {
+        self.config.max_games = max_games;
+        self
+    }
+
+    /// Games kept running even when idle, started with the server
+    pub fn min_games(mut self, min_games: i32) -> Self {
+        self.config.min_games = min_games;
+        self
+    }
+
+    /// Time an empty game keeps running before it is shut down
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.config.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Players per game
+    pub fn max_players(mut self, max_players: i32) -> Self {
+        self.config.max_players = max_players;
+        self
+    }
+
+    /// Players needed to start a match
+    pub fn min_players(mut self, min_players: i32) -> Self {
+        self.config.min_players = min_players;
+        self
+    }
+
+    /// Time between enough players joining and the match starting
+    pub fn countdown(mut self, countdown: Duration) -> Self {
+        self.config.countdown = countdown;
+        self
+    }
+
+    /// Makes players pick their team and options, and mark themselves ready, before the match
+    pub fn ready_check(mut self, ready_check: bool) -> Self {
+        self.config.ready_check = ready_check;
+        self
+    }
+
+    /// Times per second the shared state is sent to clients
+    pub fn send_data_rate(mut self, send_data_rate: i32) -> Self {
+        self.config.send_data_rate = send_data_rate;
+        self
+    }
+
+    /// Times per second the games update
+    pub fn game_update_rate(mut self, game_update_rate: i32) -> Self {
+        self.config.game_update_rate = game_update_rate;
+        self
+    }
+
+    /// Max time the lobby and the game ports wait for the first message of a client
+    pub fn lobby_timeout(mut self, lobby_timeout: Duration) -> Self {
+        self.config.lobby_timeout = lobby_timeout;
+        self
+    }
+
+    /// Clients the lobby serves at once, each holds a thread until it gets its ticket
+    pub fn max_lobby_connections(mut self, max_lobby_connections: usize) -> Self {
+        self.config.max_lobby_connections = max_lobby_connections;
+        self
+    }
+
+    /// Time a disconnected player keeps its slot, zero removes players right away
+    pub fn reconnect_grace(mut self, reconnect_grace: Duration) -> Self {
+        self.config.reconnect_grace = reconnect_grace;
+        self
+    }
+
+    /// Time between pings to every player
+    pub fn heartbeat_interval(mut self, heartbeat_interval: Duration) -> Self {
+        self.config.heartbeat_interval = heartbeat_interval;
+        self
+    }
+
+    /// Time without packets before a player counts as gone, and is disconnected
+    pub fn client_timeout(mut self, client_timeout: Duration) -> Self {
+        self.config.client_timeout = client_timeout;
+        self
+    }
+
+    /// Time the slot of a join ticket is reserved, unused tickets expire after it
+    pub fn reservation_timeout(mut self, reservation_timeout: Duration) -> Self {
+        self.config.reservation_timeout = reservation_timeout;
+        self
+    }
+
+    /// Time running matches have to finish on shutdown, zero stops them right away
+    pub fn shutdown_timeout(mut self, shutdown_timeout: Duration) -> Self {
+        self.config.shutdown_timeout = shutdown_timeout;
+        self
+    }
+
+    /// What to do with client messages that can't be read
+    pub fn malformed_input(mut self, malformed_input: MalformedInputPolicy) -> Self {
+        self.config.malformed_input = malformed_input;
+        self
+    }
+
+    /// Max bytes of a client message, with its newline
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.config.max_message_size = max_message_size;
+        self
+    }
+
+    /// Messages a client may send per second, and at once
+    pub fn message_limit(mut self, per_second: u32, burst: u32) -> Self {
+        self.config.message_limit = Some(RateLimit::new(per_second, burst));
+        self
+    }
+
+    /// Bytes a client may send per second, and at once
+    pub fn byte_limit(mut self, per_second: u32, burst: u32) -> Self {
+        self.config.byte_limit = Some(RateLimit::new(per_second, burst));
+        self
+    }
+
+    /// What to do with clients over their message or byte limit
+    pub fn flood_policy(mut self, flood_policy: FloodPolicy) -> Self {
+        self.config.flood_policy = flood_policy;
+        self
+    }
+
+    /// Accepted messages of each player the validator sees
+    pub fn history_size(mut self, history_size: usize) -> Self {
+        self.config.history_size = history_size;
+        self
+    }
+
+    /// Suspicion points every player loses per second
+    pub fn suspicion_decay(mut self, suspicion_decay: u32) -> Self {
+        self.config.suspicion_decay = suspicion_decay;
+        self
+    }
+
+    /// Suspicion score that kicks a player, `None` never kicks
+    pub fn kick_suspicion(mut self, kick_suspicion: Option<u32>) -> Self {
+        self.config.kick_suspicion = kick_suspicion;
+        self
+    }
+
+    /// Suspicion score that bans a player, `None` never bans
+    pub fn ban_suspicion(mut self, ban_suspicion: Option<u32>) -> Self {
+        self.config.ban_suspicion = ban_suspicion;
+        self
+    }
+
+    /// Packets queued for a client before it counts as slow
+    pub fn send_queue_limit(mut self, send_queue_limit: usize) -> Self {
+        self.config.send_queue_limit = send_queue_limit;
+        self
+    }
+
+    /// What to do with clients that read slower than the game sends
+    pub fn slow_client(mut self, slow_client: SlowClientPolicy) -> Self {
+        self.config.slow_client = slow_client;
+        self
+    }
+
+    /// Host clients connect to for games, when the games are behind another address
+    pub fn public_host(mut self, public_host: &str) -> Self {
+        self.config.public_host = Some(public_host.to_string());
+        self
+    }
+
+    /// Bans a username, an account or an IP address
+    pub fn ban(mut self, entry: BanEntry) -> Self {
+        self.config.banned.push(entry);
+        self
+    }
+
+    /// Encrypts the lobby and game connections with TLS, with the certificate and key PEM files
+    pub fn tls<P: Into<PathBuf>>(mut self, certificate: P, key: P) -> Self {
+        self.config.tls = Some(TlsFiles {
+            certificate: certificate.into(),
+            key: key.into(),
+        });
+        self
+    }
+
+    /// Opens the admin interface on a loopback TCP address or a Unix socket
+    pub fn admin(mut self, address: AdminAddress) -> Self {
+        self.config.admin = Some(address);
+        self
+    }
+
+    /// Serves the Prometheus metrics over HTTP on the address
+    pub fn metrics_address(mut self, address: SocketAddr) -> Self {
+        self.config.metrics_address = Some(address);
+        self
+    }
+
+    /// Chooses the game of every player, `FirstFit` by default
+    pub fn matchmaker<M: Matchmaker + 'static>(mut self, matchmaker: M) -> Self {
+        self.config.matchmaker = Arc::new(matchmaker);
+        self
+    }
+
+    /// Checks the credentials of every client, clients it refuses are rejected as unauthorized
+    pub fn authenticator<A: Authenticator + 'static>(mut self, authenticator: A) -> Self {
+        self.config.authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Callback for lobby, game and player connection errors
+    pub fn on_error<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&ServerError, ErrorSource) + Send + Sync + 'static,
+    {
+        self.config.on_error = Some(ErrorHandler::new(callback));
+        self
+    }
+
+    /// Callback for the results of finished matches
+    pub fn on_match_end<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&MatchReport) + Send + Sync + 'static,
+    {
+        self.config.on_match_end = Some(MatchEndHandler::new(callback));
+        self
+    }
+
+    /// Validates and returns the configuration
+    pub fn build(self) -> Result<ServerConfig, ConfigError> {
+        let config = self.config;
+
+        if config.max_games < 1 {
+            return Err(ConfigError::Invalid("max_games must be at least 1".into()));
+        }
+        if config.min_games < 0 || config.min_games > config.max_games {
+            return Err(ConfigError::Invalid(
+                "min_games must be between 0 and max_games".into(),
+            ));
+        }
+        if config.max_players < 1 {
+            return Err(ConfigError::Invalid(
+                "max_players must be at least 1".into(),
+            ));
+        }
+        if config.min_players < 1 || config.min_players > config.max_players {
+            return Err(ConfigError::Invalid(
+                "min_players must be between 1 and max_players".into(),
+            ));
+        }
+        if config.max_lobby_connections < 1 {
+            return Err(ConfigError::Invalid(
+                "max_lobby_connections must be at least 1".into(),
+            ));
+        }
+        // Rates become sleep times of 1000 / rate millis
+        if config.send_data_rate < 1 || config.send_data_rate > 1000 {
+            return Err(ConfigError::Invalid(
+                "send_data_rate must be between 1 and 1000".into(),
+            ));
+        }
+        if config.game_update_rate < 1 || config.game_update_rate > 1000 {
+            return Err(ConfigError::Invalid(
+                "game_update_rate must be between 1 and 1000".into(),
+            ));
+        }
+        // Clients answer pings, so they need a chance to answer one before timing out
+        if config.heartbeat_interval == Duration::from_secs(0) {
+            return Err(ConfigError::Invalid(
+                "heartbeat_interval must be longer than zero".into(),
+            ));
+        }
+        if config.client_timeout <= config.heartbeat_interval {
+            return Err(ConfigError::Invalid(
+                "client_timeout must be longer than heartbeat_interval".into(),
+            ));
+        }
+        if config.max_message_size < 1 {
+            return Err(ConfigError::Invalid(
+                "max_message_size must be at least 1".into(),
+            ));
+        }
+        for limit in config.message_limit.iter().chain(&config.byte_limit) {
+            if limit.per_second < 1 || limit.burst < 1 {
+                return Err(ConfigError::Invalid(
+                    "rate limits need at least 1 per second and a burst of 1".into(),
+                ));
+            }
+        }
+        // Sleeping readers would hold every connection of the event loop
+        if config.flood_policy == FloodPolicy::Throttle
+            && cfg!(feature = "async-io")
+            && config.tls.is_none()
+        {
+            return Err(ConfigError::Invalid(
+                "flood_policy throttle would stall the async-io event loop".into(),
+            ));
+        }
+        // Messages over the byte burst could never be sent
+        if let Some(limit) = config.byte_limit {
+            if (limit.burst as usize) < config.max_message_size {
+                return Err(ConfigError::Invalid(
+                    "byte_limit burst must hold a message of max_message_size".into(),
+                ));
+            }
+        }
+        if config.send_queue_limit < 1 {
+            return Err(ConfigError::Invalid(
+                "send_queue_limit must be at least 1".into(),
+            ));
+        }
+        if config.tls.is_some() && !cfg!(feature = "tls") {
+            return Err(ConfigError::Invalid(
+                "tls needs the tls feature of blizzard-server".into(),
+            ));
+        }
+        match &config.admin {
+            Some(AdminAddress::Unix(_)) if !cfg!(unix) => {
+                return Err(ConfigError::Invalid(
+                    "admin unix sockets need a unix system".into(),
+                ));
+            }
+            Some(AdminAddress::Tcp(address)) if !address.ip().is_loopback() => {
+                return Err(ConfigError::Invalid(format!(
+                    "admin address {} is not a loopback address",
+                    address
+                )));
+            }
+            _ => {}
+        }
+        // Clients must know the lobby port, it can't be picked by the transport
+        if !valid_port(config.lobby_port) || config.lobby_port == 0 {
+            return Err(ConfigError::Invalid(format!(
+                "lobby_port {} is not a valid port",
+                config.lobby_port
+            )));
+        }
+        if let GamePorts::Range { first, last } = config.game_ports {
+            if !valid_port(first) || !valid_port(last) || first == 0 || first > last {
+                return Err(ConfigError::Invalid(format!(
+                    "game port range {}..={} is not valid",
+                    first, last
+                )));
+            }
+            if (first..=last).contains(&config.lobby_port) {
+                return Err(ConfigError::Invalid(format!(
+                    "lobby_port {} is inside the game port range",
+                    config.lobby_port
+                )));
+            }
+        }
+        if let Some(capacity) = config.game_ports.capacity() {
+            if capacity < config.max_games {
+                return Err(ConfigError::Invalid(format!(
+                    "game port range holds {} games, but max_games is {}",
+                    capacity, config.max_games
+                )));
+            }
+        }
+
+        // Matchmakers from the builder and from files both see the timeout
+        config
+            .matchmaker
+            .reservation_timeout(config.reservation_timeout);
+        Ok(config)
+    }
+}
+
+fn valid_port(port: i32) -> bool {
+    port >= 0 && port <= u16::MAX as i32
+}
+
+/// Layout of a TOML configuration file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    bind_address: Option<IpAddr>,
+    lobby_port: Option<i32>,
+    game_ports: Option<GamePorts>,
+    max_games: Option<i32>,
+    min_games: Option<i32>,
+    idle_timeout_ms: Option<u64>,
+    max_players: Option<i32>,
+    min_players: Option<i32>,
+    countdown_ms: Option<u64>,
+    ready_check: Option<bool>,
+    send_data_rate: Option<i32>,
+    game_update_rate: Option<i32>,
+    lobby_timeout_ms: Option<u64>,
+    max_lobby_connections: Option<usize>,
+    reconnect_grace_ms: Option<u64>,
+    heartbeat_interval_ms: Option<u64>,
+    client_timeout_ms: Option<u64>,
+    reservation_timeout_ms: Option<u64>,
+    shutdown_timeout_ms: Option<u64>,
+    malformed_input: Option<MalformedInputPolicy>,
+    max_message_size: Option<usize>,
+    message_limit: Option<RateLimit>,
+    byte_limit: Option<RateLimit>,
+    flood_policy: Option<FloodPolicy>,
+    history_size: Option<usize>,
+    suspicion_decay: Option<u32>,
+    kick_suspicion: Option<Threshold>,
+    ban_suspicion: Option<Threshold>,
+    send_queue_limit: Option<usize>,
+    slow_client: Option<SlowClientPolicy>,
+    public_host: Option<String>,
+    banned: Option<Vec<BanEntry>>,
+    tls: Option<TlsFiles>,
+    admin: Option<AdminAddress>,
+    metrics_address: Option<SocketAddr>,
+    matchmaker: Option<MatchmakerName>,
+    authenticator: Option<AuthenticatorName>,
+}
+
+/// Suspicion score of a TOML configuration file, or `"off"` to never act on it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Threshold {
+    Score(u32),
+    Off(Off),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Off {
+    Off,
+}
+
+impl Threshold {
+    fn score(self) -> Option<u32> {
+        match self {
+            Threshold::Score(score) => Some(score),
+            Threshold::Off(_) => None,
+        }
+    }
+}
+
+/// Built-in matchmakers of a TOML configuration file.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MatchmakerName {
+    FirstFit,
+    FillMostFull,
+    BalancedLoad,
+    RatingBuckets { bucket_size: u32 },
+    Regions,
+    Parties,
+}
+
+impl MatchmakerName {
+    fn build(self) -> Arc<dyn Matchmaker> {
+        match self {
+            MatchmakerName::FirstFit => Arc::new(FirstFit),
+            MatchmakerName::FillMostFull => Arc::new(FillMostFull),
+            MatchmakerName::BalancedLoad => Arc::new(BalancedLoad),
+            MatchmakerName::RatingBuckets { bucket_size } => {
+                Arc::new(RatingBuckets::new(bucket_size))
+            }
+            MatchmakerName::Regions => Arc::new(Regions::new()),
+            MatchmakerName::Parties => Arc::new(Parties::new()),
+        }
+    }
+}
+
+/// Built-in authenticators of a TOML configuration file.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AuthenticatorName {
+    SharedSecret(String),
+    SignedToken { key: String },
+    AllowList(HashMap<String, AllowedAccount>),
+}
+
+impl AuthenticatorName {
+    fn build(self) -> Arc<dyn Authenticator> {
+        match self {
+            AuthenticatorName::SharedSecret(secret) => Arc::new(SharedSecret::new(&secret)),
+            AuthenticatorName::SignedToken { key } => Arc::new(SignedToken::new(key.as_bytes())),
+            AuthenticatorName::AllowList(accounts) => Arc::new(AllowList::from(accounts)),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Overrides the builder values present in the file
+    fn apply(self, mut builder: ServerConfigBuilder) -> ServerConfigBuilder {
+        let config = &mut builder.config;
+        if let Some(bind_address) = self.bind_address {
+            config.bind_address = bind_address;
+        }
+        if let Some(lobby_port) = self.lobby_port {
+            config.lobby_port = lobby_port;
+        }
+        if let Some(game_ports) = self.game_ports {
+            config.game_ports = game_ports;
+        }
+        if let Some(max_games) = self.max_games {
+            config.max_games = max_games;
+        }
+        if let Some(min_games) = self.min_games {
+            config.min_games = min_games;
+        }
+        if let Some(idle_timeout_ms) = self.idle_timeout_ms {
+            config.idle_timeout = Duration::from_millis(idle_timeout_ms);
+        }
+        if let Some(max_players) = self.max_players {
+            config.max_players = max_players;
+        }
+        if let Some(min_players) = self.min_players {
+            config.min_players = min_players;
+        }
+        if let Some(countdown_ms) = self.countdown_ms {
+            config.countdown = Duration::from_millis(countdown_ms);
+        }
+        if let Some(ready_check) = self.ready_check {
+            config.ready_check = ready_check;
+        }
+        if let Some(send_data_rate) = self.send_data_rate {
+            config.send_data_rate = send_data_rate;
+        }
+        if let Some(game_update_rate) = self.game_update_rate {
+            config.game_update_rate = game_update_rate;
+        }
+        if let Some(lobby_timeout_ms) = self.lobby_timeout_ms {
+            config.lobby_timeout = Duration::from_millis(lobby_timeout_ms);
+        }
+        if let Some(max_lobby_connections) = self.max_lobby_connections {
+            config.max_lobby_connections = max_lobby_connections;
+        }
+        if let Some(reconnect_grace_ms) = self.reconnect_grace_ms {
+            config.reconnect_grace = Duration::from_millis(reconnect_grace_ms);
+        }
+        if let Some(heartbeat_interval_ms) = self.heartbeat_interval_ms {
+            config.heartbeat_interval = Duration::from_millis(heartbeat_interval_ms);
+        }
+        if let Some(client_timeout_ms) = self.client_timeout_ms {
+            config.client_timeout = Duration::from_millis(client_timeout_ms);
+        }
+        if let Some(reservation_timeout_ms) = self.reservation_timeout_ms {
+            config.reservation_timeout = Duration::from_millis(reservation_timeout_ms);
+        }
+        if let Some(shutdown_timeout_ms) = self.shutdown_timeout_ms {
+            config.shutdown_timeout = Duration::from_millis(shutdown_timeout_ms);
+        }
+        if let Some(malformed_input) = self.malformed_input {
+            config.malformed_input = malformed_input;
+        }
+        if let Some(max_message_size) = self.max_message_size {
+            config.max_message_size = max_message_size;
+        }
+        if let Some(message_limit) = self.message_limit {
+            config.message_limit = Some(message_limit);
+        }
+        if let Some(byte_limit) = self.byte_limit {
+            config.byte_limit = Some(byte_limit);
+        }
+        if let Some(flood_policy) = self.flood_policy {
+            config.flood_policy = flood_policy;
+        }
+        if let Some(history_size) = self.history_size {
+            config.history_size = history_size;
+        }
+        if let Some(suspicion_decay) = self.suspicion_decay {
+            config.suspicion_decay = suspicion_decay;
+        }
+        if let Some(kick_suspicion) = self.kick_suspicion {
+            config.kick_suspicion = kick_suspicion.score();
+        }
+        if let Some(ban_suspicion) = self.ban_suspicion {
+            config.ban_suspicion = ban_suspicion.score();
+        }
+        if let Some(send_queue_limit) = self.send_queue_limit {
+            config.send_queue_limit = send_queue_limit;
+        }
+        if let Some(slow_client) = self.slow_client {
+            config.slow_client = slow_client;
+        }
+        if let Some(public_host) = self.public_host {
+            config.public_host = Some(public_host);
+        }
+        if let Some(banned) = self.banned {
+            config.banned = banned;
+        }
+        if let Some(tls) = self.tls {
+            config.tls = Some(tls);
+        }
+        if let Some(admin) = self.admin {
+            config.admin = Some(admin);
+        }
+        if let Some(metrics_address) = self.metrics_address {
+            config.metrics_address = Some(metrics_address);
+        }
+        if let Some(matchmaker) = self.matchmaker {
+            config.matchmaker = matchmaker.build();
+        }
+        if let Some(authenticator) = self.authenticator {
+            config.authenticator = Some(authenticator.build());
+        }
+        builder
+    }
+}
+
+/// Errors when loading or validating a configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read
+    Io(io::Error),
+    /// The file is not valid TOML or has unknown keys
+    Parse(toml::de::Error),
+    /// A value is out of range
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Could not read config: {}", error),
+            ConfigError::Parse(error) => write!(f, "Could not parse config: {}", error),
+            ConfigError::Invalid(reason) => write!(f, "Invalid config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(error: toml::de::Error) -> Self {
+        ConfigError::Parse(error)
+    }
+}
@@ -1,10 +1,13 @@
 //! # Connector
 //! The connector passes information between the game pool and controller.
-//! It is in charge of enabling "connecting" capabilities.
+//! It is in charge of enabling "connecting" capabilities:
+//! the slots reserved for join tickets, the phase of the match and whether the game still takes players.
 
-use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -12,7 +15,17 @@ use serde::Serialize;
 use blizzard_engine::core::network_application::Application;
 use blizzard_engine::game::Game;
 
+use crate::game::{random_token, InputHandler, MatchPhase, PlayerInfo};
+use crate::server::config::ServerConfig;
 use crate::server::controller::Controller;
+use crate::server::error::{lock, ServerError};
+use crate::server::matchmaker::GameSummary;
+use crate::server::metrics::Metrics;
+use crate::server::transport::{Listener, Transport};
+use crate::server::validation::Moderation;
+
+/// Creates the app of a new match, from the prototype game
+pub type AppFactory<T, K, I> = dyn Fn() -> Application<T, K, I> + Send + Sync;
 
 /// # Functionality
 /// * Connects clients to game
@@ -24,28 +37,92 @@ pub struct Connector {
     pub port: i32,
     max_players: i32,
     player_count: usize,
+    phase: MatchPhase,
+    reservation_timeout: Duration,
+    // Tickets handed out by the lobby, not yet used to join
+    tickets: HashMap<String, Reservation>,
+    // Last time a player or a reservation came or went
+    last_active: Instant,
+    retired: bool,
+    // The controller stopped listening, the port can be bound again
+    port_closed: bool,
+    // Server is shutting down, no match starts
+    closing: bool,
+    // The game plays no more matches
+    matches_ended: bool,
+    // Thread of the controller, joined when the server shuts down
+    thread: Option<JoinHandle<()>>,
+    // Unblocks the controller waiting for connections
+    wake: Box<dyn Fn() + Send>,
+    // Given by the matchmaker when the game started
+    tags: HashMap<String, String>,
+    room: Option<Room>,
+    // Controller of the game, for the admin interface
+    controller: Weak<Mutex<Controller>>,
+}
+
+/// Private room of a game
+struct Room {
+    code: String,
+    password: Option<String>,
+    // Ticket of the player who created the room
+    host_ticket: Option<String>,
+    host: Option<usize>,
+    start_requested: bool,
+}
+
+/// Slot held for the player of a ticket
+struct Reservation {
+    info: PlayerInfo,
+    expires: Instant,
 }
 
 impl Connector {
     /// Creates a game connector
-    pub fn new<T: Game<K, I>, K, I, M>(
+    /// Fails if the game port can't be opened.
+    pub fn new<X: Transport, T: Game<K, I>, K, I, M>(
+        transport: &X,
         port: i32,
-        max_players: i32,
-        app: Application<T, K, I>,
-        handle_input: &'static (dyn Fn(Receiver<(M, usize)>, Arc<Mutex<I>>) -> I + Sync),
-        send_data_rate: i32,
-    ) -> Arc<Mutex<Connector>>
+        config: Arc<ServerConfig>,
+        new_app: Arc<AppFactory<T, K, I>>,
+        handle_input: &'static InputHandler<M, I>,
+        moderation: Moderation<M>,
+        metrics: Metrics,
+    ) -> io::Result<Arc<Mutex<Connector>>>
     where
         T: Send + 'static,
         K: Send + Serialize + 'static,
-        I: Send + Copy,
-        M: Send + DeserializeOwned,
+        I: Send + Copy + 'static,
+        M: Send + DeserializeOwned + 'static,
     {
+        // Open the game port, before the controller thread so a bad port fails early
+        let listener = transport.bind(port)?;
+        let port = listener.port();
+
+        // A connection wakes the controller up, so it sees the game was retired
+        let transport = transport.clone();
+        let wake = move || {
+            transport.connect(port).map(|_| ()).unwrap_or(());
+        };
+
         // Create game wrapper
         let game_connector = Connector {
             player_count: 0,
+            phase: MatchPhase::Waiting,
+            reservation_timeout: config.reservation_timeout,
+            tickets: HashMap::new(),
+            last_active: Instant::now(),
+            retired: false,
+            port_closed: false,
+            closing: false,
+            matches_ended: false,
+            thread: None,
+            wake: Box::new(wake),
+            tags: HashMap::new(),
+            room: None,
+            controller: Weak::new(),
             port,
-            max_players,
+            max_players: config.max_players,
         };
 
         // Clone game connector for game controller
@@ -55,35 +132,271 @@ impl Connector {
         // Spawn thread for handling connections
         let builder = thread::Builder::new().name(format!("Controller-{}", port));
 
-        builder
+        let thread = builder
             .spawn(move || {
                 Controller::open_game_port(
-                    port,
-                    max_players,
+                    listener,
+                    config,
                     connector_clone,
                     handle_input,
-                    app,
-                    send_data_rate,
+                    new_app,
+                    moderation,
+                    metrics,
                 );
             })
             .expect("Could not create thread");
+        lock(&game_connector).thread = Some(thread);
 
         // Return connector for pool
-        return game_connector;
+        return Ok(game_connector);
+    }
+
+    /// Links the controller of the game, set once it starts
+    pub fn attach(&mut self, controller: &Arc<Mutex<Controller>>) {
+        self.controller = Arc::downgrade(controller);
+    }
+
+    /// Controller of the game, `None` before it starts or after it closed
+    /// Drop the connector lock before locking it, controllers lock their connector.
+    pub fn controller(&self) -> Option<Arc<Mutex<Controller>>> {
+        self.controller.upgrade()
     }
 
     /// Determine if game is no full, to add new players
+    /// Reserved slots count as taken until they expire, finished matches take no players.
     pub fn is_empty(&self) -> bool {
-        self.player_count < self.max_players as usize
+        self.summary().free_slots > 0
     }
 
     /// Add a player
     pub fn add_player(&mut self) {
         self.player_count += 1;
+        self.last_active = Instant::now();
     }
 
     /// Remove a player
     pub fn remove_player(&mut self) {
         self.player_count -= 1;
+        self.last_active = Instant::now();
+    }
+
+    /// What the matchmaker knows about the game
+    pub fn summary(&self) -> GameSummary {
+        let now = Instant::now();
+        let reserved = self.tickets.values().filter(|r| r.expires > now).count();
+        let players = self.player_count + reserved;
+        let free_slots = if self.phase.is_joinable() && !self.closing {
+            (self.max_players as usize).saturating_sub(players)
+        } else {
+            0
+        };
+
+        GameSummary {
+            port: self.port,
+            players,
+            free_slots,
+            phase: self.phase,
+            tags: self.tags.clone(),
+        }
+    }
+
+    /// Tags the game for the matchmaker
+    pub fn set_tags(&mut self, tags: HashMap<String, String>) {
+        self.tags = tags;
+    }
+
+    /// Players in the game, including disconnected ones within the grace period
+    pub fn player_count(&self) -> usize {
+        self.player_count
+    }
+
+    /// Phase of the current match
+    pub fn phase(&self) -> MatchPhase {
+        self.phase
+    }
+
+    /// Moves the match to another phase
+    pub fn set_phase(&mut self, phase: MatchPhase) {
+        self.phase = phase;
+    }
+
+    /// Forgets every player, reservation and tag, for the next match
+    pub fn reset(&mut self) {
+        self.player_count = 0;
+        self.tickets.clear();
+        self.tags.clear();
+        self.last_active = Instant::now();
+        if let Some(room) = &mut self.room {
+            room.host = None;
+            room.start_requested = false;
+        }
+    }
+
+    /// Turns the game into a private room, returns the ticket of its host
+    pub fn open_room(
+        &mut self,
+        code: String,
+        password: Option<String>,
+        host: PlayerInfo,
+    ) -> Option<String> {
+        let host_ticket = self.issue_ticket(host)?;
+        self.room = Some(Room {
+            code,
+            password,
+            host_ticket: Some(host_ticket.clone()),
+            host: None,
+            start_requested: false,
+        });
+        Some(host_ticket)
+    }
+
+    /// Determine if the game is a private room
+    pub fn is_private(&self) -> bool {
+        self.room.is_some()
+    }
+
+    /// Join code of the room, `None` for public games
+    pub fn room_code(&self) -> Option<&str> {
+        self.room.as_ref().map(|room| room.code.as_str())
+    }
+
+    /// Determine if the password opens the room, rooms without password open with any
+    pub fn check_password(&self, password: Option<&str>) -> bool {
+        match &self.room {
+            Some(Room {
+                password: Some(expected),
+                ..
+            }) => password == Some(expected.as_str()),
+            _ => true,
+        }
+    }
+
+    /// Makes the player host of the room, if it joined with the host ticket or the room has no host
+    pub fn claim_host(&mut self, ticket: &str, id: usize) {
+        if let Some(room) = &mut self.room {
+            if room.host.is_none() || room.host_ticket.as_deref() == Some(ticket) {
+                room.host = Some(id);
+                room.host_ticket = None;
+            }
+        }
+    }
+
+    /// Passes the host role on when the host leaves
+    pub fn player_left(&mut self, id: usize, next_host: Option<usize>) {
+        if let Some(room) = &mut self.room {
+            if room.host == Some(id) {
+                room.host = next_host;
+            }
+        }
+    }
+
+    /// Id of the host of the room, `None` for public games
+    pub fn host(&self) -> Option<usize> {
+        self.room.as_ref().and_then(|room| room.host)
+    }
+
+    /// Host asks to start the match of the room
+    pub fn request_start(&mut self, id: usize) -> Result<(), ServerError> {
+        match &mut self.room {
+            Some(room) if room.host == Some(id) => {
+                room.start_requested = true;
+                Ok(())
+            }
+            _ => Err(ServerError::NotHost),
+        }
+    }
+
+    /// Determine if the match can start: the host asked for it in rooms, enough players in public games
+    pub fn ready_to_start(&self, min_players: usize) -> bool {
+        match &self.room {
+            Some(room) => room.start_requested,
+            None => self.player_count >= min_players,
+        }
+    }
+
+    /// Determine if the game had no players nor reservations for the timeout
+    pub fn is_idle(&mut self, timeout: Duration) -> bool {
+        self.drop_expired();
+        self.player_count == 0 && self.tickets.is_empty() && self.last_active.elapsed() >= timeout
+    }
+
+    /// Marks the game as retired and wakes its controller, which closes the port and stops the app
+    pub fn retire(&mut self) {
+        self.retired = true;
+        (self.wake)();
+    }
+
+    /// Determine if the game was retired by the pool
+    pub fn is_retired(&self) -> bool {
+        self.retired
+    }
+
+    /// Marks that the controller closed the game port
+    pub fn close_port(&mut self) {
+        self.port_closed = true;
+    }
+
+    /// Determine if the game port was closed, a new game can open it
+    pub fn port_closed(&self) -> bool {
+        self.port_closed
+    }
+
+    /// Starts no more matches and takes no more players, the current match may finish
+    pub fn close(&mut self) {
+        self.closing = true;
+    }
+
+    /// Determine if the server is shutting down the game
+    pub fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    /// Marks that the game plays no more matches, set once its last match is over
+    pub fn end_matches(&mut self) {
+        self.matches_ended = true;
+    }
+
+    /// Determine if the game plays no more matches
+    pub fn matches_ended(&self) -> bool {
+        self.matches_ended
+    }
+
+    /// Takes the thread of the controller, to wait for the game to close
+    pub fn take_thread(&mut self) -> Option<JoinHandle<()>> {
+        self.thread.take()
+    }
+
+    /// Reserves a slot and creates a ticket the player can join this game with
+    /// Returns `None` if the game is full.
+    pub fn issue_ticket(&mut self, info: PlayerInfo) -> Option<String> {
+        self.drop_expired();
+        if !self.is_empty() {
+            return None;
+        }
+
+        let ticket = random_token();
+        let reservation = Reservation {
+            info,
+            expires: Instant::now() + self.reservation_timeout,
+        };
+        self.tickets.insert(ticket.clone(), reservation);
+        self.last_active = Instant::now();
+        Some(ticket)
+    }
+
+    /// Uses up a ticket, its reserved slot becomes a player
+    /// Returns the info of the player, `None` if the ticket is unknown or expired.
+    pub fn redeem_ticket(&mut self, ticket: &str) -> Option<PlayerInfo> {
+        self.drop_expired();
+        let reservation = self.tickets.remove(ticket)?;
+        self.add_player();
+        Some(reservation.info)
+    }
+
+    /// Gives back the slots of expired reservations
+    fn drop_expired(&mut self) {
+        let now = Instant::now();
+        self.tickets
+            .retain(|_, reservation| reservation.expires > now);
     }
 }
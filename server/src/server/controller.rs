@@ -1,240 +1,1097 @@
 //! # Controller
 //! The controller is in charge of opening the game ports and handling client connections to games.
-//! It opens threads per client.
-//! Each client has a receiver and a sender thread.
+//! It reads clients, sends them the state of the app every send period,
+//! and runs the matches of its game, from waiting for players to resetting for the next one.
 
-use std::io::{BufRead, BufReader, Error, Write};
-use std::net::{TcpListener, TcpStream};
-use std::str;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{self, BufRead, BufReader};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-use blizzard_engine::core::network_application::Application;
+use blizzard_engine::core::network_application::{Application, UpdateTimes};
 use blizzard_engine::game::Game;
 use blizzard_id::Uid;
 
-use crate::game::Player;
-use crate::server::connector::Connector;
+use crate::game::{
+    InputHandler, Latency, MatchPhase, MatchReport, Player, PlayerEvent, PlayerInfo, PlayerSetup,
+};
+use crate::protocol::{
+    encode_packet, parse_packet, write_packet, ClientPacket, RosterEntry, ServerPacket,
+};
+use crate::server::admin::PlayerStatus;
+use crate::server::bans::{address_of, BanEntry, BanList};
+use crate::server::config::ServerConfig;
+use crate::server::connector::{AppFactory, Connector};
+use crate::server::error::{
+    lock, ErrorSource, FloodPolicy, MalformedInputPolicy, ServerError, SlowClientPolicy,
+};
+use crate::server::metrics::{Dropped, Metrics};
+use crate::server::rate_limit::ClientLimits;
+use crate::server::transport::{Connection, LineHandler, Listener, Outbox};
+use crate::server::validation::{MessageCheck, Moderation, Suspicion, Verdict};
+
+/// Time between checks while waiting for players
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// App of a match, with the receiver of its player events
+type MatchApp<T, K, I, M> = (Application<T, K, I>, Receiver<(PlayerEvent<M>, usize)>);
+
+/// Channel and shared state of the match being played
+struct Match<K, M> {
+    sender: Sender<(PlayerEvent<M>, usize)>,
+    shared_state: Arc<Mutex<K>>,
+    // Tick of the shared state, read while holding the shared state
+    tick: Arc<Mutex<u64>>,
+    stop_app: Arc<Mutex<bool>>,
+}
+
+/// Liveness of the connection of a player
+struct Heartbeat {
+    // Time of the last packet, updated by the reader
+    last_seen: Arc<Mutex<Instant>>,
+    // Last ping answered, late pongs of older pings are ignored
+    last_pong: u64,
+}
 
 /// # Functionality:
 /// * Connection controller
 /// * Provides information to connector
 /// * Handles client connections to the game
 pub struct Controller {
-    // Waiting for future use
     port: i32,
     connector: Arc<Mutex<Connector>>,
     players: Vec<Player>,
     max_players: i32,
+    // Closes the current connection of each player
+    closers: HashMap<usize, Box<dyn Fn() + Send>>,
+    // Packets waiting to be written to each player
+    outboxes: HashMap<usize, Box<dyn Outbox>>,
+    // Last state packet of the broadcast, for players joining between ticks
+    last_state: Option<Arc<Vec<u8>>>,
+    // Liveness of the connection of each player
+    heartbeats: HashMap<usize, Heartbeat>,
+    // Pings sent within the client timeout, with the time they were sent
+    pings: VecDeque<(u64, Instant)>,
+    // Changes every time the roster changes, so writers know when to send it
+    roster_version: u64,
+    // Address of the current connection of each player
+    peers: HashMap<usize, String>,
+    // Players kicked by the admin, removed instead of held when their connection closes
+    kicked: HashSet<usize>,
+    // Pauses the app of the current match
+    suspended: Arc<Mutex<bool>>,
+    // Update times of the app of the current match
+    update_times: Arc<Mutex<UpdateTimes>>,
+    metrics: Metrics,
 }
 
 impl Controller {
     /// Create a new controller from a connector
-    fn new(port: i32, max_players: i32, connector: Arc<Mutex<Connector>>) -> Controller {
+    fn new(
+        port: i32,
+        max_players: i32,
+        connector: Arc<Mutex<Connector>>,
+        metrics: Metrics,
+    ) -> Controller {
         Controller {
             players: vec![],
             max_players,
             port,
             connector,
+            closers: HashMap::new(),
+            outboxes: HashMap::new(),
+            last_state: None,
+            heartbeats: HashMap::new(),
+            pings: VecDeque::new(),
+            roster_version: 0,
+            peers: HashMap::new(),
+            kicked: HashSet::new(),
+            suspended: Arc::new(Mutex::new(false)),
+            update_times: Arc::new(Mutex::new(UpdateTimes::default())),
+            metrics,
         }
     }
 
+    /// Takes the handles of the app of a new match
+    fn watch_app<T: Game<K, I>, K, I>(&mut self, app: &Application<T, K, I>) {
+        self.suspended = app.suspend_handle();
+        self.update_times = app.update_times_handle();
+    }
+
     /// What it does:
-    /// * Runs a game (application).
+    /// * Runs matches of the game (application), each one created from the prototype.
     /// * Creates messaging channel between controller and app.
     /// * Creates a shared state to share between app and client.
     /// * Opens a port for game.
-    pub fn open_game_port<'de, T: Game<K, I>, K, I, M>(
-        port: i32,
-        max_players: i32,
+    /// * Closes the port and stops the app once the game is retired, then waits for the game threads.
+    pub fn open_game_port<'de, L: Listener, T: Game<K, I>, K, I, M>(
+        listener: L,
+        config: Arc<ServerConfig>,
         connector: Arc<Mutex<Connector>>,
-        handle_input: &'static (dyn Fn(Receiver<(M, usize)>, Arc<Mutex<I>>) -> I + Sync),
-        mut app: Application<T, K, I>,
-        send_data_rate: i32,
+        handle_input: &'static InputHandler<M, I>,
+        new_app: Arc<AppFactory<T, K, I>>,
+        moderation: Moderation<M>,
+        metrics: Metrics,
     ) where
         T: Send + 'static,
         K: Send + Serialize + 'static,
-        I: Send + Copy,
-        M: Send + DeserializeOwned,
+        I: Send + Copy + 'static,
+        M: Send + DeserializeOwned + 'static,
     {
         // Store port id
+        let port = listener.port();
         let id = port;
 
-        // Create message channel
-        let (tx, rx) = mpsc::channel();
-
-        // Get shared state between controller and app
-        let shared_state = Arc::clone(&app.shared_state);
+        // Channel and shared state of the first match
+        let (first, current) = Controller::new_match(&*new_app);
+        let current = Arc::new(Mutex::new(current));
 
         // Create controller mutex with reference counter
-        let controller = Arc::new(Mutex::new(Controller::new(port, max_players, connector)));
+        let mut new_controller =
+            Controller::new(port, config.max_players, Arc::clone(&connector), metrics);
+        new_controller.watch_app(&first.0);
+        let controller = Arc::new(Mutex::new(new_controller));
+        lock(&connector).attach(&controller);
 
-        // Format port
-        let port = format!("0.0.0.0:{}", port);
         println!("Opening game in port {}", port);
 
-        // Start app / game in a new thread
+        // Play matches in a new thread
+        let match_controller = Arc::clone(&controller);
+        let match_current = Arc::clone(&current);
+        let match_config = Arc::clone(&config);
         let builder = thread::Builder::new().name(format!("App-thread-{}", id));
-        builder
+        let app_thread = builder
             .spawn(move || {
-                app.start(rx, handle_input);
+                Controller::run_matches(
+                    match_controller,
+                    match_current,
+                    first,
+                    new_app,
+                    handle_input,
+                    match_config,
+                );
             })
             .expect("Could not create thread");
 
-        // Create tcp listener
-        let listener = TcpListener::bind(port).expect("Could not bind");
+        // Send the game to the players in a new thread
+        let broadcast_controller = Arc::clone(&controller);
+        let broadcast_current = Arc::clone(&current);
+        let broadcast_config = Arc::clone(&config);
+        let builder = thread::Builder::new().name(format!("Broadcast-{}", id));
+        let broadcast_thread = builder
+            .spawn(move || {
+                Controller::broadcast(broadcast_controller, broadcast_current, broadcast_config);
+            })
+            .expect("Could not create thread");
+
+        // Ping the players in a new thread
+        let heartbeat_controller = Arc::clone(&controller);
+        let heartbeat_config = Arc::clone(&config);
+        let builder = thread::Builder::new().name(format!("Heartbeat-{}", id));
+        let heartbeat_thread = builder
+            .spawn(move || Controller::heartbeat(heartbeat_controller, heartbeat_config))
+            .expect("Could not create thread");
 
-        for stream in listener.incoming() {
-            match stream {
-                Err(e) => {
-                    eprintln!("failed: {}", e)
+        loop {
+            match listener.accept() {
+                Err(e) => config.report_error(&ServerError::Io(e), ErrorSource::Game { port }),
+                // Retired games are woken up by a connection, to stop accepting
+                Ok(_) if lock(&connector).is_retired() => break,
+                Ok(stream) => {
+                    // Create concurrency clones
+                    let controller = Arc::clone(&controller);
+                    let current = Arc::clone(&current);
+                    let config = Arc::clone(&config);
+                    let moderation = moderation.clone();
+
+                    // Spawn thread and move thread and controller, the client joins or resumes there
+                    let builder = thread::Builder::new().name(format!("Game-{}-connection", id));
+                    builder
+                        .spawn(move || {
+                            Controller::handle_player_connection::<L::Connection, K, M>(
+                                stream,
+                                controller,
+                                current,
+                                Arc::clone(&config),
+                                moderation,
+                            )
+                            .unwrap_or_else(|error| {
+                                config.report_error(&error, ErrorSource::Game { port })
+                            });
+                        })
+                        .expect("Could not create thread");
                 }
-                Ok(mut stream) => {
-                    // Push a new player to the game
-                    let (could_join, player_id) = controller.lock().unwrap().add_player();
+            }
+        }
+
+        // The port closes before waiting for the game, the input handler stops with the last sender
+        println!("Closing game in port {}", port);
+        drop(listener);
+        lock(&connector).close_port();
+        *lock(&lock(&current).stop_app) = true;
+        app_thread.join().unwrap_or(());
+        broadcast_thread.join().unwrap_or(());
+        heartbeat_thread.join().unwrap_or(());
+    }
+
+    /// Creates the app, the channel and the handles of a new match
+    fn new_match<T: Game<K, I>, K, I, M>(
+        new_app: &AppFactory<T, K, I>,
+    ) -> (MatchApp<T, K, I, M>, Match<K, M>) {
+        let app = new_app();
+        let (sender, receiver) = mpsc::channel();
+        let current = Match {
+            sender,
+            shared_state: Arc::clone(&app.shared_state),
+            tick: app.tick_handle(),
+            stop_app: app.stop_handle(),
+        };
+        ((app, receiver), current)
+    }
+
+    /// Plays matches until the game is retired or closed
+    /// Every match waits for players, counts down, runs until the game ends,
+    /// reports its results and is replaced by a new match from the prototype.
+    /// A closed game lets its current match finish, and starts no other.
+    fn run_matches<T: Game<K, I>, K, I, M>(
+        controller: Arc<Mutex<Controller>>,
+        current: Arc<Mutex<Match<K, M>>>,
+        first: MatchApp<T, K, I, M>,
+        new_app: Arc<AppFactory<T, K, I>>,
+        handle_input: &'static InputHandler<M, I>,
+        config: Arc<ServerConfig>,
+    ) where
+        K: Serialize,
+        I: Send + Copy,
+        M: Send,
+    {
+        let connector = Arc::clone(&lock(&controller).connector);
+        let port = lock(&controller).port;
+        let (mut app, mut receiver) = first;
 
-                    if could_join {
-                        // Clone the controller
-                        let controller = Arc::clone(&controller);
+        loop {
+            if !Controller::wait_to_start(&controller, &connector, &config) {
+                break;
+            }
 
-                        // Update game wrapper player count
+            // Games get the picks of every player before the first update
+            if config.ready_check {
+                let sender = lock(&current).sender.clone();
+                for player in &lock(&controller).players {
+                    let setup = PlayerEvent::Setup(player.setup.clone());
+                    sender.send((setup, player.id)).unwrap_or(());
+                }
+            }
+
+            // Events of players that joined while waiting are queued in the receiver
+            lock(&connector).set_phase(MatchPhase::Running);
+            let started = Instant::now();
+            app.start(receiver, handle_input);
+
+            // Clients get the final state with the finished phase, stopped matches are reported as they are
+            let interrupted = lock(&connector).is_retired();
+            if !interrupted {
+                lock(&connector).set_phase(MatchPhase::Finished);
+            }
+            let report = MatchReport {
+                port,
+                players: lock(&controller)
+                    .players
+                    .iter()
+                    .map(|p| (p.id, p.info.clone()))
+                    .collect(),
+                result: serde_json::to_value(&*lock(&app.shared_state))
+                    .unwrap_or(serde_json::Value::Null),
+                duration: started.elapsed(),
+                interrupted,
+            };
+            if interrupted {
+                println!("Match in port {} stopped", port);
+            } else {
+                println!("Match in port {} finished", port);
+            }
+            if let Some(handler) = &config.on_match_end {
+                handler.report(&report);
+            }
+
+            // The pool closes the connections of stopped and closed games
+            if interrupted {
+                break;
+            }
+
+            // Let the writers send the last packets, then free every slot for the next match
+            thread::sleep(Duration::from_millis(
+                2 * (1000 / config.send_data_rate) as u64,
+            ));
+            if lock(&connector).is_closing() {
+                break;
+            }
+            lock(&connector).set_phase(MatchPhase::Resetting);
+            lock(&controller).reset();
+
+            // The broadcast reads the match under the controller, so it keeps no state of the old one
+            let (next_app, next) = Controller::new_match(&*new_app);
+            {
+                let mut controller = lock(&controller);
+                *lock(&current) = next;
+                controller.last_state = None;
+                controller.watch_app(&next_app.0);
+            }
+            app = next_app.0;
+            receiver = next_app.1;
+            lock(&connector).set_phase(MatchPhase::Waiting);
+        }
+        lock(&connector).end_matches();
+    }
+
+    /// Sends the phase, the roster and the shared state to every player, until the game is retired
+    /// Every packet is encoded once per send period, and queued in the outbox of each player.
+    /// Players with `send_queue_limit` packets waiting are handled by the slow client policy.
+    /// A state is sent once per tick, and again with every phase change.
+    fn broadcast<K: Serialize, M>(
+        controller: Arc<Mutex<Controller>>,
+        current: Arc<Mutex<Match<K, M>>>,
+        config: Arc<ServerConfig>,
+    ) {
+        // 1000 / millis = frames per sec
+        // millis = 1000 / frames_per_sec
+        let sleep_time = Duration::from_millis((1000 / config.send_data_rate) as u64);
+        let connector = Arc::clone(&lock(&controller).connector);
+        let port = lock(&controller).port;
+        let mut phase = None;
+        let mut roster_version = None;
+        let mut sent_tick = None;
+
+        loop {
+            thread::sleep(sleep_time);
+            if lock(&connector).is_retired() {
+                return;
+            }
+
+            // Encoded before taking the controller, the app holds the tick through every update
+            let (shared_state, tick) = {
+                let current = lock(&current);
+                (Arc::clone(&current.shared_state), Arc::clone(&current.tick))
+            };
+            let mut packets = vec![];
+            let current_phase = lock(&connector).phase();
+            if phase != Some(current_phase) {
+                phase = Some(current_phase);
+                sent_tick = None;
+                packets.push(encode_packet(&ServerPacket::<()>::Phase(current_phase)));
+            }
+            let state = {
+                // The app changes the state while holding the tick
+                let app_tick = lock(&tick);
+                let state = lock(&shared_state);
+                let tick = *app_tick;
+                if sent_tick == Some(tick) {
+                    None
+                } else {
+                    sent_tick = Some(tick);
+                    Some(encode_packet(&ServerPacket::State {
+                        tick,
+                        state: &*state,
+                    }))
+                }
+            };
+
+            // Players join under the controller, so they get every change after their first phase
+            let mut controller = lock(&controller);
+            if config.ready_check && roster_version != Some(controller.roster_version) {
+                roster_version = Some(controller.roster_version);
+                packets.push(encode_packet(&ServerPacket::<()>::Roster(
+                    controller.roster(),
+                )));
+            }
+
+            // Closed outboxes are removed when their reader notices the disconnection
+            for packet in packets {
+                match packet {
+                    Ok(packet) => {
+                        for outbox in controller.outboxes.values() {
+                            outbox.push(Arc::clone(&packet)).unwrap_or(());
+                        }
                         controller
-                            .lock()
-                            .unwrap()
-                            .connector
-                            .lock()
-                            .unwrap()
-                            .add_player();
-
-                        // Create concurrency clones
-                        let sender = tx.clone();
-                        let shared_state = Arc::clone(&shared_state);
-
-                        // Spawn thread and move thread and controller
-                        let builder = thread::Builder::new()
-                            .name(format!("Game-{}-player-{}", id, player_id));
-                        builder
-                            .spawn(move || {
-                                Controller::handle_player_connection::<K, M>(
-                                    stream,
-                                    controller,
-                                    player_id,
-                                    sender,
-                                    shared_state,
-                                    send_data_rate,
-                                )
-                                .unwrap_or_else(|error| eprintln!("{:?}", error));
-                            })
-                            .expect("Could not create thread");
-                    } else {
-                        stream.write("Could not join".as_bytes()).unwrap();
+                            .metrics
+                            .sent(controller.outboxes.len(), packet.len());
                     }
+                    Err(error) => config.report_error(&error, ErrorSource::Game { port }),
+                }
+            }
+
+            // States only go to players that keep up, the next one replaces a skipped one
+            let state = match state {
+                Some(Ok(state)) => state,
+                Some(Err(error)) => {
+                    config.report_error(&error, ErrorSource::Game { port });
+                    continue;
+                }
+                None => continue,
+            };
+            controller.last_state = Some(Arc::clone(&state));
+            let mut slow = vec![];
+            let mut sent = 0;
+            for (id, outbox) in &controller.outboxes {
+                let queued = outbox.queued();
+                if queued < config.send_queue_limit {
+                    outbox.push(Arc::clone(&state)).unwrap_or(());
+                    sent += 1;
+                    continue;
+                }
+                controller.metrics.skipped_state();
+                if config.slow_client == SlowClientPolicy::Disconnect {
+                    slow.push((*id, queued));
+                }
+            }
+            controller.metrics.sent(sent, state.len());
+
+            // The reader of a closed connection holds or removes the player
+            for (id, queued) in slow {
+                let source = ErrorSource::Player {
+                    port,
+                    player_id: id,
+                };
+                config.report_error(&ServerError::SlowClient(queued), source);
+                controller.outboxes.remove(&id);
+                if let Some(close) = controller.closers.get(&id) {
+                    close();
+                }
+            }
+        }
+    }
+
+    /// Pings every player once per heartbeat interval, until the game is retired
+    /// Players that sent nothing for the client timeout are disconnected, they may resume their session.
+    fn heartbeat(controller: Arc<Mutex<Controller>>, config: Arc<ServerConfig>) {
+        let connector = Arc::clone(&lock(&controller).connector);
+        let port = lock(&controller).port;
+        let mut next_ping = 0;
+
+        loop {
+            thread::sleep(config.heartbeat_interval);
+            if lock(&connector).is_retired() {
+                return;
+            }
+
+            let mut controller = lock(&controller);
+            next_ping += 1;
+            let now = Instant::now();
+            controller.pings.push_back((next_ping, now));
+            while let Some((_, sent)) = controller.pings.front() {
+                if now.duration_since(*sent) <= config.client_timeout {
+                    break;
+                }
+                controller.pings.pop_front();
+            }
+            let ping = encode_packet(&ServerPacket::<()>::Ping(next_ping));
+
+            let mut timed_out = vec![];
+            for (id, heartbeat) in &controller.heartbeats {
+                if lock(&heartbeat.last_seen).elapsed() > config.client_timeout {
+                    timed_out.push(*id);
+                } else if let (Ok(ping), Some(outbox)) = (&ping, controller.outboxes.get(id)) {
+                    outbox.push(Arc::clone(ping)).unwrap_or(());
+                    controller.metrics.sent(1, ping.len());
+                }
+            }
+
+            // The reader of a closed connection holds or removes the player
+            for id in timed_out {
+                let source = ErrorSource::Player {
+                    port,
+                    player_id: id,
+                };
+                config.report_error(&ServerError::ClientTimedOut, source);
+                controller.heartbeats.remove(&id);
+                if let Some(close) = controller.closers.get(&id) {
+                    close();
+                }
+            }
+        }
+    }
+
+    /// Waits for the min amount of players, their ready check and the countdown
+    /// Returns false if the game was retired or closed while waiting.
+    fn wait_to_start(
+        controller: &Arc<Mutex<Controller>>,
+        connector: &Arc<Mutex<Connector>>,
+        config: &ServerConfig,
+    ) -> bool {
+        // The controller is locked on its own, it locks the connector itself
+        let can_start = || {
+            let players_ready = !config.ready_check || lock(controller).all_ready();
+            players_ready && lock(connector).ready_to_start(config.min_players as usize)
+        };
+        let stopped = || {
+            let connector = lock(connector);
+            connector.is_retired() || connector.is_closing()
+        };
+
+        loop {
+            lock(connector).set_phase(MatchPhase::Waiting);
+            loop {
+                if stopped() {
+                    return false;
+                }
+                if can_start() {
+                    break;
+                }
+                thread::sleep(WAIT_INTERVAL);
+            }
+
+            // Players leaving or no longer ready during the countdown send the game back to waiting
+            lock(connector).set_phase(MatchPhase::Countdown {
+                duration_ms: config.countdown.as_millis() as u64,
+            });
+            let countdown = Instant::now();
+            loop {
+                if countdown.elapsed() >= config.countdown {
+                    return true;
+                }
+                thread::sleep(WAIT_INTERVAL.min(config.countdown));
+                if stopped() {
+                    return false;
+                }
+                if !can_start() {
+                    break;
                 }
             }
         }
     }
 
     /// Handles player writing and reading
-    pub fn handle_player_connection<'de, K, M>(
-        stream: TcpStream,
+    /// Reads the join or resume packet, then spawns the reader and writer threads.
+    /// Errors only disconnect this player, they are reported to the error handler.
+    fn handle_player_connection<'de, C: Connection, K, M>(
+        mut stream: C,
         game: Arc<Mutex<Controller>>,
-        id: usize,
-        sender: Sender<(M, usize)>,
-        shared_state: Arc<Mutex<K>>,
-        send_data_rate: i32,
-    ) -> Result<(), Error>
+        current: Arc<Mutex<Match<K, M>>>,
+        config: Arc<ServerConfig>,
+        moderation: Moderation<M>,
+    ) -> Result<(), ServerError>
     where
         K: Send + Serialize + 'static,
         M: Send + DeserializeOwned + 'static,
     {
+        // Keep one reader, so bytes buffered past a newline are not lost
+        let peer = stream.peer()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        // First packet decides if this is a new player or a returning one
+        let first = Controller::read_first_line(&mut stream, &mut reader, &config);
+
+        // The player is added to the match whose sender it gets, a reset can't come in between
+        let joined = match first {
+            Err(ServerError::Io(error)) => {
+                stream.close().unwrap_or(());
+                return Err(ServerError::Io(error));
+            }
+            Err(error) => Err(error),
+            Ok(buffer) => parse_packet::<ClientPacket<M>>(&buffer).and_then(|packet| {
+                let mut controller = lock(&game);
+                let joined = match packet {
+                    ClientPacket::Join { ticket } => controller.join(&ticket),
+                    ClientPacket::Resume { token } => controller.resume(&token),
+                    _ => Err(ServerError::UnexpectedPacket),
+                };
+                joined.map(|joined| (joined, lock(&current).sender.clone()))
+            }),
+        };
+
+        let ((id, token, connection, event), sender) = match joined {
+            Ok(joined) => joined,
+            Err(error) => {
+                let rejected: ServerPacket<()> = ServerPacket::Rejected {
+                    reason: error.to_string(),
+                };
+                write_packet(&mut stream, &rejected).unwrap_or(());
+                stream.close().unwrap_or(());
+                return Err(error);
+            }
+        };
+
         println!("Connecting player {} to game", id);
-        let mut stream_clone = stream.try_clone().unwrap();
-        let sender = sender.clone();
+        let last_seen = Arc::new(Mutex::new(Instant::now()));
 
-        // Defines bool for dropping the thread on disconnection
-        let drop_thread = Arc::new(Mutex::new(false));
-        let drop_copy = Arc::clone(&drop_thread);
+        let source = ErrorSource::Player {
+            port: lock(&game).port,
+            player_id: id,
+        };
 
-        // Stream receiver: Read from client
-        thread::spawn(move || {
-            let drop = drop_copy;
-            loop {
-                let mut buffer: Vec<u8> = Vec::new();
-                let mut reader = BufReader::new(&stream);
-                reader
-                    .read_until(b'\n', &mut buffer)
-                    .expect("Could not read into buffer");
-                let bytes_read = buffer.len();
-
-                // On stream input, get lock and aquire player id
-                let mut game = game.lock().unwrap();
-                let player_index = game.players.iter().position(|p| p.id == id).unwrap();
-
-                // If no bytes end connection
-                if bytes_read == 0 {
-                    // Remove player
-                    game.connector.lock().unwrap().remove_player();
-                    game.remove_player(player_index);
-
-                    // Mark thread for dropping
-                    *drop.lock().unwrap() = true;
-                    break;
-                }
+        // Tell the app about the player, then the client its session
+        // Players that never got their session can't resume it, so their slot is freed right away.
+        let resumed = matches!(event, PlayerEvent::Reconnected);
+        if sender.send((event, id)).is_err() {
+            Controller::disconnect_player(&game, id, connection, false, &sender, &config);
+            stream.close().unwrap_or(());
+            return Err(ServerError::AppClosed);
+        }
+        let session: ServerPacket<()> = ServerPacket::Session {
+            player_id: id,
+            token,
+        };
+        let opened = write_packet(&mut stream, &session).and_then(|_| {
+            let closer = stream.try_clone()?;
+            let takeover_closer = stream.try_clone()?;
+            Ok((stream.outbox()?, closer, takeover_closer))
+        });
+        let (outbox, closer, takeover_closer) = match opened {
+            Ok(opened) => opened,
+            Err(error) => {
+                Controller::disconnect_player(&game, id, connection, resumed, &sender, &config);
+                stream.close().unwrap_or(());
+                return Err(error);
+            }
+        };
 
-                // Parse message and send to app
-                let json = str::from_utf8(&buffer).unwrap();
-                let signal: M = serde_json::from_str(&json.trim()).unwrap();
+        // Player gets the phase and the state now, the broadcast sends it the roster and changes
+        {
+            let mut controller = lock(&game);
 
-                match sender.send((signal, id)) {
-                    Ok(_) => {}
-                    Err(_) => println!("Could not send signal to app."),
+            // A reset or a newer connection of the player may have come first
+            let superseded = match controller.players.iter().find(|p| p.id == id) {
+                None => Some(ServerError::MatchFinished),
+                Some(player) if player.connection != connection => {
+                    Some(ServerError::InvalidSession)
                 }
+                Some(_) => None,
+            };
+            if let Some(error) = superseded {
+                closer.close().unwrap_or(());
+                return Err(error);
             }
-        });
 
-        // Stream sender: write to client
-        thread::spawn(move || {
-            // 1000 / millis = frames per sec
-            // millis = 1000 / frames_per_sec
-            let sleep_time: u64 = (1000 / send_data_rate) as u64;
+            // Kicked before its connection could be closed
+            if controller.kicked.contains(&id) {
+                drop(controller);
+                closer.close().unwrap_or(());
+                Controller::disconnect_player(&game, id, connection, false, &sender, &config);
+                return Err(ServerError::InvalidSession);
+            }
 
-            // Client event loop
-            loop {
-                thread::sleep(Duration::from_millis(sleep_time));
-
-                // On stream input, aquire shared state lock
-                let reduced_state = shared_state.lock().unwrap();
-                let serialized = serde_json::to_string(&*reduced_state);
-
-                // Send state to client
-                if let Ok(s) = serialized {
-                    let s = s + "\n";
-                    match stream_clone.write(s.as_bytes()) {
-                        Ok(_) => {}
-                        Err(_) => println!("Could not send data to client."),
-                    }
-                }
+            controller
+                .closers
+                .insert(id, Box::new(move || takeover_closer.close().unwrap_or(())));
+            let phase = encode_packet(&ServerPacket::<()>::Phase(
+                lock(&controller.connector).phase(),
+            ))?;
+            controller.metrics.sent(1, phase.len());
+            outbox.push(phase).unwrap_or(());
+            if let Some(state) = &controller.last_state {
+                controller.metrics.sent(1, state.len());
+                outbox.push(Arc::clone(state)).unwrap_or(());
+            }
+            controller.outboxes.insert(id, outbox);
+            controller.peers.insert(id, peer.clone());
+            controller.heartbeats.insert(
+                id,
+                Heartbeat {
+                    last_seen: Arc::clone(&last_seen),
+                    last_pong: 0,
+                },
+            );
+            controller.roster_version += 1;
+        }
 
-                // Drop thread when client disconnects
-                if *drop_thread.lock().unwrap() {
-                    break;
-                }
+        // Read from client until it disconnects
+        let limits = ClientLimits::new(config.message_limit, config.byte_limit);
+        let suspicion = Suspicion::new(config.suspicion_decay);
+        let metrics = lock(&game).metrics.clone();
+        let player_reader = PlayerReader {
+            id,
+            connection,
+            sender,
+            game,
+            config,
+            source,
+            closer,
+            malformed: 0,
+            last_seen,
+            limits,
+            flooding: false,
+            rejecting: false,
+            check: moderation.check.map(|new_check| new_check()),
+            suspicion,
+            bans: moderation.bans,
+            peer,
+            metrics,
+        };
+        C::read_lines(reader, Box::new(player_reader));
+
+        Ok(())
+    }
+
+    /// Reads the first line of a client within the lobby timeout, trickled bytes don't extend it
+    /// Bytes past the newline stay in the reader, for the packets that follow.
+    fn read_first_line<C: Connection>(
+        stream: &mut C,
+        reader: &mut BufReader<C>,
+        config: &ServerConfig,
+    ) -> Result<Vec<u8>, ServerError> {
+        let deadline = Instant::now() + config.lobby_timeout;
+        let mut line = Vec::new();
+
+        while line.last() != Some(&b'\n') {
+            let now = Instant::now();
+            if now >= deadline {
+                let timed_out = io::Error::new(io::ErrorKind::TimedOut, "Join timed out");
+                return Err(ServerError::Io(timed_out));
+            }
+            stream.set_read_timeout(Some(deadline - now))?;
+
+            let available = reader.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            let used = match available.iter().position(|byte| *byte == b'\n') {
+                Some(end) => end + 1,
+                None => available.len(),
+            };
+            line.extend_from_slice(&available[..used]);
+            reader.consume(used);
+
+            if line.len() > config.max_message_size {
+                return Err(ServerError::MessageTooLarge(line.len()));
+            }
+        }
+
+        stream.set_read_timeout(None)?;
+        Ok(line)
+    }
+
+    /// Adds the player of a lobby ticket, returns its id, token, connection and join event
+    fn join<M>(
+        &mut self,
+        ticket: &str,
+    ) -> Result<(usize, String, u32, PlayerEvent<M>), ServerError> {
+        if lock(&self.connector).is_closing() {
+            return Err(ServerError::ShuttingDown);
+        }
+        if !lock(&self.connector).phase().is_joinable() {
+            return Err(ServerError::MatchFinished);
+        }
+
+        // The ticket's reserved slot is counted as a player by the connector
+        let info = lock(&self.connector)
+            .redeem_ticket(ticket)
+            .ok_or(ServerError::InvalidTicket)?;
+
+        let (could_join, id) = self.add_player(info);
+        if !could_join {
+            lock(&self.connector).remove_player();
+            return Err(ServerError::GameFull);
+        }
+
+        lock(&self.connector).claim_host(ticket, id);
+        self.roster_version += 1;
+
+        let player = self.players.iter().find(|p| p.id == id).unwrap();
+        Ok((
+            id,
+            player.token.clone(),
+            player.connection,
+            PlayerEvent::Joined(player.info.clone()),
+        ))
+    }
+
+    /// Resumes the session of a token, returns the player id, token, connection and event
+    /// A player that is still connected is taken over by the new connection.
+    fn resume<M>(
+        &mut self,
+        token: &str,
+    ) -> Result<(usize, String, u32, PlayerEvent<M>), ServerError> {
+        if !lock(&self.connector).phase().is_joinable() {
+            return Err(ServerError::MatchFinished);
+        }
+
+        // Kicked players can't come back
+        let kicked = self
+            .players
+            .iter()
+            .any(|p| p.token == token && self.kicked.contains(&p.id));
+        if kicked {
+            return Err(ServerError::InvalidSession);
+        }
+        let player = self
+            .players
+            .iter_mut()
+            .find(|p| p.token == token)
+            .ok_or(ServerError::InvalidSession)?;
+
+        // Stale connections of this player can no longer remove it
+        player.connection += 1;
+        player.connected = true;
+        let resumed = (
+            player.id,
+            player.token.clone(),
+            player.connection,
+            PlayerEvent::Reconnected,
+        );
+
+        // Close the previous connection, if the server did not notice it dropped yet
+        if let Some(close) = self.closers.remove(&resumed.0) {
+            close();
+        }
+        self.roster_version += 1;
+        Ok(resumed)
+    }
+
+    /// Handles a lost connection of a player
+    /// Without `hold` or a grace period the player is removed,
+    /// otherwise its slot is held until the grace period ends.
+    fn disconnect_player<M: Send + 'static>(
+        game: &Arc<Mutex<Controller>>,
+        id: usize,
+        connection: u32,
+        hold: bool,
+        sender: &Sender<(PlayerEvent<M>, usize)>,
+        config: &ServerConfig,
+    ) {
+        let mut controller = lock(game);
+
+        // Player already left, or a newer connection took over the session
+        let current = controller
+            .players
+            .iter()
+            .any(|p| p.id == id && p.connection == connection);
+        if !current {
+            return;
+        }
+
+        controller.closers.remove(&id);
+        controller.outboxes.remove(&id);
+        controller.heartbeats.remove(&id);
+        controller.peers.remove(&id);
+
+        // Kicked players can't come back
+        let hold = hold && !controller.kicked.contains(&id);
+        if !hold || config.reconnect_grace == Duration::from_secs(0) {
+            controller.leave(id);
+            sender.send((PlayerEvent::Left, id)).unwrap_or(());
+            return;
+        }
+
+        if let Some(player) = controller.players.iter_mut().find(|p| p.id == id) {
+            player.connected = false;
+        }
+        controller.roster_version += 1;
+        sender.send((PlayerEvent::Disconnected, id)).unwrap_or(());
+
+        // Remove the player if it does not come back in time
+        let game = Arc::clone(game);
+        let sender = sender.clone();
+        let grace = config.reconnect_grace;
+        thread::spawn(move || {
+            thread::sleep(grace);
+            let mut controller = lock(&game);
+            let expired = controller
+                .players
+                .iter()
+                .any(|p| p.id == id && !p.connected && p.connection == connection);
+            if expired {
+                controller.leave(id);
+                sender.send((PlayerEvent::Left, id)).unwrap_or(());
             }
         });
+    }
+
+    /// Closes every connection and removes every player, for the next match
+    pub fn reset(&mut self) {
+        for (_, close) in self.closers.drain() {
+            close();
+        }
+        self.outboxes.clear();
+        self.heartbeats.clear();
+        self.peers.clear();
+        self.kicked.clear();
+        self.players.clear();
+        self.roster_version += 1;
+        lock(&self.connector).reset();
+    }
+
+    /// Keeps the last tick a player acknowledged, older acks arriving late are ignored
+    fn acknowledge(game: &Arc<Mutex<Controller>>, id: usize, tick: u64) {
+        let mut controller = lock(game);
+        if let Some(player) = controller.players.iter_mut().find(|p| p.id == id) {
+            player.acked_tick = player.acked_tick.max(tick);
+        }
+    }
+
+    /// Measures the round-trip time of a player from its pong, `None` for unknown or late pongs
+    fn pong(game: &Arc<Mutex<Controller>>, id: usize, ping: u64) -> Option<Latency> {
+        let mut controller = lock(game);
+        let sent = controller
+            .pings
+            .iter()
+            .find(|(number, _)| *number == ping)
+            .map(|(_, sent)| *sent)?;
+        let heartbeat = controller.heartbeats.get_mut(&id)?;
+        if ping <= heartbeat.last_pong {
+            return None;
+        }
+        heartbeat.last_pong = ping;
+
+        let player = controller.players.iter_mut().find(|p| p.id == id)?;
+        let latency = Latency::measure(player.latency, sent.elapsed());
+        player.latency = Some(latency);
+        Some(latency)
+    }
+
+    /// Changes the picks of a player, only before the match starts
+    fn update_setup<F>(
+        game: &Arc<Mutex<Controller>>,
+        id: usize,
+        change: F,
+    ) -> Result<(), ServerError>
+    where
+        F: FnOnce(&mut PlayerSetup),
+    {
+        let mut controller = lock(game);
+        match lock(&controller.connector).phase() {
+            MatchPhase::Waiting | MatchPhase::Countdown { .. } => {}
+            _ => return Err(ServerError::MatchStarted),
+        }
+        if let Some(player) = controller.players.iter_mut().find(|p| p.id == id) {
+            change(&mut player.setup);
+        }
+        controller.roster_version += 1;
+        Ok(())
+    }
+
+    /// Determine if every connected player is ready, false without players
+    fn all_ready(&self) -> bool {
+        let mut connected = self.players.iter().filter(|p| p.connected).peekable();
+        connected.peek().is_some() && connected.all(|p| p.setup.ready)
+    }
+
+    /// Players of the game, as clients see them
+    fn roster(&self) -> Vec<RosterEntry> {
+        let host = lock(&self.connector).host();
+        self.players
+            .iter()
+            .map(|p| RosterEntry {
+                player_id: p.id,
+                username: p.info.username.clone(),
+                host: host == Some(p.id),
+                connected: p.connected,
+                setup: p.setup.clone(),
+            })
+            .collect()
+    }
+
+    /// Removes a player from the controller and the connector
+    fn leave(&mut self, id: usize) {
+        if let Some(index) = self.players.iter().position(|p| p.id == id) {
+            self.remove_player(index);
+            self.kicked.remove(&id);
+            self.roster_version += 1;
+            let mut connector = lock(&self.connector);
+            connector.remove_player();
+            connector.player_left(id, self.players.first().map(|p| p.id));
+        }
+    }
 
-        return Ok(());
+    /// Players of the game, as the admin interface shows them
+    pub fn player_statuses(&self) -> Vec<PlayerStatus> {
+        self.players
+            .iter()
+            .map(|p| PlayerStatus {
+                player_id: p.id,
+                username: p.info.username.clone(),
+                account_id: p.info.account_id().map(String::from),
+                connected: p.connected,
+                rtt_ms: p.latency.map(|latency| latency.rtt.as_millis() as u64),
+                acked_tick: p.acked_tick,
+            })
+            .collect()
+    }
+
+    /// Closes the connection of a player, which removes it from the game
+    /// Disconnected players can't resume, they are removed when their grace period ends.
+    /// Returns false if the player is not in the game.
+    pub fn kick(&mut self, id: usize) -> bool {
+        if !self.players.iter().any(|p| p.id == id) {
+            return false;
+        }
+        self.kicked.insert(id);
+        if let Some(close) = self.closers.get(&id) {
+            close();
+        }
+        true
+    }
+
+    /// Kicks the players whose username, account or address is the ban entry, returns how many
+    pub fn kick_banned(&mut self, entry: &BanEntry) -> usize {
+        let banned: Vec<usize> = self
+            .players
+            .iter()
+            .filter(|p| match entry {
+                BanEntry::Username(username) => p.info.username == *username,
+                BanEntry::Account(account) => p.info.account_id() == Some(account.as_str()),
+                BanEntry::Address(address) => {
+                    matches!(self.peers.get(&p.id), Some(peer) if address_of(peer) == *address)
+                }
+            })
+            .map(|p| p.id)
+            .collect();
+        for id in &banned {
+            self.kick(*id);
+        }
+        banned.len()
+    }
+
+    /// Sends a notice to every connected player
+    pub fn notify(&self, message: &str) -> Result<(), ServerError> {
+        self.send_to_all(&ServerPacket::Notice(message.to_string()))
+    }
+
+    /// Tells every connected player why the server shuts down, before their connection is closed
+    pub fn announce_shutdown(&self, reason: &str) -> Result<(), ServerError> {
+        self.send_to_all(&ServerPacket::Shutdown {
+            reason: reason.to_string(),
+        })
+    }
+
+    /// Queues a packet for every connected player
+    fn send_to_all(&self, packet: &ServerPacket<()>) -> Result<(), ServerError> {
+        let packet = encode_packet(packet)?;
+        for outbox in self.outboxes.values() {
+            outbox.push(Arc::clone(&packet)).unwrap_or(());
+        }
+        self.metrics.sent(self.outboxes.len(), packet.len());
+        Ok(())
+    }
+
+    /// Pauses or resumes the current match, the next match starts unpaused
+    pub fn set_paused(&self, paused: bool) {
+        *lock(&self.suspended) = paused;
+    }
+
+    /// Determine if the current match is paused
+    pub fn is_paused(&self) -> bool {
+        *lock(&self.suspended)
+    }
+
+    /// Time spent in the updates of the current match
+    pub fn update_times(&self) -> UpdateTimes {
+        *lock(&self.update_times)
     }
 
     /// Add a player to the game
-    pub fn add_player(&mut self) -> (bool, usize) {
+    pub fn add_player(&mut self, info: PlayerInfo) -> (bool, usize) {
         if self.players.len() < self.max_players as usize {
-            let id = Uid::new_numerical(4) as usize;
-            let new_player = Player::new(id);
+            // Ids must be unique inside a game, sessions are found by them
+            let mut id = Uid::new_numerical(4) as usize;
+            while self.players.iter().any(|p| p.id == id) {
+                id = Uid::new_numerical(4) as usize;
+            }
+            let new_player = Player::new(id, info);
             self.players.push(new_player);
             return (true, id);
         }
@@ -250,3 +1107,199 @@ impl Controller {
         true
     }
 }
+
+/// Reads the packets of a player and sends them to the app, until its connection ends
+/// Start requests go to the connector, ready checks and picks to the roster.
+struct PlayerReader<C, M> {
+    id: usize,
+    // Connection of the session, stale connections can't remove the player
+    connection: u32,
+    sender: Sender<(PlayerEvent<M>, usize)>,
+    game: Arc<Mutex<Controller>>,
+    config: Arc<ServerConfig>,
+    source: ErrorSource,
+    closer: C,
+    malformed: u32,
+    // Time of the last packet, read by the heartbeat
+    last_seen: Arc<Mutex<Instant>>,
+    limits: ClientLimits,
+    // Messages are being dropped, reported once per flood
+    flooding: bool,
+    // Messages are being rejected, reported once per streak
+    rejecting: bool,
+    // Validator with the history of the player
+    check: Option<Box<dyn MessageCheck<M>>>,
+    suspicion: Suspicion,
+    bans: BanList,
+    peer: String,
+    metrics: Metrics,
+}
+
+impl<C, M> PlayerReader<C, M> {
+    /// Asks the validator about a message, false if it is rejected
+    /// Players too suspicious are kicked, or banned by username and address.
+    fn validate(&mut self, tick: u64, message: &M) -> Result<bool, ServerError> {
+        let id = self.id;
+        let points = match self
+            .check
+            .as_mut()
+            .map(|check| check.check(id, tick, message))
+        {
+            None | Some(Verdict::Accept) => {
+                self.rejecting = false;
+                return Ok(true);
+            }
+            Some(Verdict::Reject(points)) => points,
+        };
+        let config = &self.config;
+        let score = self.suspicion.add(points);
+        if !self.rejecting {
+            self.rejecting = true;
+            config.report_error(&ServerError::MessageRejected(score), self.source);
+        }
+
+        if matches!(config.ban_suspicion, Some(ban) if score >= ban) {
+            let controller = lock(&self.game);
+            if let Some(player) = controller.players.iter().find(|p| p.id == self.id) {
+                self.bans
+                    .ban(&BanEntry::Username(player.info.username.clone()));
+                if let Some(account) = player.info.account_id() {
+                    self.bans.ban(&BanEntry::Account(account.to_string()));
+                }
+            }
+            self.bans.ban(&BanEntry::Address(address_of(&self.peer)));
+            return Err(ServerError::TooSuspicious(score));
+        }
+        if matches!(config.kick_suspicion, Some(kick) if score >= kick) {
+            return Err(ServerError::TooSuspicious(score));
+        }
+        Ok(false)
+    }
+}
+
+impl<C: Connection, M: Send + DeserializeOwned + 'static> LineHandler for PlayerReader<C, M> {
+    fn line(&mut self, line: &[u8]) -> Result<(), ServerError> {
+        let config = &self.config;
+        *lock(&self.last_seen) = Instant::now();
+        self.metrics.received(line.len());
+
+        // Parse message, pre-game packets need the ready check
+        let packet = parse_packet::<ClientPacket<M>>(line).and_then(|packet| match packet {
+            ClientPacket::Join { .. } | ClientPacket::Resume { .. } => {
+                Err(ServerError::UnexpectedPacket)
+            }
+            ClientPacket::Ready(_) | ClientPacket::PickTeam(_) | ClientPacket::SetOption { .. }
+                if !config.ready_check =>
+            {
+                Err(ServerError::UnexpectedPacket)
+            }
+            packet => Ok(packet),
+        });
+
+        // Floods never reach the app, acks and pongs answer the server and are not limited
+        let control = matches!(packet, Ok(ClientPacket::Ack(_)) | Ok(ClientPacket::Pong(_)));
+        match config.flood_policy {
+            _ if control => {}
+            FloodPolicy::Throttle => self.limits.wait(line.len()),
+            _ if self.limits.take(line.len()) => self.flooding = false,
+            FloodPolicy::Kick => return Err(ServerError::RateLimited),
+            _ => {
+                if !self.flooding {
+                    self.flooding = true;
+                    config.report_error(&ServerError::RateLimited, self.source);
+                }
+                self.metrics.dropped(Dropped::Flood);
+                return Ok(());
+            }
+        }
+
+        let packet = match packet {
+            Ok(packet) => packet,
+            Err(error) => {
+                self.malformed += 1;
+                match config.malformed_input {
+                    MalformedInputPolicy::Disconnect => return Err(error),
+                    MalformedInputPolicy::Tolerate(max) if self.malformed > max => {
+                        config.report_error(&error, self.source);
+                        return Err(ServerError::TooManyMalformedMessages(self.malformed));
+                    }
+                    _ => config.report_error(&error, self.source),
+                }
+                self.metrics.dropped(Dropped::Malformed);
+                return Ok(());
+            }
+        };
+
+        // Rejected messages never reach the app
+        if let ClientPacket::Input { tick, message } = &packet {
+            if !self.validate(*tick, message)? {
+                self.metrics.dropped(Dropped::Rejected);
+                return Ok(());
+            }
+        }
+
+        // Send input to app, requests the player can't make are just reported
+        let (config, game, id) = (&self.config, &self.game, self.id);
+        let requested = match packet {
+            ClientPacket::Input { tick, message } => {
+                self.sender
+                    .send((PlayerEvent::Message { tick, message }, id))
+                    .map_err(|_| ServerError::AppClosed)?;
+                Ok(())
+            }
+            ClientPacket::Ack(tick) => {
+                Controller::acknowledge(game, id, tick);
+                Ok(())
+            }
+            ClientPacket::Pong(ping) => {
+                if let Some(latency) = Controller::pong(game, id, ping) {
+                    self.sender
+                        .send((PlayerEvent::Latency(latency), id))
+                        .map_err(|_| ServerError::AppClosed)?;
+                }
+                Ok(())
+            }
+            ClientPacket::Ready(ready) => {
+                Controller::update_setup(game, id, |setup| setup.ready = ready)
+            }
+            ClientPacket::PickTeam(team) => {
+                Controller::update_setup(game, id, |setup| setup.team = Some(team))
+            }
+            ClientPacket::SetOption { key, value } => Controller::update_setup(game, id, |setup| {
+                setup.options.insert(key, value);
+            }),
+            // Only the host starts the match of a room
+            _ => lock(&lock(game).connector).request_start(id),
+        };
+        if let Err(error) = requested {
+            config.report_error(&error, self.source);
+        }
+        Ok(())
+    }
+
+    fn max_line_size(&self) -> usize {
+        self.config.max_message_size
+    }
+
+    fn closed(self: Box<Self>, result: Result<(), ServerError>) {
+        // Lost connections may come back, players kicked for errors may not
+        let hold = match &result {
+            Ok(_) | Err(ServerError::Io(_)) => true,
+            Err(_) => false,
+        };
+        if let Err(error) = result {
+            self.config.report_error(&error, self.source);
+        }
+
+        // Remove or hold the player, on disconnection or error
+        Controller::disconnect_player(
+            &self.game,
+            self.id,
+            self.connection,
+            hold,
+            &self.sender,
+            &self.config,
+        );
+        self.closer.close().unwrap_or(());
+    }
+}
@@ -0,0 +1,211 @@
+//! # Error
+//! Errors of the lobby, the games and the player connections.
+//!
+//! Errors in a player connection only disconnect that player,
+//! they are reported to the configured error handler instead of panicking.
+
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Server error definition
+#[derive(Debug)]
+pub enum ServerError {
+    /// Reading, writing or binding failed
+    Io(io::Error),
+    /// A client sent bytes that are not UTF-8
+    InvalidUtf8(Utf8Error),
+    /// A client sent a message that could not be deserialized
+    MalformedMessage(serde_json::Error),
+    /// The shared state could not be serialized
+    Serialize(serde_json::Error),
+    /// A client sent a message longer than allowed, with the bytes read
+    MessageTooLarge(usize),
+    /// A client sent too many malformed messages
+    TooManyMalformedMessages(u32),
+    /// A client reads slower than the game sends, with the packets waiting for it
+    SlowClient(usize),
+    /// A client sent nothing for the client timeout
+    ClientTimedOut,
+    /// A client sent more messages or bytes than its rate limit
+    RateLimited,
+    /// The validator rejected a message, with the suspicion score of the player
+    MessageRejected(u32),
+    /// The suspicion score of a player reached the kick or ban threshold
+    TooSuspicious(u32),
+    /// The app stopped receiving messages
+    AppClosed,
+    /// A player tried to join a full game
+    GameFull,
+    /// A player tried to resume an expired or unknown session
+    InvalidSession,
+    /// A player tried to join without a valid ticket from the lobby
+    InvalidTicket,
+    /// A player tried to join a game whose match is over
+    MatchFinished,
+    /// A player that is not the host of a room tried to start its match
+    NotHost,
+    /// A player changed its ready check or picks after the match started
+    MatchStarted,
+    /// A client sent a packet that is not valid at this point of the protocol
+    UnexpectedPacket,
+    /// A player tried to join while the server shuts down
+    ShuttingDown,
+    /// The lobby already serves its max amount of clients, with that amount
+    LobbyBusy(usize),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerError::Io(error) => write!(f, "I/O error: {}", error),
+            ServerError::InvalidUtf8(error) => write!(f, "Message is not UTF-8: {}", error),
+            ServerError::MalformedMessage(error) => write!(f, "Malformed message: {}", error),
+            ServerError::Serialize(error) => write!(f, "Could not serialize state: {}", error),
+            ServerError::MessageTooLarge(size) => {
+                write!(f, "Message too large, read {} bytes", size)
+            }
+            ServerError::TooManyMalformedMessages(count) => {
+                write!(f, "Disconnected after {} malformed messages", count)
+            }
+            ServerError::SlowClient(queued) => {
+                write!(f, "Client too slow, {} packets waiting", queued)
+            }
+            ServerError::ClientTimedOut => write!(f, "Client sent nothing for too long"),
+            ServerError::RateLimited => write!(f, "Client is over its rate limit"),
+            ServerError::MessageRejected(score) => {
+                write!(f, "Message rejected, suspicion score {}", score)
+            }
+            ServerError::TooSuspicious(score) => {
+                write!(f, "Removed with a suspicion score of {}", score)
+            }
+            ServerError::AppClosed => write!(f, "App is no longer receiving messages"),
+            ServerError::GameFull => write!(f, "Game is full"),
+            ServerError::InvalidSession => write!(f, "Session expired or unknown"),
+            ServerError::InvalidTicket => write!(f, "Join ticket unknown, expired or already used"),
+            ServerError::MatchFinished => write!(f, "Match is over"),
+            ServerError::NotHost => write!(f, "Only the host of a room can start its match"),
+            ServerError::MatchStarted => write!(f, "Match already started"),
+            ServerError::UnexpectedPacket => write!(f, "Unexpected packet"),
+            ServerError::ShuttingDown => write!(f, "Server is shutting down"),
+            ServerError::LobbyBusy(max) => {
+                write!(
+                    f,
+                    "Lobby is busy with {} clients, disconnected another",
+                    max
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ServerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ServerError::Io(error) => Some(error),
+            ServerError::InvalidUtf8(error) => Some(error),
+            ServerError::MalformedMessage(error) => Some(error),
+            ServerError::Serialize(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ServerError {
+    fn from(error: io::Error) -> Self {
+        ServerError::Io(error)
+    }
+}
+
+impl From<Utf8Error> for ServerError {
+    fn from(error: Utf8Error) -> Self {
+        ServerError::InvalidUtf8(error)
+    }
+}
+
+/// Where an error happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorSource {
+    /// Lobby port, while finding a game
+    Lobby,
+    /// Game port, while accepting connections
+    Game { port: i32 },
+    /// Connection of a player in a game
+    Player { port: i32, player_id: usize },
+    /// Admin interface, while running commands
+    Admin,
+    /// Metrics endpoint, while answering a scrape
+    Metrics,
+}
+
+/// Signature of error callbacks
+type ErrorCallback = dyn Fn(&ServerError, ErrorSource) + Send + Sync;
+
+/// Callback for errors, set with `ServerConfigBuilder::on_error`.
+#[derive(Clone)]
+pub struct ErrorHandler(Arc<ErrorCallback>);
+
+impl ErrorHandler {
+    /// Wraps a callback
+    pub fn new<F>(callback: F) -> ErrorHandler
+    where
+        F: Fn(&ServerError, ErrorSource) + Send + Sync + 'static,
+    {
+        ErrorHandler(Arc::new(callback))
+    }
+
+    /// Calls the callback
+    pub fn report(&self, error: &ServerError, source: ErrorSource) {
+        (self.0)(error, source);
+    }
+}
+
+impl fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ErrorHandler")
+    }
+}
+
+/// What happens when a client sends a message that can't be read.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MalformedInputPolicy {
+    /// Disconnect the client on the first malformed message
+    Disconnect,
+    /// Skip malformed messages
+    Ignore,
+    /// Skip up to this many malformed messages, then disconnect
+    Tolerate(u32),
+}
+
+/// What happens when the send queue of a client is full.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SlowClientPolicy {
+    /// Skip states until the client catches up, phase changes are still sent
+    Drop,
+    /// Disconnect the client, it may resume its session
+    Disconnect,
+}
+
+/// What happens when a client sends faster than its rate limit.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FloodPolicy {
+    /// Skip messages until the client is back under its limit
+    Drop,
+    /// Stop reading the client until it is back under its limit.
+    /// Not allowed with the `async-io` event loop, where it would hold every connection.
+    Throttle,
+    /// Remove the client from the game
+    Kick,
+}
+
+/// Locks a mutex, recovering the data if another thread panicked while holding it.
+/// One failing connection must not lock every other player out.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
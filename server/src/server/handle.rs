@@ -0,0 +1,166 @@
+//! # Handle
+//! The handle of a running server shuts it down.
+//!
+//! Shutting down stops the lobby, the admin interface and the metrics endpoint,
+//! lets running matches finish within the `shutdown_timeout` of the config,
+//! tells every player why with a `Shutdown` packet, and waits for the threads of every game.
+//! Matches that don't finish in time are stopped and reported as interrupted, so games can save them.
+//!
+//! The input handler of a match is not waited for, it ends once its receiver has no sender left:
+//! after the connections of the players closed, and the reconnect grace of disconnected players ran out.
+
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::JoinHandle;
+
+#[cfg(unix)]
+use std::process;
+#[cfg(unix)]
+use std::thread;
+
+#[cfg(unix)]
+use signal_hook::consts::{SIGINT, SIGTERM};
+#[cfg(unix)]
+use signal_hook::iterator::Signals;
+
+use crate::server::error::lock;
+
+/// Reason players get when a signal shuts the server down
+#[cfg(unix)]
+const SIGNAL_REASON: &str = "Server is shutting down";
+
+/// Stops every part of the server, with the reason for the players
+type StopServer = dyn FnOnce(&str) + Send;
+
+/// Handle of a running server, returned by `Server::start`.
+/// Clones control the same server, dropping them leaves it running.
+/// # Example
+/// ```no_run
+/// # fn start() -> blizzard_server::server::ServerHandle { unimplemented!() }
+/// let server = start();
+/// server.shutdown_on_signals().expect("Could not handle signals");
+/// server.wait();
+/// ```
+#[derive(Clone)]
+pub struct ServerHandle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    // Taken by the first shutdown
+    stop: Mutex<Option<Box<StopServer>>>,
+    stopped: Mutex<bool>,
+    stopped_changed: Condvar,
+}
+
+impl ServerHandle {
+    /// Wraps what stops the server
+    pub(crate) fn new<F>(stop: F) -> ServerHandle
+    where
+        F: FnOnce(&str) + Send + 'static,
+    {
+        ServerHandle {
+            shared: Arc::new(Shared {
+                stop: Mutex::new(Some(Box::new(stop))),
+                stopped: Mutex::new(false),
+                stopped_changed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Shuts the server down and returns once every game closed
+    /// Players get the reason in a `Shutdown` packet.
+    /// Later calls wait for the first one to finish, their reason is not sent.
+    pub fn shutdown(&self, reason: &str) {
+        let stop = lock(&self.shared.stop).take();
+        match stop {
+            Some(stop) => {
+                println!("Shutting down: {}", reason);
+                stop(reason);
+                *lock(&self.shared.stopped) = true;
+                self.shared.stopped_changed.notify_all();
+                println!("Server shut down");
+            }
+            None => self.wait(),
+        }
+    }
+
+    /// Determine if the server started shutting down
+    pub fn is_shutting_down(&self) -> bool {
+        lock(&self.shared.stop).is_none()
+    }
+
+    /// Blocks until the server is shut down, by another thread or a signal
+    /// Input handlers may still be running, see the module docs.
+    pub fn wait(&self) {
+        let stopped = lock(&self.shared.stopped);
+        let _stopped = self
+            .shared
+            .stopped_changed
+            .wait_while(stopped, |stopped| !*stopped)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Shuts the server down on the first SIGINT or SIGTERM
+    /// A second signal exits the process right away, without waiting for the games.
+    #[cfg(unix)]
+    pub fn shutdown_on_signals(&self) -> io::Result<()> {
+        let mut signals = Signals::new([SIGINT, SIGTERM])?;
+        let server = self.clone();
+
+        let builder = thread::Builder::new().name("Signals".to_string());
+        builder
+            .spawn(move || {
+                for signal in signals.forever() {
+                    if server.is_shutting_down() {
+                        eprintln!("Received signal {} again, exiting", signal);
+                        process::exit(1);
+                    }
+                    println!("Received signal {}", signal);
+
+                    // Signals are still caught while the games close
+                    let server = server.clone();
+                    let builder = thread::Builder::new().name("Shutdown".to_string());
+                    builder
+                        .spawn(move || server.shutdown(SIGNAL_REASON))
+                        .expect("Could not create thread");
+                }
+            })
+            .expect("Could not create thread");
+        Ok(())
+    }
+
+    /// Signals need a unix system
+    #[cfg(not(unix))]
+    pub fn shutdown_on_signals(&self) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Shutting down on signals needs a unix system",
+        ))
+    }
+}
+
+/// A listener of the server running in its own thread, the lobby, the admin interface or the metrics endpoint
+/// Its accept loop ends when it is woken up with the stop flag set.
+pub(crate) struct Service {
+    thread: JoinHandle<()>,
+    wake: Box<dyn Fn() + Send>,
+}
+
+impl Service {
+    pub(crate) fn new<F>(thread: JoinHandle<()>, wake: F) -> Service
+    where
+        F: Fn() + Send + 'static,
+    {
+        Service {
+            thread,
+            wake: Box::new(wake),
+        }
+    }
+
+    /// Wakes the accept loop up and waits for it to end, the stop flag must be set
+    pub(crate) fn stop(self) {
+        (self.wake)();
+        self.thread.join().unwrap_or(());
+    }
+}
@@ -0,0 +1,297 @@
+//! # Matchmaker
+//! The matchmaker decides which game the lobby sends a player to.
+//!
+//! It is called with the username and metadata of the client hello,
+//! and the running games that have free slots.
+//! Games are tagged by the matchmaker when their first player is sent to them, so later players can be matched by tag.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::game::{MatchPhase, PlayerInfo};
+use crate::server::error::lock;
+
+/// What the matchmaker knows about a running game
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSummary {
+    /// Port of the game
+    pub port: i32,
+    /// Players and reservations in the game
+    pub players: usize,
+    /// Slots left
+    pub free_slots: usize,
+    /// Phase of the current match
+    pub phase: MatchPhase,
+    /// Tags given by the matchmaker for the first player of the game
+    pub tags: HashMap<String, String>,
+}
+
+/// Chooses games for players.
+/// # Example
+/// ```
+/// use blizzard_server::game::PlayerInfo;
+/// use blizzard_server::server::matchmaker::{GameSummary, Matchmaker};
+///
+/// // Sends every player to the game with the lowest port
+/// #[derive(Debug)]
+/// struct LowestPort;
+///
+/// impl Matchmaker for LowestPort {
+///     fn choose(&self, _: &PlayerInfo, games: &[GameSummary]) -> Option<usize> {
+///         (0..games.len()).min_by_key(|index| games[*index].port)
+///     }
+/// }
+/// ```
+pub trait Matchmaker: Send + Sync + fmt::Debug {
+    /// Picks one of the games, all of them have free slots.
+    /// Returns the index of the game, or `None` to start a new game if the cap allows it.
+    fn choose(&self, player: &PlayerInfo, games: &[GameSummary]) -> Option<usize>;
+
+    /// Tags of a game the player is the first to be sent to
+    fn tags(&self, _player: &PlayerInfo) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
+    /// Called with the port the player was sent to
+    fn assigned(&self, _player: &PlayerInfo, _port: i32) {}
+
+    /// Called when the config is built, with how long join tickets hold their slot
+    fn reservation_timeout(&self, _timeout: Duration) {}
+}
+
+/// Sends players to the first game with space, the default
+#[derive(Debug, Clone, Copy)]
+pub struct FirstFit;
+
+impl Matchmaker for FirstFit {
+    fn choose(&self, _: &PlayerInfo, games: &[GameSummary]) -> Option<usize> {
+        if games.is_empty() {
+            None
+        } else {
+            Some(0)
+        }
+    }
+}
+
+/// Sends players to the fullest game, so matches start quickly
+#[derive(Debug, Clone, Copy)]
+pub struct FillMostFull;
+
+impl Matchmaker for FillMostFull {
+    fn choose(&self, _: &PlayerInfo, games: &[GameSummary]) -> Option<usize> {
+        fullest(games, |_| true)
+    }
+}
+
+/// Sends players to the emptiest game, so load is spread over the running games
+#[derive(Debug, Clone, Copy)]
+pub struct BalancedLoad;
+
+impl Matchmaker for BalancedLoad {
+    fn choose(&self, _: &PlayerInfo, games: &[GameSummary]) -> Option<usize> {
+        (0..games.len()).min_by_key(|index| games[*index].players)
+    }
+}
+
+/// Groups players by skill, read from a metadata key.
+/// Players whose rating falls in the same bucket play together, missing ratings count as 0.
+#[derive(Debug, Clone)]
+pub struct RatingBuckets {
+    /// Metadata key of the rating
+    pub key: String,
+    /// Width of a bucket
+    pub bucket_size: u32,
+}
+
+impl RatingBuckets {
+    /// Buckets of `bucket_size` on the `rating` metadata key
+    pub fn new(bucket_size: u32) -> RatingBuckets {
+        RatingBuckets {
+            key: "rating".to_string(),
+            bucket_size,
+        }
+    }
+
+    fn bucket(&self, player: &PlayerInfo) -> String {
+        let rating: u32 = player
+            .metadata
+            .get(&self.key)
+            .and_then(|rating| rating.parse().ok())
+            .unwrap_or(0);
+        (rating / self.bucket_size.max(1)).to_string()
+    }
+}
+
+impl Matchmaker for RatingBuckets {
+    fn choose(&self, player: &PlayerInfo, games: &[GameSummary]) -> Option<usize> {
+        let bucket = self.bucket(player);
+        tagged_or_idle(games, |game| game.tags.get("bucket") == Some(&bucket))
+    }
+
+    fn tags(&self, player: &PlayerInfo) -> HashMap<String, String> {
+        tag("bucket", self.bucket(player))
+    }
+}
+
+/// Keeps players of a region together, read from a metadata key.
+/// Players without a region play with each other.
+#[derive(Debug, Clone)]
+pub struct Regions {
+    /// Metadata key of the region
+    pub key: String,
+}
+
+impl Regions {
+    /// Regions on the `region` metadata key
+    pub fn new() -> Regions {
+        Regions {
+            key: "region".to_string(),
+        }
+    }
+
+    fn region(&self, player: &PlayerInfo) -> String {
+        player.metadata.get(&self.key).cloned().unwrap_or_default()
+    }
+}
+
+impl Default for Regions {
+    fn default() -> Regions {
+        Regions::new()
+    }
+}
+
+impl Matchmaker for Regions {
+    fn choose(&self, player: &PlayerInfo, games: &[GameSummary]) -> Option<usize> {
+        let region = self.region(player);
+        tagged_or_idle(games, |game| game.tags.get("region") == Some(&region))
+    }
+
+    fn tags(&self, player: &PlayerInfo) -> HashMap<String, String> {
+        tag("region", self.region(player))
+    }
+}
+
+/// Keeps friends together.
+/// Members of a party send the same party id and the party size in their metadata.
+/// The first member goes to a game with room for the whole party, the rest follow it.
+/// Players without a party are matched like `FillMostFull`.
+#[derive(Debug)]
+pub struct Parties {
+    /// Metadata key of the party id
+    pub key: String,
+    /// Metadata key of the party size
+    pub size_key: String,
+    /// How long the rest of a party is waited for after a member was sent to a game
+    /// `None` waits as long as the reservations of the config.
+    pub timeout: Option<Duration>,
+    // Reservation timeout of the config
+    reservation_timeout: Mutex<Duration>,
+    // Party id to game port, members still to come and when the party is forgotten
+    parties: Mutex<HashMap<String, (i32, usize, Instant)>>,
+}
+
+impl Parties {
+    /// Parties on the `party` and `party_size` metadata keys, waited for as long as reservations
+    pub fn new() -> Parties {
+        Parties {
+            key: "party".to_string(),
+            size_key: "party_size".to_string(),
+            timeout: None,
+            reservation_timeout: Mutex::new(Duration::from_secs(10)),
+            parties: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn party(&self, player: &PlayerInfo) -> Option<(String, usize)> {
+        let party = player.metadata.get(&self.key)?;
+        let size = player
+            .metadata
+            .get(&self.size_key)
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(1);
+        Some((party.clone(), size))
+    }
+}
+
+impl Default for Parties {
+    fn default() -> Parties {
+        Parties::new()
+    }
+}
+
+impl Matchmaker for Parties {
+    fn choose(&self, player: &PlayerInfo, games: &[GameSummary]) -> Option<usize> {
+        let (party, size) = match self.party(player) {
+            Some(party) => party,
+            None => return fullest(games, |_| true),
+        };
+
+        // Follow the members that came first
+        if let Some((port, _, expires)) = lock(&self.parties).get(&party) {
+            if *expires > Instant::now() {
+                if let Some(index) = games.iter().position(|game| game.port == *port) {
+                    return Some(index);
+                }
+            }
+        }
+
+        fullest(games, |game| game.free_slots >= size)
+    }
+
+    fn assigned(&self, player: &PlayerInfo, port: i32) {
+        let (party, size) = match self.party(player) {
+            Some(party) => party,
+            None => return,
+        };
+
+        // Forget parties whose members stopped coming
+        let now = Instant::now();
+        let mut parties = lock(&self.parties);
+        parties.retain(|_, (_, _, expires)| *expires > now);
+
+        let remaining = match parties.get(&party) {
+            Some((_, remaining, _)) => remaining.saturating_sub(1),
+            None => size.saturating_sub(1),
+        };
+
+        // Forget parties once every member arrived
+        if remaining == 0 {
+            parties.remove(&party);
+        } else {
+            let timeout = self
+                .timeout
+                .unwrap_or_else(|| *lock(&self.reservation_timeout));
+            parties.insert(party, (port, remaining, now + timeout));
+        }
+    }
+
+    fn reservation_timeout(&self, timeout: Duration) {
+        *lock(&self.reservation_timeout) = timeout;
+    }
+}
+
+/// Index of the fullest game that passes the filter
+fn fullest<F>(games: &[GameSummary], filter: F) -> Option<usize>
+where
+    F: Fn(&GameSummary) -> bool,
+{
+    (0..games.len())
+        .filter(|index| filter(&games[*index]))
+        .min_by_key(|index| games[*index].free_slots)
+}
+
+/// Index of the fullest game that passes the filter, or of a game without players
+fn tagged_or_idle<F>(games: &[GameSummary], filter: F) -> Option<usize>
+where
+    F: Fn(&GameSummary) -> bool,
+{
+    fullest(games, filter).or_else(|| fullest(games, |game| game.players == 0))
+}
+
+fn tag(key: &str, value: String) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+    tags.insert(key.to_string(), value);
+    tags
+}
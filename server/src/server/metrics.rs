@@ -0,0 +1,320 @@
+//! # Metrics
+//! Counters of the lobby and the players, and the load of every game.
+//!
+//! The server counts lobby connections, messages and bytes received, packets and bytes sent
+//! and messages dropped before reaching the games. Games add their players and update times.
+//! With the `metrics_address` of the config, they are served over HTTP in the Prometheus text format.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use blizzard_engine::core::network_application::UpdateTimes;
+
+use crate::server::config::ServerConfig;
+use crate::server::error::{lock, ErrorSource, ServerError};
+use crate::server::handle::Service;
+use crate::server::pool::Pool;
+
+/// Max bytes of a scrape request
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Time a scraper has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Why a message of a player never reached its game
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dropped {
+    /// Over the rate limits, with the drop flood policy
+    Flood,
+    /// Rejected by the validator
+    Rejected,
+    /// Could not be parsed, with a malformed input policy that keeps the player
+    Malformed,
+}
+
+/// Totals since the server started.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counters {
+    pub lobby_connections: u64,
+    /// Clients the lobby turned away
+    pub lobby_rejections: u64,
+    /// Lines read from players
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    /// Packets queued for players
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub dropped_flood: u64,
+    pub dropped_rejected: u64,
+    pub dropped_malformed: u64,
+    /// States skipped for players with a full send queue
+    pub skipped_states: u64,
+}
+
+/// Load of a running game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameMetrics {
+    pub port: i32,
+    /// Players, including disconnected ones within the grace period
+    pub players: usize,
+    /// Update times of the current match
+    pub updates: UpdateTimes,
+}
+
+/// Shared counters of the server.
+/// Clones share the same counters.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl Metrics {
+    /// Creates counters starting at zero
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Totals counted so far
+    pub fn counters(&self) -> Counters {
+        *lock(&self.counters)
+    }
+
+    pub(crate) fn lobby_connection(&self) {
+        lock(&self.counters).lobby_connections += 1;
+    }
+
+    pub(crate) fn lobby_rejection(&self) {
+        lock(&self.counters).lobby_rejections += 1;
+    }
+
+    /// Counts a line read from a player
+    pub(crate) fn received(&self, bytes: usize) {
+        let mut counters = lock(&self.counters);
+        counters.messages_received += 1;
+        counters.bytes_received += bytes as u64;
+    }
+
+    /// Counts packets queued for players
+    pub(crate) fn sent(&self, packets: usize, bytes: usize) {
+        let mut counters = lock(&self.counters);
+        counters.packets_sent += packets as u64;
+        counters.bytes_sent += (packets * bytes) as u64;
+    }
+
+    pub(crate) fn dropped(&self, reason: Dropped) {
+        let mut counters = lock(&self.counters);
+        match reason {
+            Dropped::Flood => counters.dropped_flood += 1,
+            Dropped::Rejected => counters.dropped_rejected += 1,
+            Dropped::Malformed => counters.dropped_malformed += 1,
+        }
+    }
+
+    pub(crate) fn skipped_state(&self) {
+        lock(&self.counters).skipped_states += 1;
+    }
+}
+
+/// Writes the counters and the games in the Prometheus text format
+pub fn render(counters: &Counters, games: &[GameMetrics]) -> String {
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, values: Vec<(String, String)>| {
+        writeln!(text, "# HELP {} {}", name, help).unwrap_or(());
+        writeln!(text, "# TYPE {} {}", name, kind).unwrap_or(());
+        for (labels, value) in values {
+            writeln!(text, "{}{} {}", name, labels, value).unwrap_or(());
+        }
+    };
+    let total = |value: u64| vec![(String::new(), value.to_string())];
+    let per_game = |value: &dyn Fn(&GameMetrics) -> String| {
+        games
+            .iter()
+            .map(|game| (format!("{{port=\"{}\"}}", game.port), value(game)))
+            .collect()
+    };
+
+    metric(
+        "blizzard_lobby_connections_total",
+        "counter",
+        "Connections accepted by the lobby",
+        total(counters.lobby_connections),
+    );
+    metric(
+        "blizzard_lobby_rejections_total",
+        "counter",
+        "Clients the lobby turned away",
+        total(counters.lobby_rejections),
+    );
+    metric(
+        "blizzard_messages_received_total",
+        "counter",
+        "Messages read from players",
+        total(counters.messages_received),
+    );
+    metric(
+        "blizzard_received_bytes_total",
+        "counter",
+        "Bytes read from players",
+        total(counters.bytes_received),
+    );
+    metric(
+        "blizzard_packets_sent_total",
+        "counter",
+        "Packets queued for players",
+        total(counters.packets_sent),
+    );
+    metric(
+        "blizzard_sent_bytes_total",
+        "counter",
+        "Bytes queued for players",
+        total(counters.bytes_sent),
+    );
+    metric(
+        "blizzard_messages_dropped_total",
+        "counter",
+        "Messages of players that never reached their game",
+        vec![
+            (
+                "{reason=\"flood\"}".into(),
+                counters.dropped_flood.to_string(),
+            ),
+            (
+                "{reason=\"rejected\"}".into(),
+                counters.dropped_rejected.to_string(),
+            ),
+            (
+                "{reason=\"malformed\"}".into(),
+                counters.dropped_malformed.to_string(),
+            ),
+        ],
+    );
+    metric(
+        "blizzard_skipped_states_total",
+        "counter",
+        "States skipped for players with a full send queue",
+        total(counters.skipped_states),
+    );
+    metric(
+        "blizzard_games",
+        "gauge",
+        "Running games",
+        total(games.len() as u64),
+    );
+    metric(
+        "blizzard_game_players",
+        "gauge",
+        "Players of each game",
+        per_game(&|game| game.players.to_string()),
+    );
+    metric(
+        "blizzard_game_updates_total",
+        "counter",
+        "Updates played by the current match of each game",
+        per_game(&|game| game.updates.count.to_string()),
+    );
+    metric(
+        "blizzard_game_update_seconds_total",
+        "counter",
+        "Time spent in the updates of the current match of each game",
+        per_game(&|game| game.updates.total.as_secs_f64().to_string()),
+    );
+    metric(
+        "blizzard_game_update_seconds",
+        "gauge",
+        "Time of the last update of each game",
+        per_game(&|game| game.updates.last.as_secs_f64().to_string()),
+    );
+    metric(
+        "blizzard_game_update_max_seconds",
+        "gauge",
+        "Time of the slowest update of the current match of each game",
+        per_game(&|game| game.updates.max.as_secs_f64().to_string()),
+    );
+    text
+}
+
+/// Serves the metrics over HTTP in the background, on any path, until the stop flag is set
+/// Fails if the address can't be bound.
+pub(crate) fn serve(
+    address: SocketAddr,
+    pool: Arc<Pool>,
+    config: Arc<ServerConfig>,
+    stopping: Arc<Mutex<bool>>,
+) -> io::Result<Service> {
+    let listener = TcpListener::bind(address)?;
+    let local_address = listener.local_addr()?;
+    println!("Serving metrics on http://{}/metrics", local_address);
+
+    // Every interface can't be connected to, loopback is one of them
+    let mut wake_address = local_address;
+    if wake_address.ip().is_unspecified() {
+        let loopback = match wake_address.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        };
+        wake_address.set_ip(loopback);
+    }
+
+    // Scrapes are rare and quick, one thread answers them all
+    let builder = thread::Builder::new().name("Metrics".to_string());
+    let thread = builder
+        .spawn(move || {
+            for stream in listener.incoming() {
+                // The server wakes the endpoint up with a connection, to stop serving
+                if *lock(&stopping) {
+                    break;
+                }
+                stream
+                    .map_err(ServerError::Io)
+                    .and_then(|stream| answer(stream, &pool))
+                    .unwrap_or_else(|error| config.report_error(&error, ErrorSource::Metrics));
+            }
+        })
+        .expect("Could not create thread");
+
+    Ok(Service::new(thread, move || {
+        TcpStream::connect(wake_address).map(|_| ()).unwrap_or(());
+    }))
+}
+
+/// Reads the request of a scraper and answers with the metrics
+/// The whole request must arrive within the timeout, slow scrapers can't hold the endpoint.
+fn answer(mut stream: TcpStream, pool: &Pool) -> Result<(), ServerError> {
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = vec![];
+    let mut chunk = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) {
+            return Err(ServerError::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Scrape request took too long",
+            )));
+        }
+        stream.set_read_timeout(Some(left))?;
+        let read = stream.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..read]);
+    }
+
+    let response = if request.starts_with(b"GET ") {
+        let body = render(&pool.metrics().counters(), &pool.game_metrics());
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string()
+    };
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
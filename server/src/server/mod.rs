@@ -1,23 +1,87 @@
 //! # Server
-//! The server is in charge of creating the game pool.
-//!
-//! and giving a client connection the port of an emtpy game that it can connect to.
+//! The server is in charge of creating the game pool,
+//! and giving a checked client connection the port of a game that it can connect to.
+//! It runs in the background over any transport, and its `ServerHandle` shuts it down gracefully.
 
+pub mod admin;
+pub mod auth;
+pub mod bans;
+pub mod config;
 mod connector;
 pub mod controller;
+pub mod error;
+pub mod handle;
+pub mod matchmaker;
+pub mod metrics;
 mod pool;
+pub mod rate_limit;
+pub mod transport;
+pub mod validation;
 
-use std::io::{Error, Read, Write};
-use std::net::{TcpListener, TcpStream};
-use std::sync::mpsc::Receiver;
+use std::io;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use blizzard_engine::game::Game;
 
+use crate::game::{InputHandler, PlayerInfo};
+use crate::protocol::{
+    parse_packet, write_packet, Hello, JoinTicket, LobbyPacket, RejectReason, RoomRequest,
+    PROTOCOL_VERSION,
+};
+
+pub use bans::{BanEntry, BanList};
+pub use config::ServerConfig;
+pub use error::ServerError;
+use error::{lock, ErrorSource};
+pub use handle::ServerHandle;
+use handle::Service;
+pub use matchmaker::Matchmaker;
+use metrics::Metrics;
 use pool::Pool;
+#[cfg(feature = "async-io")]
+use transport::AsyncTcpTransport;
+#[cfg(any(feature = "tls", not(feature = "async-io")))]
+use transport::TcpTransport;
+#[cfg(feature = "tls")]
+use transport::TlsTransport;
+use transport::{Connection, Listener, Transport};
+use validation::{CheckFactory, Moderation, Validator};
+
+/// Max bytes of a client hello
+const MAX_HELLO_SIZE: usize = 4096;
+
+/// Wait after a failed accept of the lobby
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Lobby connection being served, frees its place when dropped
+struct Handshake {
+    active: Arc<Mutex<usize>>,
+}
+
+impl Handshake {
+    /// Takes a place, `None` if the lobby already serves `max` connections
+    fn begin(active: &Arc<Mutex<usize>>, max: usize) -> Option<Handshake> {
+        let mut count = lock(active);
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+        Some(Handshake {
+            active: Arc::clone(active),
+        })
+    }
+}
+
+impl Drop for Handshake {
+    fn drop(&mut self) {
+        *lock(&self.active) -= 1;
+    }
+}
 
 /// Server struct for creating servers.
 /// # Example
@@ -26,77 +90,386 @@ use pool::Pool;
 pub struct Server {}
 
 impl Server {
-    /// Start a new server
-    pub fn new<T: Game<K, I>, K, I, M>(
-        port: i32,
-        max_games: i32,
-        max_players: i32,
+    /// Start a new TCP server, bound to the configured address
+    /// Players share one event loop thread with the `async-io` feature.
+    /// TLS connections are read on threads of their own, even with the `async-io` feature.
+    /// Returns once the ports are open, the handle shuts the server down.
+    pub fn start<T: Game<K, I>, K, I, M>(
+        config: ServerConfig,
+        game: T,
+        shared_state: K,
+        input: I,
+        handle_input: &'static InputHandler<M, I>,
+    ) -> ServerHandle
+    where
+        T: Clone + Send + 'static,
+        K: Clone + Send + Serialize + 'static,
+        I: Send + Copy + 'static,
+        M: Send + DeserializeOwned + 'static,
+    {
+        #[cfg(feature = "tls")]
+        {
+            if let Some(files) = &config.tls {
+                let tcp = TcpTransport::bound_to(config.bind_address);
+                let transport =
+                    TlsTransport::new(tcp, files).expect("Could not load the TLS certificate");
+                return Server::with_transport(
+                    transport,
+                    config,
+                    game,
+                    shared_state,
+                    input,
+                    handle_input,
+                );
+            }
+        }
+
+        #[cfg(feature = "async-io")]
+        let transport = AsyncTcpTransport::bound_to(config.bind_address)
+            .expect("Could not start the event loop");
+        #[cfg(not(feature = "async-io"))]
+        let transport = TcpTransport::bound_to(config.bind_address);
+
+        Server::with_transport(transport, config, game, shared_state, input, handle_input)
+    }
+
+    /// Start a new server over any transport, for example in-process channels
+    pub fn with_transport<X: Transport, T: Game<K, I>, K, I, M>(
+        transport: X,
+        config: ServerConfig,
+        game: T,
+        shared_state: K,
+        input: I,
+        handle_input: &'static InputHandler<M, I>,
+    ) -> ServerHandle
+    where
+        T: Clone + Send + 'static,
+        K: Clone + Send + Serialize + 'static,
+        I: Send + Copy + 'static,
+        M: Send + DeserializeOwned + 'static,
+    {
+        Server::run(
+            transport,
+            config,
+            game,
+            shared_state,
+            input,
+            handle_input,
+            None,
+        )
+    }
+
+    /// Start a new server over any transport, where the validator checks every message of the players
+    /// Players whose suspicion score reaches `kick_suspicion` or `ban_suspicion` are removed.
+    pub fn with_validator<X: Transport, T: Game<K, I>, K, I, M, V>(
+        transport: X,
+        config: ServerConfig,
+        game: T,
+        shared_state: K,
+        input: I,
+        handle_input: &'static InputHandler<M, I>,
+        validator: V,
+    ) -> ServerHandle
+    where
+        T: Clone + Send + 'static,
+        K: Clone + Send + Serialize + 'static,
+        I: Send + Copy + 'static,
+        M: Clone + Send + DeserializeOwned + 'static,
+        V: Validator<M>,
+    {
+        let check = validation::check_factory(validator, config.history_size);
+        Server::run(
+            transport,
+            config,
+            game,
+            shared_state,
+            input,
+            handle_input,
+            Some(check),
+        )
+    }
+
+    /// Starts the games and runs the lobby in the background
+    fn run<X: Transport, T: Game<K, I>, K, I, M>(
+        transport: X,
+        config: ServerConfig,
         game: T,
         shared_state: K,
         input: I,
-        handle_input: &'static (dyn Fn(Receiver<(M, usize)>, Arc<Mutex<I>>) -> I + Sync),
-        send_data_rate: i32,
-        game_update_rate: i32,
-    ) where
+        handle_input: &'static InputHandler<M, I>,
+        check: Option<Arc<CheckFactory<M>>>,
+    ) -> ServerHandle
+    where
         T: Clone + Send + 'static,
         K: Clone + Send + Serialize + 'static,
-        I: Send + Copy,
-        M: Send + DeserializeOwned,
+        I: Send + Copy + 'static,
+        M: Send + DeserializeOwned + 'static,
     {
+        let config = Arc::new(config);
+
+        // Bans can be added while the server runs, games ban cheaters
+        let bans = BanList::new(&config.banned);
+        let moderation = Moderation {
+            check,
+            bans: bans.clone(),
+        };
+
         // Create game pool
         let game_pool = Pool::new(
-            max_games,
-            max_players,
+            transport.clone(),
+            Arc::clone(&config),
             game,
             shared_state,
             input,
             handle_input,
-            send_data_rate,
-            game_update_rate,
+            moderation,
         );
+        let metrics = game_pool.metrics().clone();
 
-        // Format TCP to local machine
-        let tcp = format!("0.0.0.0:{}", port);
+        // Listeners stop accepting once it is set
+        let stopping = Arc::new(Mutex::new(false));
+        let mut services = vec![];
 
-        // Create TCP listener
-        let listener = TcpListener::bind(tcp).expect("Could not bind");
+        // Operators control the games while the lobby runs
+        if let Some(address) = &config.admin {
+            let admin = admin::serve(
+                address,
+                Arc::clone(&game_pool),
+                bans.clone(),
+                Arc::clone(&config),
+                Arc::clone(&stopping),
+            )
+            .expect("Could not bind the admin interface");
+            services.push(admin);
+        }
+        if let Some(address) = config.metrics_address {
+            let endpoint = metrics::serve(
+                address,
+                Arc::clone(&game_pool),
+                Arc::clone(&config),
+                Arc::clone(&stopping),
+            )
+            .expect("Could not bind the metrics endpoint");
+            services.push(endpoint);
+        }
+
+        // Open lobby port
+        let listener = transport.bind(config.lobby_port).expect("Could not bind");
+        let lobby_port = listener.port();
+        let lobby_pool = Arc::clone(&game_pool);
+        let lobby_stopping = Arc::clone(&stopping);
 
         // For every new connection
-        for stream in listener.incoming() {
-            match stream {
-                Err(e) => {
-                    eprintln!("failed: {}", e)
-                }
-                Ok(stream) => {
-                    // Handle connection and find a game
-                    if let Some(game_wrapper_port) = game_pool.find_empty_game() {
-                        Server::handle_client_connection(stream, game_wrapper_port)
-                            .unwrap_or_else(|error| eprintln!("{:?}", error));
-                    } else {
-                        Server::handle_client_connection(stream, 0)
-                            .unwrap_or_else(|error| eprintln!("{:?}", error));
+        let handshakes = Arc::new(Mutex::new(0));
+        let builder = thread::Builder::new().name("Lobby".to_string());
+        let lobby = builder
+            .spawn(move || loop {
+                match listener.accept() {
+                    // Running out of file descriptors fails every accept, give connections time to close
+                    Err(e) => {
+                        config.report_error(&ServerError::Io(e), ErrorSource::Lobby);
+                        thread::sleep(ACCEPT_BACKOFF);
+                    }
+                    // The handle wakes the lobby up with a connection, to stop accepting
+                    Ok(_) if *lock(&lobby_stopping) => break,
+                    Ok(stream) => {
+                        metrics.lobby_connection();
+                        let handshake =
+                            match Handshake::begin(&handshakes, config.max_lobby_connections) {
+                                Some(handshake) => handshake,
+                                None => {
+                                    metrics.lobby_rejection();
+                                    let busy = ServerError::LobbyBusy(config.max_lobby_connections);
+                                    config.report_error(&busy, ErrorSource::Lobby);
+                                    continue;
+                                }
+                            };
+                        let game_pool = Arc::clone(&lobby_pool);
+                        let bans = bans.clone();
+                        let thread_config = Arc::clone(&config);
+                        let metrics = metrics.clone();
+
+                        // Handle connection and find a game, slow clients only hold their own thread
+                        // A thread that can't start drops the connection and frees its place
+                        let builder = thread::Builder::new().name("Lobby-connection".to_string());
+                        let spawned = builder.spawn(move || {
+                            let config = thread_config;
+                            let handled = Server::handle_client_connection(
+                                stream, &game_pool, &bans, &config, &metrics,
+                            );
+                            handled.unwrap_or_else(|error| {
+                                config.report_error(&error, ErrorSource::Lobby)
+                            });
+                            drop(handshake);
+                        });
+                        if let Err(error) = spawned {
+                            config.report_error(&ServerError::Io(error), ErrorSource::Lobby);
+                        }
                     }
                 }
+            })
+            .expect("Could not create thread");
+        let server_transport = transport.clone();
+        services.insert(
+            0,
+            Service::new(lobby, move || {
+                transport.connect(lobby_port).map(|_| ()).unwrap_or(());
+            }),
+        );
+
+        // The lobby stops first, so no player is sent to a closing game
+        // The transport stops last, once the connections of the players are closed
+        ServerHandle::new(move |reason| {
+            *lock(&stopping) = true;
+            for service in services {
+                service.stop();
             }
-        }
+            game_pool.shutdown(reason);
+            server_transport.shutdown();
+        })
     }
 
-    /// Handles client connections if a game was found.
+    /// Handles the handshake of a client with the lobby.
     ///
-    /// Sends the client the port of the game.
+    /// Reads the client hello and answers with a join ticket for an empty game, or the rejection reason.
     ///
-    /// Client should then connect to the new port.
-    fn handle_client_connection(mut stream: TcpStream, port: i32) -> Result<(), Error> {
-        println!("Incoming connection from: {}", stream.peer_addr()?);
-        let mut buf = [0; 512];
+    /// Client should then connect to the game and join with the ticket.
+    fn handle_client_connection<C: Connection>(
+        mut stream: C,
+        game_pool: &Pool,
+        bans: &BanList,
+        config: &ServerConfig,
+        metrics: &Metrics,
+    ) -> Result<(), ServerError> {
+        let peer = stream.peer()?;
+        println!("Incoming connection from: {}", peer);
+
+        // Idle clients are dropped, instead of holding a lobby thread forever
+        let hello = Server::read_hello(&mut stream, config.lobby_timeout)
+            .and_then(|buffer| parse_packet::<Hello>(&buffer));
+
+        let hello = match hello {
+            Ok(hello) => hello,
+            Err(ServerError::Io(error)) => {
+                stream.close().unwrap_or(());
+                return Err(ServerError::Io(error));
+            }
+            Err(error) => {
+                metrics.lobby_rejection();
+                let rejected = LobbyPacket::Rejected(RejectReason::Malformed);
+                write_packet(&mut stream, &rejected).unwrap_or(());
+                stream.close().unwrap_or(());
+                return Err(error);
+            }
+        };
+
+        let reply = Server::welcome(hello, &peer, game_pool, bans, config);
+        if let LobbyPacket::Rejected(reason) = &reply {
+            metrics.lobby_rejection();
+            println!("Rejected {}: {:?}", peer, reason);
+        }
+
+        let sent = write_packet(&mut stream, &reply);
+        stream.close().unwrap_or(());
+        sent
+    }
+
+    /// Reads the hello line, the whole line must arrive before the timeout
+    /// A client trickling bytes can't stretch the handshake past it.
+    /// The lobby answers one hello per connection, bytes sent after its newline are ignored.
+    fn read_hello<C: Connection>(
+        stream: &mut C,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, ServerError> {
+        let deadline = Instant::now() + timeout;
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut chunk = [0; 512];
+
         loop {
-            let bytes_read = stream.read(&mut buf)?;
+            if let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                buffer.truncate(end + 1);
+            }
+            if buffer.len() > MAX_HELLO_SIZE {
+                return Err(ServerError::MessageTooLarge(buffer.len()));
+            }
+            if buffer.ends_with(b"\n") {
+                break;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                let timed_out =
+                    io::Error::new(io::ErrorKind::TimedOut, "Lobby handshake timed out");
+                return Err(ServerError::Io(timed_out));
+            }
+            stream.set_read_timeout(Some(deadline - now))?;
+
+            let bytes_read = stream.read(&mut chunk)?;
             if bytes_read == 0 {
-                return Ok(());
+                break;
             }
-            let port: String = format!("{}\n", port);
-            stream.write(port.as_bytes()).unwrap();
-            stream.flush().unwrap();
+            buffer.extend_from_slice(&chunk[..bytes_read]);
+        }
+
+        Ok(buffer)
+    }
+
+    /// Decides where a client goes
+    fn welcome(
+        hello: Hello,
+        peer: &str,
+        game_pool: &Pool,
+        bans: &BanList,
+        config: &ServerConfig,
+    ) -> LobbyPacket {
+        if hello.protocol_version != PROTOCOL_VERSION {
+            return LobbyPacket::Rejected(RejectReason::VersionMismatch {
+                server_version: PROTOCOL_VERSION,
+            });
+        }
+        if bans.is_banned(&hello.username, None, peer) {
+            return LobbyPacket::Rejected(RejectReason::Banned);
+        }
+
+        // Accounts are banned too, whatever username they pick
+        let identity = match &config.authenticator {
+            None => None,
+            Some(authenticator) => match authenticator.authenticate(&hello, peer) {
+                None => return LobbyPacket::Rejected(RejectReason::Unauthorized),
+                Some(identity) => {
+                    if bans.is_banned(&hello.username, identity.account_id.as_deref(), peer) {
+                        return LobbyPacket::Rejected(RejectReason::Banned);
+                    }
+                    Some(identity)
+                }
+            },
+        };
+
+        let info = PlayerInfo {
+            username: hello.username,
+            metadata: hello.metadata,
+            identity,
+        };
+        let issued = match hello.room {
+            None => game_pool
+                .issue_ticket(info)
+                .map(|(port, id)| (port, id, None)),
+            Some(RoomRequest::Create { password }) => game_pool
+                .create_room(info, password)
+                .map(|(port, id, code)| (port, id, Some(code))),
+            Some(RoomRequest::Join { code, password }) => game_pool
+                .join_room(info, &code, password.as_deref())
+                .map(|(port, id)| (port, id, Some(code))),
+        };
+
+        match issued {
+            Ok((port, id, room_code)) => LobbyPacket::Welcome(JoinTicket {
+                id,
+                host: config.public_host.clone(),
+                port,
+                room_code,
+            }),
+            Err(reason) => LobbyPacket::Rejected(reason),
         }
     }
 }
@@ -1,8 +1,19 @@
 //! # Pool
 //! The pool is in charge of finding emtpy games to connect the client.
+//!
+//! The configured matchmaker picks the game of every player.
+//! Games start when the matchmaker finds no game for a player, up to `max_games`,
+//! and games that stay empty for the idle timeout are shut down, down to `min_games`.
+//! Private rooms are games started for a host, the matchmaker never picks them.
+//! The admin interface lists the games of the pool and shuts them down.
+//! When the server shuts down, the pool lets running matches finish and closes every game.
 
-use std::sync::mpsc::Receiver;
-use std::sync::{Arc, Mutex};
+use std::io;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
 
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -10,7 +21,28 @@ use serde::Serialize;
 use blizzard_engine::core::network_application::create_app;
 use blizzard_engine::game::Game;
 
-use crate::server::connector::Connector;
+use crate::game::{InputHandler, PlayerInfo};
+use crate::protocol::RejectReason;
+use crate::server::admin::GameStatus;
+use crate::server::config::ServerConfig;
+use crate::server::connector::{AppFactory, Connector};
+use crate::server::controller::Controller;
+use crate::server::error::{lock, ErrorSource, ServerError};
+use crate::server::metrics::{GameMetrics, Metrics};
+use crate::server::transport::Transport;
+use crate::server::validation::Moderation;
+
+/// Characters of room codes, without look-alikes
+const ROOM_CODE_CHARACTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Length of room codes
+const ROOM_CODE_LENGTH: usize = 6;
+
+/// Time between checks while running matches finish on shutdown
+const SHUTDOWN_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Starts a game on a port, `0` lets the transport choose
+type GameStarter = dyn Fn(i32) -> io::Result<Arc<Mutex<Connector>>> + Send + Sync;
 
 /// A pool of game connectors
 /// Pool finds empty games and returns to the client empty game port.
@@ -18,65 +50,466 @@ use crate::server::connector::Connector;
 /// For a working example, please see official github repository, in the example lib.
 ///
 pub struct Pool {
-    game_connectors: Vec<Arc<Mutex<Connector>>>,
+    game_connectors: Mutex<Vec<Arc<Mutex<Connector>>>>,
+    start_game: Box<GameStarter>,
+    config: Arc<ServerConfig>,
+    metrics: Metrics,
+    // Set under the pool lock, no game starts after it
+    shutting_down: Mutex<bool>,
+    // Retired games still closing, their ports stay taken until closed
+    retiring: Mutex<Vec<Arc<Mutex<Connector>>>>,
+    // Wakes the reaper up on shutdown
+    reaper_wake: Condvar,
+    reaper: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl Pool {
-    /// Creates a new game.
+    /// Creates a new game pool, with `min_games` games running.
+    /// Every match is a clone of the given game and shared state.
     /// # Type definitions:
     /// * T: Game type
     /// * K: Shared state type (to share state to client)
     /// * I: Input type (to manipulate user input inside app)
     /// * M: Message type (sent from server to app)
-    pub fn new<T: Game<K, I>, K, I, M>(
-        max_games: i32,
-        max_players: i32,
+    pub fn new<X: Transport, T: Game<K, I>, K, I, M>(
+        transport: X,
+        config: Arc<ServerConfig>,
         game: T,
         shared_state: K,
         input: I,
-        handle_input: &'static (dyn Fn(Receiver<(M, usize)>, Arc<Mutex<I>>) -> I + Sync),
-        send_data_rate: i32,
-        game_update_rate: i32,
-    ) -> Pool
+        handle_input: &'static InputHandler<M, I>,
+        moderation: Moderation<M>,
+    ) -> Arc<Pool>
     where
         T: Clone + Send + 'static,
         K: Clone + Send + Serialize + 'static,
-        I: Send + Copy,
-        M: Send + DeserializeOwned,
+        I: Send + Copy + 'static,
+        M: Send + DeserializeOwned + 'static,
     {
-        // Game wrapper vec
-        let mut game_connectors = vec![];
+        // Prototype every match is cloned from
+        let prototype = Mutex::new((game, shared_state, input));
+        let update_rate = config.game_update_rate;
+        let new_app: Arc<AppFactory<T, K, I>> = Arc::new(move || {
+            let prototype = lock(&prototype);
+            create_app(
+                prototype.0.clone(),
+                prototype.1.clone(),
+                prototype.2,
+                update_rate,
+            )
+        });
 
-        // Loop max games
-        for i in 0..max_games {
-            // Create port id
-            let port = 7000 + i;
-
-            // Create a new app for each port specified
-            let app = create_app(game.clone(), shared_state.clone(), input, game_update_rate);
-
-            // Push new game connector
-            game_connectors.push(Connector::new(
+        let metrics = Metrics::new();
+        let game_metrics = metrics.clone();
+        let game_config = Arc::clone(&config);
+        let start_game = move |port: i32| {
+            Connector::new(
+                &transport,
                 port,
-                max_players,
-                app,
+                Arc::clone(&game_config),
+                Arc::clone(&new_app),
                 handle_input,
-                send_data_rate,
-            ));
+                moderation.clone(),
+                game_metrics.clone(),
+            )
+        };
+
+        let pool = Arc::new(Pool {
+            game_connectors: Mutex::new(vec![]),
+            start_game: Box::new(start_game),
+            config,
+            metrics,
+            shutting_down: Mutex::new(false),
+            retiring: Mutex::new(vec![]),
+            reaper_wake: Condvar::new(),
+            reaper: Mutex::new(None),
+        });
+
+        // Games that always run
+        {
+            let mut game_connectors = lock(&pool.game_connectors);
+            for _ in 0..pool.config.min_games {
+                let connector = pool
+                    .start_next_game(&game_connectors)
+                    .expect("Could not bind");
+                game_connectors.push(connector);
+            }
         }
 
+        // Retire idle games in the background
+        let reaper = Arc::clone(&pool);
+        let builder = thread::Builder::new().name("Pool-reaper".to_string());
+        let reaper_thread = builder
+            .spawn(move || {
+                let interval = (reaper.config.idle_timeout / 2).max(Duration::from_millis(10));
+                loop {
+                    let (shutting_down, _) = reaper
+                        .reaper_wake
+                        .wait_timeout_while(lock(&reaper.shutting_down), interval, |stop| !*stop)
+                        .unwrap_or_else(PoisonError::into_inner);
+                    if *shutting_down {
+                        return;
+                    }
+                    drop(shutting_down);
+                    reaper.retire_idle_games();
+                }
+            })
+            .expect("Could not create thread");
+        *lock(&pool.reaper) = Some(reaper_thread);
+
         // Return game pool
-        Pool { game_connectors }
+        pool
+    }
+
+    /// Asks the matchmaker for a game, reserves a slot and returns its port and a join ticket for the player
+    /// The choice and the reservation happen under the pool lock, so games are never overbooked.
+    /// Starts a new game if the matchmaker picks none and the cap allows it.
+    pub fn issue_ticket(&self, info: PlayerInfo) -> Result<(i32, String), RejectReason> {
+        let matchmaker = &self.config.matchmaker;
+        let mut game_connectors = lock(&self.game_connectors);
+        if *lock(&self.shutting_down) {
+            return Err(RejectReason::ShuttingDown);
+        }
+
+        // Games with free slots, and where they are in the pool
+        let mut indexes = vec![];
+        let mut games = vec![];
+        for (index, game_connector) in game_connectors.iter().enumerate() {
+            let game_connector = lock(game_connector);
+            if game_connector.is_private() {
+                continue;
+            }
+            let summary = game_connector.summary();
+            if summary.free_slots > 0 {
+                indexes.push(index);
+                games.push(summary);
+            }
+        }
+
+        let game_connector = match matchmaker.choose(&info, &games) {
+            Some(chosen) => Arc::clone(&game_connectors[indexes[chosen]]),
+            // Every game is full
+            None if game_connectors.len() >= self.config.max_games as usize => {
+                return Err(RejectReason::Full)
+            }
+            None => match self.start_next_game(&game_connectors) {
+                Ok(game_connector) => {
+                    game_connectors.push(Arc::clone(&game_connector));
+                    game_connector
+                }
+                Err(error) => {
+                    self.config
+                        .report_error(&ServerError::Io(error), ErrorSource::Lobby);
+                    return Err(RejectReason::Full);
+                }
+            },
+        };
+
+        // The first player of a game decides its tags
+        let mut game_connector = lock(&game_connector);
+        if game_connector.summary().players == 0 {
+            game_connector.set_tags(matchmaker.tags(&info));
+        }
+        let port = game_connector.port;
+        let ticket = game_connector
+            .issue_ticket(info.clone())
+            .ok_or(RejectReason::Full)?;
+        matchmaker.assigned(&info, port);
+        Ok((port, ticket))
     }
 
-    /// Finds an empty game and returns the port if there is an empty game
-    pub fn find_empty_game(&self) -> Option<i32> {
-        for game_connector in &self.game_connectors {
-            let game_connector = game_connector.lock().unwrap();
-            if game_connector.is_empty() {
-                return Some(game_connector.port);
+    /// Starts a private room, returns its port, the ticket of the host and the join code
+    pub fn create_room(
+        &self,
+        info: PlayerInfo,
+        password: Option<String>,
+    ) -> Result<(i32, String, String), RejectReason> {
+        let mut game_connectors = lock(&self.game_connectors);
+        if *lock(&self.shutting_down) {
+            return Err(RejectReason::ShuttingDown);
+        }
+        if game_connectors.len() >= self.config.max_games as usize {
+            return Err(RejectReason::Full);
+        }
+
+        // Codes are short, make sure no open room has it
+        let mut code = room_code();
+        while game_connectors
+            .iter()
+            .any(|c| lock(c).room_code() == Some(code.as_str()))
+        {
+            code = room_code();
+        }
+
+        let game_connector = match self.start_next_game(&game_connectors) {
+            Ok(game_connector) => game_connector,
+            Err(error) => {
+                self.config
+                    .report_error(&ServerError::Io(error), ErrorSource::Lobby);
+                return Err(RejectReason::Full);
             }
+        };
+        game_connectors.push(Arc::clone(&game_connector));
+
+        let mut game_connector = lock(&game_connector);
+        let ticket = game_connector
+            .open_room(code.clone(), password, info)
+            .ok_or(RejectReason::Full)?;
+        Ok((game_connector.port, ticket, code))
+    }
+
+    /// Reserves a slot in the private room of the code, returns its port and a join ticket
+    pub fn join_room(
+        &self,
+        info: PlayerInfo,
+        code: &str,
+        password: Option<&str>,
+    ) -> Result<(i32, String), RejectReason> {
+        let game_connectors = lock(&self.game_connectors);
+        if *lock(&self.shutting_down) {
+            return Err(RejectReason::ShuttingDown);
         }
-        None
+        let game_connector = game_connectors
+            .iter()
+            .find(|c| lock(c).room_code() == Some(code))
+            .ok_or(RejectReason::RoomNotFound)?;
+
+        let mut game_connector = lock(game_connector);
+        if !game_connector.check_password(password) {
+            return Err(RejectReason::WrongPassword);
+        }
+        let ticket = game_connector
+            .issue_ticket(info)
+            .ok_or(RejectReason::Full)?;
+        Ok((game_connector.port, ticket))
     }
+
+    /// Status of every running game
+    pub fn games(&self) -> Vec<GameStatus> {
+        // Controllers lock their connector, so the pool is not held while locking them
+        let game_connectors = lock(&self.game_connectors).clone();
+        game_connectors
+            .iter()
+            .map(|game_connector| {
+                let (summary, room_code, controller) = {
+                    let game_connector = lock(game_connector);
+                    (
+                        game_connector.summary(),
+                        game_connector.room_code().map(|code| code.to_string()),
+                        game_connector.controller(),
+                    )
+                };
+                GameStatus {
+                    port: summary.port,
+                    phase: summary.phase,
+                    players: summary.players,
+                    free_slots: summary.free_slots,
+                    room_code,
+                    tags: summary.tags,
+                    paused: matches!(controller, Some(c) if lock(&c).is_paused()),
+                }
+            })
+            .collect()
+    }
+
+    /// Counters of the lobby and of the players of every game
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Players and update times of every running game
+    pub fn game_metrics(&self) -> Vec<GameMetrics> {
+        let game_connectors = lock(&self.game_connectors).clone();
+        game_connectors
+            .iter()
+            .map(|game_connector| {
+                let (port, players, controller) = {
+                    let game_connector = lock(game_connector);
+                    (
+                        game_connector.port,
+                        game_connector.player_count(),
+                        game_connector.controller(),
+                    )
+                };
+                GameMetrics {
+                    port,
+                    players,
+                    updates: controller
+                        .map(|controller| lock(&controller).update_times())
+                        .unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Controller of the game in a port, `None` if no game runs there
+    pub fn controller(&self, port: i32) -> Option<Arc<Mutex<Controller>>> {
+        let game_connector = lock(&self.game_connectors)
+            .iter()
+            .find(|c| lock(c).port == port)
+            .map(Arc::clone)?;
+        let controller = lock(&game_connector).controller();
+        controller
+    }
+
+    /// Controllers of every running game
+    pub fn controllers(&self) -> Vec<Arc<Mutex<Controller>>> {
+        lock(&self.game_connectors)
+            .iter()
+            .filter_map(|c| lock(c).controller())
+            .collect()
+    }
+
+    /// Closes every connection of the game in a port and retires it, false if no game runs there
+    /// The pool starts games again when the lobby needs them.
+    pub fn shutdown_game(&self, port: i32) -> bool {
+        let game_connector = {
+            let mut game_connectors = lock(&self.game_connectors);
+            match game_connectors.iter().position(|c| lock(c).port == port) {
+                Some(index) => game_connectors.remove(index),
+                None => return false,
+            }
+        };
+
+        println!("Shutting down game in port {}", port);
+        self.close_games(&[game_connector], None);
+        true
+    }
+
+    /// Shuts every game down, for the shutdown of the server
+    /// Running matches get the shutdown timeout of the config to finish, and no other match starts.
+    /// Players then get the reason and are disconnected.
+    pub fn shutdown(&self, reason: &str) {
+        let game_connectors: Vec<_> = {
+            let mut game_connectors = lock(&self.game_connectors);
+            *lock(&self.shutting_down) = true;
+            game_connectors.drain(..).collect()
+        };
+        self.reaper_wake.notify_all();
+        let reaper = lock(&self.reaper).take();
+        if let Some(reaper) = reaper {
+            reaper.join().unwrap_or(());
+        }
+
+        for game_connector in &game_connectors {
+            lock(game_connector).close();
+        }
+        let deadline = Instant::now() + self.config.shutdown_timeout;
+        while Instant::now() < deadline && !game_connectors.iter().all(|c| lock(c).matches_ended())
+        {
+            thread::sleep(SHUTDOWN_INTERVAL);
+        }
+        self.close_games(&game_connectors, Some(reason));
+    }
+
+    /// Retires games, waits for their threads and closes the connections of their players
+    /// Retired first, so nobody joins while the players are closed,
+    /// and the players are still there when stopped matches are reported.
+    /// With a reason, players get it once their game stopped sending, before their connection closes.
+    fn close_games(&self, game_connectors: &[Arc<Mutex<Connector>>], reason: Option<&str>) {
+        let closing: Vec<_> = game_connectors
+            .iter()
+            .map(|game_connector| {
+                let mut game_connector = lock(game_connector);
+                game_connector.retire();
+                (game_connector.controller(), game_connector.take_thread())
+            })
+            .collect();
+        let controllers: Vec<_> = closing
+            .into_iter()
+            .filter_map(|(controller, thread)| {
+                if let Some(thread) = thread {
+                    thread.join().unwrap_or(());
+                }
+                controller
+            })
+            .collect();
+
+        if let Some(reason) = reason {
+            for controller in &controllers {
+                lock(controller).announce_shutdown(reason).unwrap_or(());
+            }
+            // Let the writers send it before the connections close
+            thread::sleep(Duration::from_millis(
+                2 * (1000 / self.config.send_data_rate) as u64,
+            ));
+        }
+        for controller in controllers {
+            lock(&controller).reset();
+        }
+    }
+
+    /// Starts a game on the first port of the range no running game uses, fails if every port is taken
+    fn start_next_game(
+        &self,
+        game_connectors: &[Arc<Mutex<Connector>>],
+    ) -> io::Result<Arc<Mutex<Connector>>> {
+        let mut used: Vec<i32> = game_connectors.iter().map(|c| lock(c).port).collect();
+        used.extend(
+            lock(&self.retiring)
+                .iter()
+                .map(|c| lock(c))
+                .filter(|c| !c.port_closed())
+                .map(|c| c.port),
+        );
+
+        let port = (0..self.config.max_games)
+            .map(|index| self.config.game_ports.port(index))
+            .find(|port| *port == 0 || !used.contains(port))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::AddrInUse, "every game port is in use"))?;
+
+        (self.start_game)(port)
+    }
+
+    /// Shuts down games that were empty for the idle timeout, keeping `min_games` public games running
+    /// Idle private rooms are always shut down.
+    /// Their ports stay taken until the games closed them, waiting for the games doesn't hold the pool.
+    fn retire_idle_games(&self) {
+        let mut game_connectors = lock(&self.game_connectors);
+        let min_games = self.config.min_games as usize;
+        let mut retired = vec![];
+
+        let mut index = 0;
+        while index < game_connectors.len() {
+            let public_games = game_connectors
+                .iter()
+                .filter(|c| !lock(c).is_private())
+                .count();
+            let retire = {
+                let mut game_connector = lock(&game_connectors[index]);
+                (game_connector.is_private() || public_games > min_games)
+                    && game_connector.is_idle(self.config.idle_timeout)
+            };
+
+            if retire {
+                let game_connector = game_connectors.remove(index);
+                lock(&self.retiring).push(Arc::clone(&game_connector));
+                retired.push(game_connector);
+            } else {
+                index += 1;
+            }
+        }
+        drop(game_connectors);
+
+        for game_connector in retired {
+            let thread = {
+                let mut connector = lock(&game_connector);
+                println!("Retiring idle game in port {}", connector.port);
+                connector.retire();
+                connector.take_thread()
+            };
+            if let Some(thread) = thread {
+                thread.join().unwrap_or(());
+            }
+            lock(&self.retiring).retain(|c| !Arc::ptr_eq(c, &game_connector));
+        }
+    }
+}
+
+/// Random join code of a private room
+fn room_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..ROOM_CODE_LENGTH)
+        .map(|_| ROOM_CODE_CHARACTERS[rng.gen_range(0..ROOM_CODE_CHARACTERS.len())] as char)
+        .collect()
 }
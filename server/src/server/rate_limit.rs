@@ -0,0 +1,133 @@
+//! # Rate limit
+//! Token buckets that limit how fast a client may send messages and bytes.
+//!
+//! A bucket holds up to `burst` tokens and gains `per_second` tokens every second.
+//! Every message of a client takes one message token and one byte token per byte.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Rate and burst of a token bucket.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Tokens gained every second
+    pub per_second: u32,
+    /// Max tokens saved up, how much a client can send at once
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// Creates a limit of `per_second` tokens, saving up to `burst`
+    pub fn new(per_second: u32, burst: u32) -> RateLimit {
+        RateLimit { per_second, burst }
+    }
+}
+
+/// Tokens left of a client.
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket
+    pub(crate) fn new(limit: RateLimit) -> TokenBucket {
+        TokenBucket {
+            limit,
+            tokens: f64::from(limit.burst),
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Takes the tokens if there are enough of them
+    pub(crate) fn take(&mut self, tokens: u32) -> bool {
+        if !self.has(tokens) {
+            return false;
+        }
+        self.tokens -= f64::from(tokens);
+        true
+    }
+
+    /// Whether the bucket has the tokens, without taking them
+    pub(crate) fn has(&mut self, tokens: u32) -> bool {
+        self.refill();
+        self.tokens >= f64::from(tokens)
+    }
+
+    /// Time until the bucket has the tokens
+    pub(crate) fn wait_time(&mut self, tokens: u32) -> Duration {
+        self.refill();
+        let needed = f64::from(tokens) - self.tokens;
+        if needed <= 0.0 {
+            return Duration::from_secs(0);
+        }
+        Duration::from_secs_f64(needed / f64::from(self.limit.per_second))
+    }
+
+    /// Adds the tokens gained since the last refill
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let gained =
+            now.duration_since(self.refilled).as_secs_f64() * f64::from(self.limit.per_second);
+        self.tokens = (self.tokens + gained).min(f64::from(self.limit.burst));
+        self.refilled = now;
+    }
+}
+
+/// Message and byte buckets of a client.
+pub(crate) struct ClientLimits {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl ClientLimits {
+    /// Creates full buckets for the limits, `None` doesn't limit
+    pub(crate) fn new(messages: Option<RateLimit>, bytes: Option<RateLimit>) -> ClientLimits {
+        ClientLimits {
+            messages: messages.map(TokenBucket::new),
+            bytes: bytes.map(TokenBucket::new),
+        }
+    }
+
+    /// Takes the tokens of a message of `size` bytes, false if the client is over a limit.
+    /// Nothing is taken from either bucket when one of them is short.
+    pub(crate) fn take(&mut self, size: usize) -> bool {
+        let size = size as u32;
+        let within = has(&mut self.messages, 1) && has(&mut self.bytes, size);
+        if within {
+            if let Some(bucket) = &mut self.messages {
+                bucket.take(1);
+            }
+            if let Some(bucket) = &mut self.bytes {
+                bucket.take(size);
+            }
+        }
+        within
+    }
+
+    /// Blocks until the client is back under its limits, then takes the tokens of the message.
+    /// Messages over the byte burst take the whole burst, more could never be saved up.
+    pub(crate) fn wait(&mut self, size: usize) {
+        let size = size as u32;
+        if let Some(bucket) = &mut self.messages {
+            while !bucket.take(1) {
+                thread::sleep(bucket.wait_time(1));
+            }
+        }
+        if let Some(bucket) = &mut self.bytes {
+            let size = size.min(bucket.limit.burst);
+            while !bucket.take(size) {
+                thread::sleep(bucket.wait_time(size));
+            }
+        }
+    }
+}
+
+/// Whether a bucket has the tokens, `None` doesn't limit
+fn has(bucket: &mut Option<TokenBucket>, tokens: u32) -> bool {
+    match bucket {
+        Some(bucket) => bucket.has(tokens),
+        None => true,
+    }
+}
@@ -0,0 +1,499 @@
+//! # Event-driven TCP transport
+//! Transport over `std::net` sockets, where the connections of players share one event loop thread.
+//!
+//! Lobby handshakes and joins use blocking calls, like `TcpTransport`.
+//! Once a player is in a game, its connection is handed to the event loop,
+//! which reads it and writes its outbox when the socket is ready, with `mio`.
+//! Shutting the transport down stops the event loop and closes the connections it still had.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use mio::net::TcpStream as EventStream;
+use mio::{Events, Interest, Poll, Registry, Token, Waker};
+
+use crate::server::error::{lock, ServerError};
+use crate::server::transport::{
+    Connection, LineHandler, Listener, Outbox, TcpTransport, Transport,
+};
+
+/// Token of the waker, connections get the ones after it
+const WAKE_TOKEN: Token = Token(0);
+
+/// Events handled per poll
+const EVENT_CAPACITY: usize = 1024;
+
+/// Bytes read from a socket at once
+const READ_CHUNK: usize = 4096;
+
+/// Binds TCP ports on a host address, with one event loop for every connection.
+#[derive(Clone)]
+pub struct AsyncTcpTransport {
+    tcp: TcpTransport,
+    event_loop: Arc<EventLoop>,
+}
+
+impl AsyncTcpTransport {
+    /// Creates a transport that binds every interface (`0.0.0.0`), and starts its event loop
+    pub fn new() -> Result<AsyncTcpTransport, Error> {
+        AsyncTcpTransport::bound_to(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+
+    /// Creates a transport that binds the given host address, and starts its event loop
+    pub fn bound_to(host: IpAddr) -> Result<AsyncTcpTransport, Error> {
+        Ok(AsyncTcpTransport {
+            tcp: TcpTransport::bound_to(host),
+            event_loop: EventLoop::start()?,
+        })
+    }
+}
+
+impl Transport for AsyncTcpTransport {
+    type Connection = AsyncTcpConnection;
+    type Listener = AsyncTcpListener;
+
+    fn bind(&self, port: i32) -> Result<AsyncTcpListener, Error> {
+        Ok(AsyncTcpListener {
+            listener: self.tcp.bind(port)?,
+            event_loop: Arc::clone(&self.event_loop),
+        })
+    }
+
+    fn connect(&self, port: i32) -> Result<AsyncTcpConnection, Error> {
+        let stream = self.tcp.connect(port)?;
+        Ok(AsyncTcpConnection::new(stream, &self.event_loop))
+    }
+
+    /// Stops the event loop, the connections it still had are closed
+    fn shutdown(&self) {
+        self.event_loop.stop();
+    }
+}
+
+/// An open TCP port of the event-driven transport.
+pub struct AsyncTcpListener {
+    listener: TcpListener,
+    event_loop: Arc<EventLoop>,
+}
+
+impl Listener for AsyncTcpListener {
+    type Connection = AsyncTcpConnection;
+
+    fn accept(&self) -> Result<AsyncTcpConnection, Error> {
+        let (stream, _) = self.listener.accept()?;
+        Ok(AsyncTcpConnection::new(stream, &self.event_loop))
+    }
+
+    fn port(&self) -> i32 {
+        Listener::port(&self.listener)
+    }
+}
+
+/// A TCP connection, blocking until it is handed to the event loop.
+pub struct AsyncTcpConnection {
+    stream: TcpStream,
+    outgoing: Arc<Outgoing>,
+    event_loop: Arc<EventLoop>,
+}
+
+/// Packets of a connection waiting for the event loop to write them, shared by its clones
+struct Outgoing {
+    token: Token,
+    queue: Mutex<Queue>,
+}
+
+struct Queue {
+    packets: VecDeque<Arc<Vec<u8>>>,
+    // Bytes of the first packet already written
+    written: usize,
+    closed: bool,
+}
+
+impl AsyncTcpConnection {
+    fn new(stream: TcpStream, event_loop: &Arc<EventLoop>) -> AsyncTcpConnection {
+        let token = Token(event_loop.next_token.fetch_add(1, Ordering::Relaxed));
+        AsyncTcpConnection {
+            stream,
+            outgoing: Arc::new(Outgoing {
+                token,
+                queue: Mutex::new(Queue {
+                    packets: VecDeque::new(),
+                    written: 0,
+                    closed: false,
+                }),
+            }),
+            event_loop: Arc::clone(event_loop),
+        }
+    }
+}
+
+impl Read for AsyncTcpConnection {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for AsyncTcpConnection {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.stream.flush()
+    }
+}
+
+impl Connection for AsyncTcpConnection {
+    fn try_clone(&self) -> Result<AsyncTcpConnection, Error> {
+        Ok(AsyncTcpConnection {
+            stream: self.stream.try_clone()?,
+            outgoing: Arc::clone(&self.outgoing),
+            event_loop: Arc::clone(&self.event_loop),
+        })
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+
+    fn peer(&self) -> Result<String, Error> {
+        self.stream.peer_addr().map(|addr| addr.to_string())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    fn read_lines(reader: BufReader<Self>, handler: Box<dyn LineHandler>) {
+        let buffered = reader.buffer().to_vec();
+        let connection = reader.into_inner();
+        let event_loop = Arc::clone(&connection.event_loop);
+        event_loop.add(Added {
+            stream: connection.stream,
+            buffered,
+            outgoing: connection.outgoing,
+            handler,
+        });
+    }
+
+    fn outbox(&self) -> Result<Box<dyn Outbox>, Error> {
+        Ok(Box::new(EventOutbox {
+            outgoing: Arc::clone(&self.outgoing),
+            event_loop: Arc::clone(&self.event_loop),
+        }))
+    }
+}
+
+/// Outbox written by the event loop
+struct EventOutbox {
+    outgoing: Arc<Outgoing>,
+    event_loop: Arc<EventLoop>,
+}
+
+impl Outbox for EventOutbox {
+    fn push(&self, packet: Arc<Vec<u8>>) -> Result<(), Error> {
+        {
+            let mut queue = lock(&self.outgoing.queue);
+            if queue.closed {
+                return Err(Error::new(ErrorKind::BrokenPipe, "Connection closed"));
+            }
+            queue.packets.push_back(packet);
+        }
+        self.event_loop.flush(self.outgoing.token);
+        Ok(())
+    }
+
+    fn queued(&self) -> usize {
+        lock(&self.outgoing.queue).packets.len()
+    }
+}
+
+/// Connection handed to the event loop
+struct Added {
+    stream: TcpStream,
+    // Read by the blocking reader before the handover
+    buffered: Vec<u8>,
+    outgoing: Arc<Outgoing>,
+    handler: Box<dyn LineHandler>,
+}
+
+/// Requests of other threads, taken by the event loop when it wakes up
+struct Requests {
+    added: Vec<Added>,
+    flushes: Vec<Token>,
+    stop: bool,
+}
+
+/// Connection owned by the event loop
+struct Entry {
+    stream: EventStream,
+    // Bytes of a line not yet ended
+    line: Vec<u8>,
+    outgoing: Arc<Outgoing>,
+    handler: Box<dyn LineHandler>,
+}
+
+impl Entry {
+    /// Hands every ended line to the handler, lines over the max size end the connection
+    fn receive(&mut self, bytes: &[u8]) -> Result<(), ServerError> {
+        let max = self.handler.max_line_size();
+        self.line.extend_from_slice(bytes);
+        while let Some(end) = self.line.iter().position(|byte| *byte == b'\n') {
+            if end >= max {
+                return Err(ServerError::MessageTooLarge(end + 1));
+            }
+            let line: Vec<u8> = self.line.drain(..=end).collect();
+            self.handler.line(&line)?;
+        }
+        if self.line.len() >= max {
+            return Err(ServerError::MessageTooLarge(self.line.len()));
+        }
+        Ok(())
+    }
+}
+
+/// Thread that reads and writes every connection handed to it
+struct EventLoop {
+    registry: Registry,
+    waker: Waker,
+    next_token: AtomicUsize,
+    requests: Mutex<Requests>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl EventLoop {
+    /// Starts the event loop thread
+    fn start() -> Result<Arc<EventLoop>, Error> {
+        let poll = Poll::new()?;
+        let event_loop = Arc::new(EventLoop {
+            registry: poll.registry().try_clone()?,
+            waker: Waker::new(poll.registry(), WAKE_TOKEN)?,
+            next_token: AtomicUsize::new(WAKE_TOKEN.0 + 1),
+            requests: Mutex::new(Requests {
+                added: vec![],
+                flushes: vec![],
+                stop: false,
+            }),
+            thread: Mutex::new(None),
+        });
+
+        let running = Arc::clone(&event_loop);
+        let builder = thread::Builder::new().name("Event-loop".to_string());
+        let thread = builder
+            .spawn(move || running.run(poll))
+            .expect("Could not create thread");
+        *lock(&event_loop.thread) = Some(thread);
+
+        Ok(event_loop)
+    }
+
+    /// Asks the event loop to end and waits for it
+    fn stop(&self) {
+        lock(&self.requests).stop = true;
+        self.waker.wake().unwrap_or(());
+        let thread = lock(&self.thread).take();
+        if let Some(thread) = thread {
+            thread.join().unwrap_or(());
+        }
+    }
+
+    /// Hands a connection to the event loop
+    /// A stopped event loop closes the connection right away
+    fn add(&self, added: Added) {
+        let mut requests = lock(&self.requests);
+        if requests.stop {
+            drop(requests);
+            EventLoop::discard(added);
+            return;
+        }
+        requests.added.push(added);
+        drop(requests);
+        self.waker.wake().unwrap_or(());
+    }
+
+    /// Asks the event loop to write the outbox of a connection
+    fn flush(&self, token: Token) {
+        lock(&self.requests).flushes.push(token);
+        self.waker.wake().unwrap_or(());
+    }
+
+    /// Waits for ready sockets and requests, until it is stopped
+    fn run(&self, mut poll: Poll) {
+        let mut events = Events::with_capacity(EVENT_CAPACITY);
+        let mut entries: HashMap<Token, Entry> = HashMap::new();
+
+        loop {
+            if let Err(error) = poll.poll(&mut events, None) {
+                if error.kind() != ErrorKind::Interrupted {
+                    eprintln!("Event loop: {}", error);
+                }
+                continue;
+            }
+
+            for event in events.iter() {
+                let token = event.token();
+                if event.is_readable() || event.is_read_closed() || event.is_error() {
+                    self.read(&mut entries, token);
+                }
+                if event.is_writable() {
+                    self.write(&mut entries, token);
+                }
+            }
+
+            // Handlers run without the requests, so they can make new ones
+            let (added, flushes) = {
+                let mut requests = lock(&self.requests);
+                if requests.stop {
+                    let added = mem::take(&mut requests.added);
+                    drop(requests);
+                    let tokens: Vec<Token> = entries.keys().copied().collect();
+                    for token in tokens {
+                        self.close(&mut entries, token, Ok(()));
+                    }
+                    for added in added {
+                        EventLoop::discard(added);
+                    }
+                    return;
+                }
+                (
+                    mem::take(&mut requests.added),
+                    mem::take(&mut requests.flushes),
+                )
+            };
+            for added in added {
+                self.register(&mut entries, added);
+            }
+            for token in flushes {
+                self.write(&mut entries, token);
+            }
+        }
+    }
+
+    /// Starts polling a connection, then handles what was read and queued before
+    fn register(&self, entries: &mut HashMap<Token, Entry>, added: Added) {
+        let Added {
+            stream,
+            buffered,
+            outgoing,
+            handler,
+        } = added;
+        let token = outgoing.token;
+
+        let registered = stream.set_nonblocking(true).and_then(|_| {
+            let mut stream = EventStream::from_std(stream);
+            self.registry
+                .register(&mut stream, token, Interest::READABLE | Interest::WRITABLE)?;
+            Ok(stream)
+        });
+        let stream = match registered {
+            Ok(stream) => stream,
+            Err(error) => {
+                lock(&outgoing.queue).closed = true;
+                handler.closed(Err(ServerError::Io(error)));
+                return;
+            }
+        };
+
+        let mut entry = Entry {
+            stream,
+            line: Vec::new(),
+            outgoing,
+            handler,
+        };
+        let received = entry.receive(&buffered);
+        entries.insert(token, entry);
+        match received {
+            Ok(_) => self.write(entries, token),
+            Err(error) => self.close(entries, token, Err(error)),
+        }
+    }
+
+    /// Reads a connection until it would block, ends it on errors and hang ups
+    fn read(&self, entries: &mut HashMap<Token, Entry>, token: Token) {
+        let entry = match entries.get_mut(&token) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let mut chunk = [0; READ_CHUNK];
+        let ended = loop {
+            match entry.stream.read(&mut chunk) {
+                Ok(0) => break Some(Ok(())),
+                Ok(count) => {
+                    if let Err(error) = entry.receive(&chunk[..count]) {
+                        break Some(Err(error));
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break None,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => break Some(Err(ServerError::Io(error))),
+            }
+        };
+
+        if let Some(result) = ended {
+            self.close(entries, token, result);
+        }
+    }
+
+    /// Writes the outbox of a connection until it is empty or the socket would block
+    fn write(&self, entries: &mut HashMap<Token, Entry>, token: Token) {
+        let entry = match entries.get_mut(&token) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let mut queue = lock(&entry.outgoing.queue);
+        let result = loop {
+            let packet = match queue.packets.front() {
+                Some(packet) => Arc::clone(packet),
+                None => break Ok(()),
+            };
+            match entry.stream.write(&packet[queue.written..]) {
+                Ok(0) => break Err(Error::new(ErrorKind::WriteZero, "Connection closed")),
+                Ok(count) => {
+                    queue.written += count;
+                    if queue.written == packet.len() {
+                        queue.packets.pop_front();
+                        queue.written = 0;
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => break Err(error),
+            }
+        };
+        drop(queue);
+
+        if let Err(error) = result {
+            self.close(entries, token, Err(ServerError::Io(error)));
+        }
+    }
+
+    /// Closes a connection that was never polled and tells its handler
+    fn discard(added: Added) {
+        added.stream.shutdown(Shutdown::Both).unwrap_or(());
+        lock(&added.outgoing.queue).closed = true;
+        added.handler.closed(Ok(()));
+    }
+
+    /// Stops polling a connection, closes it and tells its handler
+    fn close(
+        &self,
+        entries: &mut HashMap<Token, Entry>,
+        token: Token,
+        result: Result<(), ServerError>,
+    ) {
+        if let Some(mut entry) = entries.remove(&token) {
+            self.registry.deregister(&mut entry.stream).unwrap_or(());
+            entry.stream.shutdown(Shutdown::Both).unwrap_or(());
+            lock(&entry.outgoing.queue).closed = true;
+            entry.handler.closed(result);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! # Channel transport
+//! In-process transport, connections are pairs of in-memory pipes.
+//!
+//! Ports only exist inside a `ChannelTransport` and its clones,
+//! so a test or a listen-server can run a whole server without opening sockets.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::server::transport::{Connection, Listener, Transport};
+
+/// First port handed out when binding port `0`
+const FIRST_EPHEMERAL_PORT: i32 = 49152;
+
+/// Registry of open ports, shared by every clone of the transport.
+struct Ports {
+    listeners: HashMap<i32, Sender<ChannelConnection>>,
+    next_ephemeral: i32,
+    next_peer: usize,
+}
+
+/// Transport over in-memory channels.
+/// Clone it to hand the same set of ports to the server and its clients.
+#[derive(Clone)]
+pub struct ChannelTransport {
+    ports: Arc<Mutex<Ports>>,
+    capacity: Option<usize>,
+}
+
+impl ChannelTransport {
+    /// Creates a transport with no open ports
+    pub fn new() -> ChannelTransport {
+        ChannelTransport {
+            ports: Arc::new(Mutex::new(Ports {
+                listeners: HashMap::new(),
+                next_ephemeral: FIRST_EPHEMERAL_PORT,
+                next_peer: 0,
+            })),
+            capacity: None,
+        }
+    }
+
+    /// Creates a transport whose pipes hold at most `capacity` bytes
+    /// Writes block while the pipe is full, like a socket whose peer stopped reading.
+    pub fn with_capacity(capacity: usize) -> ChannelTransport {
+        ChannelTransport {
+            capacity: Some(capacity.max(1)),
+            ..ChannelTransport::new()
+        }
+    }
+}
+
+impl Default for ChannelTransport {
+    fn default() -> ChannelTransport {
+        ChannelTransport::new()
+    }
+}
+
+impl Transport for ChannelTransport {
+    type Connection = ChannelConnection;
+    type Listener = ChannelListener;
+
+    fn bind(&self, port: i32) -> Result<ChannelListener, Error> {
+        let mut ports = self.ports.lock().unwrap();
+
+        let port = if port == 0 {
+            while ports.listeners.contains_key(&ports.next_ephemeral) {
+                ports.next_ephemeral += 1;
+            }
+            ports.next_ephemeral
+        } else {
+            port
+        };
+
+        if ports.listeners.contains_key(&port) {
+            return Err(Error::new(
+                ErrorKind::AddrInUse,
+                format!("Channel port {} is already bound", port),
+            ));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        ports.listeners.insert(port, sender);
+
+        Ok(ChannelListener {
+            port,
+            receiver: Mutex::new(receiver),
+            ports: Arc::clone(&self.ports),
+        })
+    }
+
+    fn connect(&self, port: i32) -> Result<ChannelConnection, Error> {
+        let mut ports = self.ports.lock().unwrap();
+        ports.next_peer += 1;
+        let peer = ports.next_peer;
+
+        let refused = || {
+            Error::new(
+                ErrorKind::ConnectionRefused,
+                format!("No channel listener on port {}", port),
+            )
+        };
+
+        let listener = ports.listeners.get(&port).ok_or_else(refused)?;
+
+        // Client writes into the server's incoming pipe and vice versa
+        let to_server = Arc::new(Pipe::new(self.capacity));
+        let to_client = Arc::new(Pipe::new(self.capacity));
+
+        let server_end = ChannelConnection::new(
+            Arc::clone(&to_server),
+            Arc::clone(&to_client),
+            format!("channel-client-{}", peer),
+        );
+        let client_end =
+            ChannelConnection::new(to_client, to_server, format!("channel-port-{}", port));
+
+        listener.send(server_end).map_err(|_| refused())?;
+        Ok(client_end)
+    }
+}
+
+/// An open channel port.
+/// The port is released when the listener is dropped.
+pub struct ChannelListener {
+    port: i32,
+    receiver: Mutex<Receiver<ChannelConnection>>,
+    ports: Arc<Mutex<Ports>>,
+}
+
+impl Listener for ChannelListener {
+    type Connection = ChannelConnection;
+
+    fn accept(&self) -> Result<ChannelConnection, Error> {
+        self.receiver.lock().unwrap().recv().map_err(|_| {
+            Error::new(
+                ErrorKind::NotConnected,
+                format!("Channel port {} was closed", self.port),
+            )
+        })
+    }
+
+    fn port(&self) -> i32 {
+        self.port
+    }
+}
+
+impl Drop for ChannelListener {
+    fn drop(&mut self) {
+        self.ports.lock().unwrap().listeners.remove(&self.port);
+    }
+}
+
+/// One direction of a connection.
+struct Pipe {
+    state: Mutex<PipeState>,
+    ready: Condvar,
+    capacity: Option<usize>,
+}
+
+struct PipeState {
+    buffer: VecDeque<u8>,
+    closed: bool,
+}
+
+impl Pipe {
+    fn new(capacity: Option<usize>) -> Pipe {
+        Pipe {
+            state: Mutex::new(PipeState {
+                buffer: VecDeque::new(),
+                closed: false,
+            }),
+            ready: Condvar::new(),
+            capacity,
+        }
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.ready.notify_all();
+    }
+}
+
+/// Both pipes of one end of a connection.
+/// Shared between clones, closes the connection when the last clone is dropped.
+struct Ends {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+    read_timeout: Mutex<Option<Duration>>,
+}
+
+impl Drop for Ends {
+    fn drop(&mut self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+/// One end of an in-memory connection.
+pub struct ChannelConnection {
+    ends: Arc<Ends>,
+    peer: String,
+}
+
+impl ChannelConnection {
+    fn new(incoming: Arc<Pipe>, outgoing: Arc<Pipe>, peer: String) -> ChannelConnection {
+        ChannelConnection {
+            ends: Arc::new(Ends {
+                incoming,
+                outgoing,
+                read_timeout: Mutex::new(None),
+            }),
+            peer,
+        }
+    }
+}
+
+impl Read for ChannelConnection {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let pipe = &self.ends.incoming;
+        let deadline = self
+            .ends
+            .read_timeout
+            .lock()
+            .unwrap()
+            .map(|timeout| Instant::now() + timeout);
+        let mut state = pipe.state.lock().unwrap();
+
+        // Block until there is data, the other end hangs up or the timeout runs out
+        while state.buffer.is_empty() && !state.closed {
+            state = match deadline {
+                None => pipe.ready.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::new(ErrorKind::TimedOut, "Channel read timed out"));
+                    }
+                    pipe.ready.wait_timeout(state, deadline - now).unwrap().0
+                }
+            };
+        }
+
+        let count = buf.len().min(state.buffer.len());
+        for (byte, value) in buf.iter_mut().zip(state.buffer.drain(..count)) {
+            *byte = value;
+        }
+        pipe.ready.notify_all();
+        Ok(count)
+    }
+}
+
+impl Write for ChannelConnection {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let pipe = &self.ends.outgoing;
+        let mut state = pipe.state.lock().unwrap();
+
+        // Block until the reader makes room
+        let capacity = pipe.capacity.unwrap_or(usize::MAX);
+        while state.buffer.len() >= capacity && !state.closed {
+            state = pipe.ready.wait(state).unwrap();
+        }
+
+        if state.closed {
+            return Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "Channel connection closed",
+            ));
+        }
+
+        let count = buf.len().min(capacity - state.buffer.len());
+        state.buffer.extend(&buf[..count]);
+        pipe.ready.notify_all();
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Connection for ChannelConnection {
+    fn try_clone(&self) -> Result<ChannelConnection, Error> {
+        Ok(ChannelConnection {
+            ends: Arc::clone(&self.ends),
+            peer: self.peer.clone(),
+        })
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        self.ends.incoming.close();
+        self.ends.outgoing.close();
+        Ok(())
+    }
+
+    fn peer(&self) -> Result<String, Error> {
+        Ok(self.peer.clone())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        *self.ends.read_timeout.lock().unwrap() = timeout;
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+//! # Transport
+//! The transport abstracts how the server opens ports and accepts connections.
+//!
+//! The lobby and the controllers only talk to the traits in this module,
+//! so the same server logic runs over TCP sockets or over in-process channels.
+//! * TCP: `TcpTransport`, for real multiplayer games.
+//! * Channels: `ChannelTransport`, for tests, local co-op and listen-servers.
+//! * Event-driven TCP: `AsyncTcpTransport`, with the `async-io` feature, for servers with many players.
+//! * TLS: `TlsTransport`, with the `tls` feature, for encrypted TCP connections.
+//!
+//! Connections of players are read and written through `Connection::read_lines` and `Connection::outbox`.
+//! By default every player gets a reader and a writer thread,
+//! event-driven transports multiplex every connection on one event loop thread instead.
+
+#[cfg(feature = "async-io")]
+mod async_tcp;
+mod channel;
+mod tcp;
+mod threaded;
+#[cfg(feature = "tls")]
+mod tls;
+
+use std::io::{BufReader, Error, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::server::error::ServerError;
+
+#[cfg(feature = "async-io")]
+pub use async_tcp::{AsyncTcpConnection, AsyncTcpListener, AsyncTcpTransport};
+pub use channel::{ChannelConnection, ChannelListener, ChannelTransport};
+pub use tcp::TcpTransport;
+#[cfg(feature = "tls")]
+pub use tls::{TlsConnection, TlsListener, TlsTransport};
+
+/// Receives what a client sends, line by line.
+pub trait LineHandler: Send + 'static {
+    /// Called with every line, an error ends the connection
+    fn line(&mut self, line: &[u8]) -> Result<(), ServerError>;
+
+    /// Called once the connection ended, with the error that ended it
+    fn closed(self: Box<Self>, result: Result<(), ServerError>);
+
+    /// Max bytes of a line with its newline, longer lines end the connection with `MessageTooLarge`
+    fn max_line_size(&self) -> usize {
+        usize::MAX
+    }
+}
+
+/// Packets waiting to be written to a client.
+pub trait Outbox: Send {
+    /// Queues an encoded packet without blocking, fails once the connection is gone
+    fn push(&self, packet: Arc<Vec<u8>>) -> Result<(), Error>;
+
+    /// Packets queued and not yet written
+    fn queued(&self) -> usize;
+}
+
+/// A two way byte stream between the server and a client.
+pub trait Connection: Read + Write + Send + Sized + 'static {
+    /// Creates a new handle to the same connection, to read and write from different threads
+    fn try_clone(&self) -> Result<Self, Error>;
+
+    /// Closes both directions of the connection
+    fn close(&self) -> Result<(), Error>;
+
+    /// Readable address of the other end of the connection
+    fn peer(&self) -> Result<String, Error>;
+
+    /// Makes blocking reads fail with a timeout error after `timeout`, `None` blocks forever
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error>;
+
+    /// Hands every line read from the connection to the handler, without blocking the caller.
+    /// Bytes already buffered by the reader come first.
+    /// Reads on a thread of its own by default.
+    fn read_lines(reader: BufReader<Self>, handler: Box<dyn LineHandler>) {
+        threaded::read_lines(reader, handler);
+    }
+
+    /// Creates the outbox the packets of the game are written from.
+    /// Writes on a thread of its own by default.
+    fn outbox(&self) -> Result<Box<dyn Outbox>, Error> {
+        Ok(Box::new(threaded::ThreadOutbox::new(self.try_clone()?)))
+    }
+}
+
+/// An open port that accepts incoming connections.
+pub trait Listener: Send + 'static {
+    type Connection: Connection;
+
+    /// Blocks until a new connection arrives
+    fn accept(&self) -> Result<Self::Connection, Error>;
+
+    /// Port the listener is bound to
+    fn port(&self) -> i32;
+}
+
+/// Opens ports and connections for the server.
+/// # Example
+/// For a working example, please see the transport tests in the server lib.
+pub trait Transport: Clone + Send + Sync + 'static {
+    type Connection: Connection;
+    type Listener: Listener<Connection = Self::Connection>;
+
+    /// Opens a port, a port of `0` asks the transport for any free port
+    fn bind(&self, port: i32) -> Result<Self::Listener, Error>;
+
+    /// Connects to a port opened by this transport
+    fn connect(&self, port: i32) -> Result<Self::Connection, Error>;
+
+    /// Stops and waits for the threads of the transport, once the server shut down
+    /// Transports without threads of their own have nothing to stop.
+    fn shutdown(&self) {}
+}
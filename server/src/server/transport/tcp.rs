@@ -0,0 +1,83 @@
+//! # TCP transport
+//! Transport over `std::net` sockets.
+
+use std::io::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+
+use crate::server::transport::{Connection, Listener, Transport};
+
+/// Binds TCP ports on a host address.
+#[derive(Clone, Debug)]
+pub struct TcpTransport {
+    host: IpAddr,
+}
+
+impl TcpTransport {
+    /// Creates a transport that binds every interface (`0.0.0.0`)
+    pub fn new() -> TcpTransport {
+        TcpTransport::bound_to(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+
+    /// Creates a transport that binds the given host address
+    pub fn bound_to(host: IpAddr) -> TcpTransport {
+        TcpTransport { host }
+    }
+}
+
+impl Default for TcpTransport {
+    fn default() -> TcpTransport {
+        TcpTransport::new()
+    }
+}
+
+impl Transport for TcpTransport {
+    type Connection = TcpStream;
+    type Listener = TcpListener;
+
+    fn bind(&self, port: i32) -> Result<TcpListener, Error> {
+        TcpListener::bind(SocketAddr::new(self.host, port as u16))
+    }
+
+    fn connect(&self, port: i32) -> Result<TcpStream, Error> {
+        // Unspecified hosts can't be connected to, use the loopback instead
+        let host = match self.host {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        TcpStream::connect(SocketAddr::new(host, port as u16))
+    }
+}
+
+impl Listener for TcpListener {
+    type Connection = TcpStream;
+
+    fn accept(&self) -> Result<TcpStream, Error> {
+        TcpListener::accept(self).map(|(stream, _)| stream)
+    }
+
+    fn port(&self) -> i32 {
+        self.local_addr()
+            .map(|addr| addr.port() as i32)
+            .unwrap_or(0)
+    }
+}
+
+impl Connection for TcpStream {
+    fn try_clone(&self) -> Result<TcpStream, Error> {
+        TcpStream::try_clone(self)
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        self.shutdown(Shutdown::Both)
+    }
+
+    fn peer(&self) -> Result<String, Error> {
+        self.peer_addr().map(|addr| addr.to_string())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
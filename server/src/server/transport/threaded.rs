@@ -0,0 +1,79 @@
+//! # Threaded I/O
+//! Reads and writes a connection with blocking calls, on a reader and a writer thread per connection.
+//! Works for every transport, the default of `Connection::read_lines` and `Connection::outbox`.
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::server::error::ServerError;
+use crate::server::transport::{Connection, LineHandler, Outbox};
+
+/// Reads lines on a new thread until the connection ends
+pub(crate) fn read_lines<C: Connection>(
+    mut reader: BufReader<C>,
+    mut handler: Box<dyn LineHandler>,
+) {
+    thread::spawn(move || {
+        let max = handler.max_line_size();
+        let result = loop {
+            let mut buffer: Vec<u8> = Vec::new();
+
+            // If no bytes end connection, lines are never buffered past the max size
+            let mut limited = (&mut reader).take(max as u64);
+            match limited.read_until(b'\n', &mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(read) if read == max && buffer.last() != Some(&b'\n') => {
+                    break Err(ServerError::MessageTooLarge(read))
+                }
+                Ok(_) => {}
+                Err(error) => break Err(ServerError::Io(error)),
+            }
+            if let Err(error) = handler.line(&buffer) {
+                break Err(error);
+            }
+        };
+        handler.closed(result);
+    });
+}
+
+/// Outbox written by a thread of its own, which stops with the outbox
+pub(crate) struct ThreadOutbox {
+    sender: Sender<Arc<Vec<u8>>>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl ThreadOutbox {
+    /// Spawns the writer thread of the connection
+    pub(crate) fn new<C: Connection>(mut stream: C) -> ThreadOutbox {
+        let (sender, receiver) = mpsc::channel::<Arc<Vec<u8>>>();
+        let queued = Arc::new(AtomicUsize::new(0));
+        let written = Arc::clone(&queued);
+        thread::spawn(move || {
+            for packet in receiver {
+                // Reader thread notices the closed stream and removes the player
+                if stream.write_all(&packet).is_err() {
+                    stream.close().unwrap_or(());
+                    break;
+                }
+                written.fetch_sub(1, Ordering::Relaxed);
+            }
+        });
+        ThreadOutbox { sender, queued }
+    }
+}
+
+impl Outbox for ThreadOutbox {
+    fn push(&self, packet: Arc<Vec<u8>>) -> Result<(), Error> {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.sender
+            .send(packet)
+            .map_err(|_| Error::new(ErrorKind::BrokenPipe, "Writer thread stopped"))
+    }
+
+    fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+}
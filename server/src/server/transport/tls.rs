@@ -0,0 +1,300 @@
+//! # TLS transport
+//! Transport over TCP sockets encrypted with rustls, with the `tls` feature.
+//!
+//! Reading and writing share the TLS session of a connection, but the socket is read and written without holding it,
+//! so a reader waiting for a client never blocks the writer, and a client that stops reading never blocks `close`.
+//! The handshake happens on the first read, or on the first write when nothing reads the connection.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rustls::{
+    Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerConfig,
+    ServerConnection, ServerName,
+};
+use rustls_pemfile::Item;
+
+use crate::server::config::TlsFiles;
+use crate::server::error::lock;
+use crate::server::transport::{Connection, Listener, TcpTransport, Transport};
+
+/// Name the certificate must have for `TlsTransport::connect`
+const LOCAL_NAME: &str = "localhost";
+
+/// Max bytes read from the socket at once, one TLS record
+const RECORD_SIZE: usize = 16 * 1024;
+
+/// Binds TCP ports whose connections are encrypted with TLS.
+/// `connect` trusts the certificates the transport serves, for tests and local clients,
+/// so they must be valid for `localhost`.
+#[derive(Clone)]
+pub struct TlsTransport {
+    tcp: TcpTransport,
+    server: Arc<ServerConfig>,
+    client: Arc<ClientConfig>,
+}
+
+impl TlsTransport {
+    /// Creates a transport serving the certificate and key of the files
+    /// Fails if the files can't be read or hold no certificate or key.
+    pub fn new(tcp: TcpTransport, files: &TlsFiles) -> Result<TlsTransport, Error> {
+        let certificates = read_certificates(&files.certificate)?;
+        let key = read_key(&files.key)?;
+
+        let server = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certificates.clone(), key)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+
+        // Local clients trust the served chain
+        let mut roots = RootCertStore::empty();
+        for certificate in &certificates {
+            roots.add(certificate).unwrap_or(());
+        }
+        let client = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        Ok(TlsTransport {
+            tcp,
+            server: Arc::new(server),
+            client: Arc::new(client),
+        })
+    }
+}
+
+impl Transport for TlsTransport {
+    type Connection = TlsConnection;
+    type Listener = TlsListener;
+
+    fn bind(&self, port: i32) -> Result<TlsListener, Error> {
+        Ok(TlsListener {
+            tcp: self.tcp.bind(port)?,
+            config: Arc::clone(&self.server),
+        })
+    }
+
+    fn connect(&self, port: i32) -> Result<TlsConnection, Error> {
+        let name = ServerName::try_from(LOCAL_NAME)
+            .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+        let session = ClientConnection::new(Arc::clone(&self.client), name)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        Ok(TlsConnection::new(
+            self.tcp.connect(port)?,
+            rustls::Connection::Client(session),
+        ))
+    }
+}
+
+/// A TCP port that starts a TLS session for every connection.
+pub struct TlsListener {
+    tcp: TcpListener,
+    config: Arc<ServerConfig>,
+}
+
+impl Listener for TlsListener {
+    type Connection = TlsConnection;
+
+    /// Accepts the TCP connection, the handshake happens on the first read or write
+    fn accept(&self) -> Result<TlsConnection, Error> {
+        let stream = Listener::accept(&self.tcp)?;
+        let session = ServerConnection::new(Arc::clone(&self.config))
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        Ok(TlsConnection::new(
+            stream,
+            rustls::Connection::Server(session),
+        ))
+    }
+
+    fn port(&self) -> i32 {
+        self.tcp.port()
+    }
+}
+
+/// A TCP connection encrypted with TLS.
+pub struct TlsConnection {
+    stream: TcpStream,
+    session: Arc<Mutex<rustls::Connection>>,
+    /// Held while reading the socket, records must reach the session in order
+    reading: Arc<Mutex<()>>,
+    /// Held while writing the socket, records must reach the client in order
+    writing: Arc<Mutex<()>>,
+}
+
+impl TlsConnection {
+    fn new(stream: TcpStream, session: rustls::Connection) -> TlsConnection {
+        TlsConnection {
+            stream,
+            session: Arc::new(Mutex::new(session)),
+            reading: Arc::new(Mutex::new(())),
+            writing: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Reads records from the socket into the session, false once the socket is closed
+    /// Must be called holding `reading`.
+    fn receive(&self) -> Result<bool, Error> {
+        let mut records = [0; RECORD_SIZE];
+
+        // Timeouts of the socket end the read, like on plain TCP
+        let read = (&self.stream).read(&mut records)?;
+
+        let mut received = &records[..read];
+        if read == 0 {
+            lock(&self.session).read_tls(&mut received)?;
+            return Ok(false);
+        }
+        let processed = {
+            let mut session = lock(&self.session);
+            let mut processed = Ok(());
+            while processed.is_ok() && !received.is_empty() {
+                session.read_tls(&mut received)?;
+                processed = session.process_new_packets().map(|_| ());
+            }
+            processed
+        };
+
+        // Handshake answers and alerts go out right away
+        self.send_records()?;
+        processed.map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        Ok(true)
+    }
+
+    /// Writes the records waiting in the session to the socket
+    /// The session is only held to take the records, not while the socket blocks.
+    fn send_records(&self) -> Result<(), Error> {
+        let _writing = lock(&self.writing);
+        let records = take_records(&mut lock(&self.session))?;
+        (&self.stream).write_all(&records)
+    }
+}
+
+/// Takes the records waiting in the session
+fn take_records(session: &mut rustls::Connection) -> Result<Vec<u8>, Error> {
+    let mut records = vec![];
+    while session.wants_write() {
+        session.write_tls(&mut records)?;
+    }
+    Ok(records)
+}
+
+impl Read for TlsConnection {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        loop {
+            match lock(&self.session).reader().read(buf) {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                // Clients that hang up without a close notify just end the connection
+                Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(0),
+                result => return result,
+            }
+            let _reading = lock(&self.reading);
+            self.receive()?;
+        }
+    }
+}
+
+impl Write for TlsConnection {
+    /// Plaintext written during the handshake goes out once it's done
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let written = {
+            let _writing = lock(&self.writing);
+            let (written, records) = {
+                let mut session = lock(&self.session);
+                let written = session.writer().write(buf)?;
+                (written, take_records(&mut session)?)
+            };
+            (&self.stream).write_all(&records)?;
+            written
+        };
+
+        // A reader finishes the handshake, unless nothing reads yet
+        if let Ok(_reading) = self.reading.try_lock() {
+            while lock(&self.session).is_handshaking() {
+                if !self.receive()? {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "Connection closed during the TLS handshake",
+                    ));
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.send_records()
+    }
+}
+
+impl Connection for TlsConnection {
+    fn try_clone(&self) -> Result<TlsConnection, Error> {
+        Ok(TlsConnection {
+            stream: self.stream.try_clone()?,
+            session: Arc::clone(&self.session),
+            reading: Arc::clone(&self.reading),
+            writing: Arc::clone(&self.writing),
+        })
+    }
+
+    /// Tells the other end the session is over, then closes the socket
+    /// The close notify is skipped while a write is blocked, closing never waits for the client.
+    fn close(&self) -> Result<(), Error> {
+        if let Ok(_writing) = self.writing.try_lock() {
+            let records = {
+                let mut session = lock(&self.session);
+                session.send_close_notify();
+                take_records(&mut session)
+            };
+            if let Ok(records) = records {
+                self.stream.set_nonblocking(true).unwrap_or(());
+                (&self.stream).write_all(&records).unwrap_or(());
+            }
+        }
+        self.stream.shutdown(Shutdown::Both)
+    }
+
+    fn peer(&self) -> Result<String, Error> {
+        self.stream.peer_addr().map(|addr| addr.to_string())
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.stream.set_read_timeout(timeout)
+    }
+}
+
+/// Reads every certificate of a PEM file
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certificates = rustls_pemfile::certs(&mut reader)?;
+    if certificates.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("No certificate in {}", path.display()),
+        ));
+    }
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first private key of a PEM file
+fn read_key(path: &Path) -> Result<PrivateKey, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => {
+                return Ok(PrivateKey(key))
+            }
+            _ => continue,
+        }
+    }
+    Err(Error::new(
+        ErrorKind::InvalidData,
+        format!("No private key in {}", path.display()),
+    ))
+}
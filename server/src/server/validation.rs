@@ -0,0 +1,203 @@
+//! # Validation
+//! Checks every message of a player before it reaches the app, for authoritative games.
+//!
+//! A `Validator` sees the message, the tick it targets and the recent accepted messages of the player,
+//! and accepts it or rejects it with a suspicion score.
+//! Rejected messages are dropped. Scores add up and decay over time,
+//! and players whose score reaches the kick or ban threshold of the config are removed.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::server::bans::BanList;
+
+/// An accepted message of a player.
+#[derive(Debug, Clone)]
+pub struct Record<M> {
+    pub message: M,
+    /// Tick the message targeted
+    pub tick: u64,
+    /// Time the server read the message
+    pub received: Instant,
+}
+
+/// Recent accepted messages of a player, oldest first.
+#[derive(Debug, Clone)]
+pub struct History<M> {
+    records: VecDeque<Record<M>>,
+    capacity: usize,
+}
+
+impl<M> History<M> {
+    /// Creates an empty history that keeps the last `capacity` messages
+    pub fn new(capacity: usize) -> History<M> {
+        History {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Accepted messages, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Record<M>> {
+        self.records.iter()
+    }
+
+    /// Last accepted message
+    pub fn last(&self) -> Option<&Record<M>> {
+        self.records.back()
+    }
+
+    /// Messages accepted within the last `period`, to spot commands sent too often
+    pub fn count_within(&self, period: Duration) -> usize {
+        self.records
+            .iter()
+            .rev()
+            .take_while(|record| record.received.elapsed() <= period)
+            .count()
+    }
+
+    /// Adds an accepted message, forgetting the oldest one when full
+    fn push(&mut self, record: Record<M>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+/// What a validator decides about a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    /// Message goes to the app
+    Accept,
+    /// Message is dropped, and the suspicion score of the player grows by the amount
+    Reject(u32),
+}
+
+/// Checks the messages of players.
+/// Closures with the same arguments are validators too.
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use blizzard_server::server::validation::{History, Verdict};
+///
+/// // Moves of one step at most, no more than 10 per second, never for past ticks
+/// let validator = |_player: usize, tick: u64, step: &i32, history: &History<i32>| {
+///     if step.abs() > 1 {
+///         Verdict::Reject(10)
+///     } else if history.count_within(Duration::from_secs(1)) >= 10 {
+///         Verdict::Reject(1)
+///     } else if history.last().map_or(false, |last| tick < last.tick) {
+///         Verdict::Reject(5)
+///     } else {
+///         Verdict::Accept
+///     }
+/// };
+/// # let _ = validator;
+/// ```
+pub trait Validator<M>: Send + Sync + 'static {
+    /// Decides about a message of a player, for the tick it targets
+    fn validate(&self, player_id: usize, tick: u64, message: &M, history: &History<M>) -> Verdict;
+}
+
+impl<M, F> Validator<M> for F
+where
+    F: Fn(usize, u64, &M, &History<M>) -> Verdict + Send + Sync + 'static,
+{
+    fn validate(&self, player_id: usize, tick: u64, message: &M, history: &History<M>) -> Verdict {
+        self(player_id, tick, message, history)
+    }
+}
+
+/// Validator with the history of one connection.
+/// Keeps the `Clone` bound of the history out of the controller.
+pub(crate) trait MessageCheck<M>: Send {
+    /// Validates a message, and keeps it in the history once accepted
+    fn check(&mut self, player_id: usize, tick: u64, message: &M) -> Verdict;
+}
+
+struct CheckedHistory<M, V> {
+    validator: Arc<V>,
+    history: History<M>,
+}
+
+impl<M: Clone + Send, V: Validator<M>> MessageCheck<M> for CheckedHistory<M, V> {
+    fn check(&mut self, player_id: usize, tick: u64, message: &M) -> Verdict {
+        let verdict = self
+            .validator
+            .validate(player_id, tick, message, &self.history);
+        if verdict == Verdict::Accept {
+            self.history.push(Record {
+                message: message.clone(),
+                tick,
+                received: Instant::now(),
+            });
+        }
+        verdict
+    }
+}
+
+/// Creates the check of every new connection
+pub(crate) type CheckFactory<M> = dyn Fn() -> Box<dyn MessageCheck<M>> + Send + Sync;
+
+/// Wraps a validator, every connection gets a history of `history_size` messages
+pub(crate) fn check_factory<M, V>(validator: V, history_size: usize) -> Arc<CheckFactory<M>>
+where
+    M: Clone + Send + 'static,
+    V: Validator<M>,
+{
+    let validator = Arc::new(validator);
+    Arc::new(move || {
+        Box::new(CheckedHistory {
+            validator: Arc::clone(&validator),
+            history: History::new(history_size),
+        })
+    })
+}
+
+/// Suspicion score of a player, which decays over time.
+pub(crate) struct Suspicion {
+    score: f64,
+    updated: Instant,
+    decay_per_second: u32,
+}
+
+impl Suspicion {
+    /// Starts a player without suspicion, losing `decay_per_second` points every second
+    pub(crate) fn new(decay_per_second: u32) -> Suspicion {
+        Suspicion {
+            score: 0.0,
+            updated: Instant::now(),
+            decay_per_second,
+        }
+    }
+
+    /// Adds to the score, returns the score after decay
+    pub(crate) fn add(&mut self, points: u32) -> u32 {
+        let now = Instant::now();
+        let decayed =
+            now.duration_since(self.updated).as_secs_f64() * f64::from(self.decay_per_second);
+        self.score = (self.score - decayed).max(0.0) + f64::from(points);
+        self.updated = now;
+        self.score as u32
+    }
+}
+
+/// What games need to validate messages and ban cheaters, shared by every game of the server.
+pub struct Moderation<M> {
+    pub(crate) check: Option<Arc<CheckFactory<M>>>,
+    pub(crate) bans: BanList,
+}
+
+impl<M> Clone for Moderation<M> {
+    fn clone(&self) -> Moderation<M> {
+        Moderation {
+            check: self.check.clone(),
+            bans: self.bans.clone(),
+        }
+    }
+}
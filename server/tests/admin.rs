@@ -0,0 +1,243 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::game::{MatchPhase, PlayerEvent};
+use blizzard_server::protocol::{ClientPacket, Hello, LobbyPacket, RejectReason, ServerPacket};
+use blizzard_server::server::admin::{AdminClient, AdminCommand, AdminReply};
+use blizzard_server::server::config::{AdminAddress, ServerConfig};
+use blizzard_server::server::transport::Connection;
+use blizzard_server::server::BanEntry;
+
+use std::io::BufReader;
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn config(address: &AdminAddress) -> ServerConfig {
+    ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .admin(address.clone())
+        .build()
+        .unwrap()
+}
+
+fn tcp(port: u16) -> AdminAddress {
+    format!("127.0.0.1:{}", port).parse().unwrap()
+}
+
+/// Retry until the server thread has opened the admin interface
+fn admin(address: &AdminAddress) -> AdminClient {
+    for _ in 0..100 {
+        if let Ok(admin) = AdminClient::connect(address) {
+            return admin;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Could not connect to the admin interface at {}", address);
+}
+
+/// Reads packets until the connection closes, false if it stays open
+fn closes(player: &mut common::Player) -> bool {
+    for _ in 0..200 {
+        if common::receive(&mut player.reader).is_none() {
+            return true;
+        }
+    }
+    false
+}
+
+#[test]
+fn games_and_players_are_listed() {
+    let address = tcp(39101);
+    let transport = common::start_server(config(&address));
+    let ticket = common::find_game(&transport);
+    let player = common::join(&transport, &ticket);
+    let mut admin = admin(&address);
+
+    let games = match admin.send(&AdminCommand::Games).unwrap() {
+        AdminReply::Games(games) => games,
+        other => panic!("Unexpected reply: {:?}", other),
+    };
+    assert_eq!(games.len(), 1);
+    assert_eq!(games[0].port, ticket.port);
+    assert_eq!(games[0].players, 1);
+    assert!(!games[0].paused);
+
+    let players = match admin
+        .send(&AdminCommand::Players { port: ticket.port })
+        .unwrap()
+    {
+        AdminReply::Players(players) => players,
+        other => panic!("Unexpected reply: {:?}", other),
+    };
+    assert_eq!(players.len(), 1);
+    assert_eq!(players[0].player_id, player.id);
+    assert_eq!(players[0].username, "player");
+    assert!(players[0].connected);
+
+    let unknown = admin.send(&AdminCommand::Players { port: 1 }).unwrap();
+    assert_eq!(unknown, AdminReply::Error("No game in port 1".to_string()));
+}
+
+#[test]
+fn kicked_players_leave_and_cannot_resume() {
+    let address = tcp(39102);
+    let (handle_input, events) = common::recording_handler();
+    let transport = common::start_server_with(config(&address), handle_input);
+    let ticket = common::find_game(&transport);
+    let mut player = common::join(&transport, &ticket);
+    let mut admin = admin(&address);
+
+    // Events reach the game once its match runs
+    assert!(common::wait_for_phase(
+        &mut player.reader,
+        MatchPhase::Running
+    ));
+    let kick = AdminCommand::Kick {
+        port: ticket.port,
+        player_id: player.id,
+    };
+    assert_eq!(admin.send(&kick).unwrap(), AdminReply::Done);
+    assert!(closes(&mut player));
+    loop {
+        match events.recv_timeout(TIMEOUT).unwrap() {
+            (PlayerEvent::Left, id) if id == player.id => break,
+            (PlayerEvent::Disconnected, _) => panic!("Kicked player was held"),
+            _ => continue,
+        }
+    }
+
+    let mut connection = common::connect(&transport, ticket.port);
+    let mut reader = BufReader::new(connection.try_clone().unwrap());
+    common::send(
+        &mut connection,
+        &ClientPacket::Resume {
+            token: player.token.clone(),
+        },
+    );
+    match common::receive(&mut reader) {
+        Some(ServerPacket::Rejected { .. }) => {}
+        other => panic!("Kicked player resumed: {:?}", other),
+    }
+}
+
+#[test]
+fn paused_games_stop_updating_and_players_get_notices() {
+    let address = tcp(39103);
+    let transport = common::start_server(config(&address));
+    let ticket = common::find_game(&transport);
+    let mut player = common::join(&transport, &ticket);
+    let mut admin = admin(&address);
+
+    common::send(
+        &mut player.connection,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 1,
+        },
+    );
+    assert_eq!(common::wait_for_state(&mut player.reader, 1), 1);
+
+    let pause = AdminCommand::Pause { port: ticket.port };
+    assert_eq!(admin.send(&pause).unwrap(), AdminReply::Done);
+    thread::sleep(Duration::from_millis(100));
+    common::send(
+        &mut player.connection,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 2,
+        },
+    );
+    thread::sleep(Duration::from_millis(200));
+
+    // States sent before the notice were played while paused
+    let broadcast = AdminCommand::Broadcast {
+        port: None,
+        message: "Paused for maintenance".to_string(),
+    };
+    assert_eq!(admin.send(&broadcast).unwrap(), AdminReply::Done);
+    loop {
+        match common::receive(&mut player.reader) {
+            Some(ServerPacket::Notice(notice)) => {
+                assert_eq!(notice, "Paused for maintenance");
+                break;
+            }
+            Some(ServerPacket::State { state, .. }) => assert_eq!(state, 1),
+            Some(_) => continue,
+            None => panic!("Connection closed"),
+        }
+    }
+    match admin.send(&AdminCommand::Games).unwrap() {
+        AdminReply::Games(games) => assert!(games[0].paused),
+        other => panic!("Unexpected reply: {:?}", other),
+    }
+
+    let unpause = AdminCommand::Unpause { port: ticket.port };
+    assert_eq!(admin.send(&unpause).unwrap(), AdminReply::Done);
+    assert_eq!(common::wait_for_state(&mut player.reader, 3), 3);
+}
+
+#[test]
+fn banned_players_are_kicked_and_turned_away() {
+    let address = tcp(39104);
+    let transport = common::start_server(config(&address));
+    let mut player = common::join(&transport, &common::find_game(&transport));
+    let mut admin = admin(&address);
+
+    let ban = AdminCommand::Ban {
+        entry: BanEntry::Username("player".to_string()),
+    };
+    assert_eq!(admin.send(&ban).unwrap(), AdminReply::Done);
+    assert!(closes(&mut player));
+    assert_eq!(
+        common::hello(&transport, &Hello::new("player")),
+        LobbyPacket::Rejected(RejectReason::Banned)
+    );
+
+    let unban = AdminCommand::Unban {
+        entry: BanEntry::Username("player".to_string()),
+    };
+    assert_eq!(admin.send(&unban).unwrap(), AdminReply::Done);
+    common::find_game(&transport);
+}
+
+#[test]
+fn operators_are_disconnected_when_the_server_shuts_down() {
+    let address = tcp(39105);
+    let (_transport, server) = common::start_server_handle(config(&address), &common::handle_input);
+    let mut admin = admin(&address);
+    assert!(admin.send(&AdminCommand::Games).is_ok());
+
+    // Shutting down waits for the thread of the connection, which the interface closes
+    server.shutdown("Maintenance");
+    assert!(admin.send(&AdminCommand::Games).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn shutdown_games_close_their_players() {
+    let path = std::env::temp_dir().join(format!("blizzard-admin-{}.sock", std::process::id()));
+    let address = AdminAddress::Unix(path.clone());
+    let transport = common::start_server(config(&address));
+    let ticket = common::find_game(&transport);
+    let mut player = common::join(&transport, &ticket);
+    let mut admin = admin(&address);
+
+    let shutdown = AdminCommand::Shutdown { port: ticket.port };
+    assert_eq!(admin.send(&shutdown).unwrap(), AdminReply::Done);
+    assert!(closes(&mut player));
+    assert_eq!(
+        admin.send(&AdminCommand::Games).unwrap(),
+        AdminReply::Games(vec![])
+    );
+
+    // The lobby starts a new game for the next player, once the port is closed
+    thread::sleep(Duration::from_millis(100));
+    common::join(&transport, &common::find_game(&transport));
+    std::fs::remove_file(path).unwrap_or(());
+}
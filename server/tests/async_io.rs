@@ -0,0 +1,179 @@
+//! Event-driven transport, run with `cargo test --features async-io`
+#![cfg(feature = "async-io")]
+
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::protocol::{ClientPacket, Hello, LobbyPacket, ServerPacket};
+use blizzard_server::server::error::ServerError;
+use blizzard_server::server::transport::{
+    AsyncTcpConnection, AsyncTcpTransport, Connection, LineHandler, Listener, Transport,
+};
+use blizzard_server::server::{Server, ServerConfig};
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Forwards lines, and the end of the connection as an empty line
+struct Forward(Sender<Vec<u8>>);
+
+impl LineHandler for Forward {
+    fn line(&mut self, line: &[u8]) -> Result<(), ServerError> {
+        self.0.send(line.to_vec()).unwrap_or(());
+        Ok(())
+    }
+
+    fn closed(self: Box<Self>, _: Result<(), ServerError>) {
+        self.0.send(vec![]).unwrap_or(());
+    }
+}
+
+fn localhost() -> AsyncTcpTransport {
+    AsyncTcpTransport::bound_to(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap()
+}
+
+#[test]
+fn event_loop_reads_and_writes() {
+    let transport = localhost();
+    let listener = transport.bind(0).unwrap();
+    let mut client = transport.connect(listener.port()).unwrap();
+    let server = listener.accept().unwrap();
+
+    // Bytes read before the handover are not lost
+    client.write_all(b"first\nsecond\nthi").unwrap();
+    let mut reader = BufReader::new(server.try_clone().unwrap());
+    let mut first = Vec::new();
+    reader.read_until(b'\n', &mut first).unwrap();
+    assert_eq!(first, b"first\n");
+
+    let (lines, read) = mpsc::channel();
+    AsyncTcpConnection::read_lines(reader, Box::new(Forward(lines)));
+    client.write_all(b"rd\n").unwrap();
+    let timeout = Duration::from_secs(5);
+    assert_eq!(read.recv_timeout(timeout).unwrap(), b"second\n");
+    assert_eq!(read.recv_timeout(timeout).unwrap(), b"third\n");
+
+    let outbox = server.outbox().unwrap();
+    outbox.push(Arc::new(b"pong\n".to_vec())).unwrap();
+    let mut reply = String::new();
+    BufReader::new(client.try_clone().unwrap())
+        .read_line(&mut reply)
+        .unwrap();
+    assert_eq!(reply, "pong\n");
+
+    // Hang ups end the connection
+    client.close().unwrap();
+    assert_eq!(read.recv_timeout(timeout).unwrap(), b"");
+    assert!(outbox.push(Arc::new(b"late\n".to_vec())).is_err());
+}
+
+#[test]
+fn shutdown_closes_the_connections_of_the_event_loop() {
+    let transport = localhost();
+    let listener = transport.bind(0).unwrap();
+    let client = transport.connect(listener.port()).unwrap();
+    let server = listener.accept().unwrap();
+
+    let (lines, read) = mpsc::channel();
+    AsyncTcpConnection::read_lines(BufReader::new(server), Box::new(Forward(lines)));
+    transport.shutdown();
+    assert_eq!(read.recv_timeout(Duration::from_secs(5)).unwrap(), b"");
+
+    let mut reply = String::new();
+    let read = BufReader::new(client).read_line(&mut reply);
+    assert!(matches!(read, Ok(0) | Err(_)));
+}
+
+#[test]
+fn server_runs_on_event_loop() {
+    let config = ServerConfig::builder()
+        .lobby_port(38888)
+        .ephemeral_game_ports()
+        .max_games(1)
+        .max_players(2)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .build()
+        .unwrap();
+    let transport = localhost();
+    let server_transport = transport.clone();
+    thread::spawn(move || {
+        Server::with_transport(
+            server_transport,
+            config,
+            common::CountGame {},
+            0,
+            0,
+            &common::handle_input,
+        );
+    });
+
+    let mut players = vec![];
+    for _ in 0..2 {
+        let mut lobby = connect(&transport, 38888);
+        send(&mut lobby, &Hello::new("player"));
+        let ticket = match receive::<LobbyPacket>(&mut BufReader::new(lobby)) {
+            LobbyPacket::Welcome(ticket) => ticket,
+            other => panic!("Lobby rejected the client: {:?}", other),
+        };
+
+        let mut game = connect(&transport, ticket.port);
+        send(&mut game, &ClientPacket::<u32>::Join { ticket: ticket.id });
+        let mut reader = BufReader::new(game.try_clone().unwrap());
+        match receive::<ServerPacket<u32>>(&mut reader) {
+            ServerPacket::Session { .. } => players.push((game, reader)),
+            other => panic!("Could not join: {:?}", other),
+        }
+    }
+
+    // Both players get the state with the input of the other
+    send(
+        &mut players[0].0,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 4,
+        },
+    );
+    send(
+        &mut players[1].0,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 3,
+        },
+    );
+    for (_, reader) in &mut players {
+        loop {
+            if let ServerPacket::State { state: 7, .. } = receive::<ServerPacket<u32>>(reader) {
+                break;
+            }
+        }
+    }
+}
+
+/// Retry until the server thread has opened the port
+fn connect(transport: &AsyncTcpTransport, port: i32) -> AsyncTcpConnection {
+    for _ in 0..100 {
+        if let Ok(connection) = transport.connect(port) {
+            return connection;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Could not connect to port {}", port);
+}
+
+fn send<P: serde::Serialize>(connection: &mut AsyncTcpConnection, packet: &P) {
+    let json = serde_json::to_string(packet).unwrap() + "\n";
+    connection.write_all(json.as_bytes()).unwrap();
+}
+
+fn receive<P: serde::de::DeserializeOwned>(reader: &mut BufReader<AsyncTcpConnection>) -> P {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    serde_json::from_str(line.trim()).unwrap()
+}
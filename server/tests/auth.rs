@@ -0,0 +1,181 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::game::{Identity, PlayerEvent};
+use blizzard_server::protocol::{Hello, LobbyPacket, RejectReason};
+use blizzard_server::server::auth::{AllowList, SharedSecret, SignedToken};
+use blizzard_server::server::config::ServerConfigBuilder;
+use blizzard_server::server::transport::ChannelTransport;
+use blizzard_server::server::{BanEntry, ServerConfig};
+
+use std::time::{Duration, SystemTime};
+
+const KEY: &[u8] = b"key shared with the backend";
+
+fn start_server(builder: ServerConfigBuilder) -> ChannelTransport {
+    common::start_server(builder.max_games(1).build().unwrap())
+}
+
+fn hello(username: &str, credentials: Option<String>) -> Hello {
+    let mut hello = Hello::new(username);
+    hello.credentials = credentials;
+    hello
+}
+
+#[test]
+fn shared_secret_lets_in_clients_that_know_it() {
+    let (handle_input, received) = common::recording_handler();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .authenticator(SharedSecret::new("s3cret"))
+        .build()
+        .unwrap();
+    let transport = common::start_server_with(config, handle_input);
+
+    for credentials in [None, Some("guess".to_string())].iter().cloned() {
+        assert_eq!(
+            common::hello(&transport, &hello("alice", credentials)),
+            LobbyPacket::Rejected(RejectReason::Unauthorized)
+        );
+    }
+
+    let ticket = match common::hello(&transport, &hello("alice", Some("s3cret".to_string()))) {
+        LobbyPacket::Welcome(ticket) => ticket,
+        other => panic!("Lobby rejected the client: {:?}", other),
+    };
+    common::join(&transport, &ticket);
+    match received.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (PlayerEvent::Joined(info), _) => {
+            // Anyone with the secret can claim a username, it is not an account
+            let identity = info.identity.unwrap();
+            assert_eq!(identity.account_id, None);
+            assert!(identity.roles.is_empty());
+        }
+        other => panic!("Expected a join event: {:?}", other),
+    }
+}
+
+#[test]
+fn signed_tokens_carry_account_and_roles() {
+    let (handle_input, received) = common::recording_handler();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .authenticator(SignedToken::new(KEY))
+        .build()
+        .unwrap();
+    let transport = common::start_server_with(config, handle_input);
+    let tokens = SignedToken::new(KEY);
+    let tomorrow = SystemTime::now() + Duration::from_secs(24 * 3600);
+
+    let token = tokens
+        .sign("account-42", &["admin", "moderator"], tomorrow)
+        .unwrap();
+    let ticket = match common::hello(&transport, &hello("alice", Some(token))) {
+        LobbyPacket::Welcome(ticket) => ticket,
+        other => panic!("Lobby rejected the client: {:?}", other),
+    };
+    common::join(&transport, &ticket);
+    match received.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (PlayerEvent::Joined(info), _) => {
+            let identity = info.identity.unwrap();
+            assert_eq!(identity.account_id.as_deref(), Some("account-42"));
+            assert!(identity.has_role("moderator"));
+            assert!(!identity.has_role("owner"));
+        }
+        other => panic!("Expected a join event: {:?}", other),
+    }
+
+    // Expired, forged and tampered tokens are refused
+    let expired = tokens
+        .sign(
+            "account-42",
+            &[],
+            SystemTime::now() - Duration::from_secs(1),
+        )
+        .unwrap();
+    let forged = SignedToken::new(b"other key")
+        .sign("account-42", &[], tomorrow)
+        .unwrap();
+    let tampered =
+        tokens
+            .sign("account-42", &[], tomorrow)
+            .unwrap()
+            .replacen("account-42", "account-43", 1);
+    for token in [expired, forged, tampered, "garbage".to_string()]
+        .iter()
+        .cloned()
+    {
+        assert_eq!(
+            common::hello(&transport, &hello("alice", Some(token))),
+            LobbyPacket::Rejected(RejectReason::Unauthorized)
+        );
+    }
+}
+
+#[test]
+fn signed_tokens_keep_claims_apart() {
+    let tokens = SignedToken::new(KEY);
+    let tomorrow = SystemTime::now() + Duration::from_secs(3600);
+    assert!(tokens.sign("account:42", &[], tomorrow).is_err());
+    assert!(tokens
+        .sign("account-42", &["admin,owner"], tomorrow)
+        .is_err());
+    assert!(tokens
+        .sign("account-42", &["admin:owner"], tomorrow)
+        .is_err());
+    assert!(tokens.sign("account-42", &[""], tomorrow).is_err());
+}
+
+#[test]
+fn allow_list_lets_in_listed_players_with_their_secret() {
+    let (handle_input, received) = common::recording_handler();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .authenticator(AllowList::new().allow("alice", "s3cret", &["admin"]))
+        .build()
+        .unwrap();
+    let transport = common::start_server_with(config, handle_input);
+
+    // Claiming a listed username is not enough
+    for credentials in [None, Some("guess".to_string())].iter().cloned() {
+        assert_eq!(
+            common::hello(&transport, &hello("alice", credentials)),
+            LobbyPacket::Rejected(RejectReason::Unauthorized)
+        );
+    }
+    assert_eq!(
+        common::hello(&transport, &hello("mallory", Some("s3cret".to_string()))),
+        LobbyPacket::Rejected(RejectReason::Unauthorized)
+    );
+
+    let ticket = match common::hello(&transport, &hello("alice", Some("s3cret".to_string()))) {
+        LobbyPacket::Welcome(ticket) => ticket,
+        other => panic!("Lobby rejected the client: {:?}", other),
+    };
+    common::join(&transport, &ticket);
+    match received.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (PlayerEvent::Joined(info), _) => {
+            assert_eq!(info.identity, Some(Identity::new("alice", &["admin"])));
+        }
+        other => panic!("Expected a join event: {:?}", other),
+    }
+}
+
+#[test]
+fn banned_accounts_are_rejected_with_any_username() {
+    let transport = start_server(
+        ServerConfig::builder()
+            .authenticator(SignedToken::new(KEY))
+            .ban(BanEntry::Account("account-7".to_string())),
+    );
+    let tomorrow = SystemTime::now() + Duration::from_secs(3600);
+    let token = SignedToken::new(KEY)
+        .sign("account-7", &[], tomorrow)
+        .unwrap();
+    assert_eq!(
+        common::hello(&transport, &hello("new-name", Some(token))),
+        LobbyPacket::Rejected(RejectReason::Banned)
+    );
+}
@@ -0,0 +1,206 @@
+//! Shared test game: the shared state is the sum of every number sent by clients.
+
+#![allow(dead_code)]
+
+use blizzard_engine::game::Game;
+use blizzard_server::game::{InputHandler, MatchPhase, PlayerEvent};
+use blizzard_server::protocol::{ClientPacket, Hello, JoinTicket, LobbyPacket, ServerPacket};
+use blizzard_server::server::transport::{
+    ChannelConnection, ChannelTransport, Connection, Transport,
+};
+use blizzard_server::server::{Server, ServerConfig, ServerHandle};
+
+use std::io::{BufRead, BufReader, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const LOBBY_PORT: i32 = 8888;
+
+// Counts every message received from clients
+#[derive(Clone)]
+pub struct CountGame {}
+
+impl Game<u32, u32> for CountGame {
+    fn world_config(&mut self) {}
+    fn update(&mut self, input: u32, shared_state: Arc<Mutex<u32>>) {
+        *shared_state.lock().unwrap() += input;
+    }
+    fn reset_input(&mut self, input: Arc<Mutex<u32>>) {
+        *input.lock().unwrap() = 0;
+    }
+    fn render(&mut self) {}
+    fn end_game(&self) -> bool {
+        false
+    }
+}
+
+pub fn handle_input(receiver: Receiver<(PlayerEvent<u32>, usize)>, input: Arc<Mutex<u32>>) -> u32 {
+    for (event, _) in receiver {
+        if let PlayerEvent::Message { message, .. } = event {
+            *input.lock().unwrap() += message;
+        }
+    }
+    0
+}
+
+/// Events of players, with the id of their player
+pub type Events = Receiver<(PlayerEvent<u32>, usize)>;
+
+/// Input handler that forwards every event to the returned receiver
+pub fn recording_handler() -> (&'static InputHandler<u32, u32>, Events) {
+    let (events, received) = mpsc::channel();
+    let events = Mutex::new(events);
+    let handle_input = Box::leak(Box::new(
+        move |receiver: Receiver<(PlayerEvent<u32>, usize)>, _: Arc<Mutex<u32>>| {
+            for event in receiver {
+                events.lock().unwrap().send(event).unwrap_or(());
+            }
+            0
+        },
+    ));
+    (handle_input, received)
+}
+
+/// Fast rates, so tests don't wait long for states
+pub fn test_config() -> ServerConfig {
+    ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .build()
+        .unwrap()
+}
+
+/// Runs a count game server over channels
+pub fn start_server(config: ServerConfig) -> ChannelTransport {
+    start_server_with(config, &handle_input)
+}
+
+/// Runs a count game server over channels, with a custom input handler
+pub fn start_server_with(
+    config: ServerConfig,
+    handle_input: &'static InputHandler<u32, u32>,
+) -> ChannelTransport {
+    start_server_handle(config, handle_input).0
+}
+
+/// Runs a count game server over channels, with the handle that shuts it down
+pub fn start_server_handle(
+    config: ServerConfig,
+    handle_input: &'static InputHandler<u32, u32>,
+) -> (ChannelTransport, ServerHandle) {
+    let transport = ChannelTransport::new();
+    let server =
+        Server::with_transport(transport.clone(), config, CountGame {}, 0, 0, handle_input);
+    (transport, server)
+}
+
+/// Retry until the server thread has opened the port
+pub fn connect(transport: &ChannelTransport, port: i32) -> ChannelConnection {
+    for _ in 0..100 {
+        if let Ok(connection) = transport.connect(port) {
+            return connection;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Could not connect to port {}", port);
+}
+
+/// Sends a hello to the lobby, returns its answer
+pub fn hello(transport: &ChannelTransport, hello: &Hello) -> LobbyPacket {
+    let mut lobby = connect(transport, LOBBY_PORT);
+    let json = serde_json::to_string(hello).unwrap() + "\n";
+    lobby.write_all(json.as_bytes()).unwrap();
+    let mut line = String::new();
+    BufReader::new(lobby.try_clone().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    lobby.close().unwrap();
+    serde_json::from_str(line.trim()).unwrap()
+}
+
+/// Asks the lobby for a game, panics if the lobby rejects the client
+pub fn find_game(transport: &ChannelTransport) -> JoinTicket {
+    match hello(transport, &Hello::new("player")) {
+        LobbyPacket::Welcome(ticket) => ticket,
+        other => panic!("Lobby rejected the client: {:?}", other),
+    }
+}
+
+/// Sends a client packet
+pub fn send(connection: &mut ChannelConnection, packet: &ClientPacket<u32>) {
+    let json = serde_json::to_string(packet).unwrap() + "\n";
+    connection.write_all(json.as_bytes()).unwrap();
+}
+
+/// Reads a server packet, `None` once the connection is closed
+pub fn receive(reader: &mut BufReader<ChannelConnection>) -> Option<ServerPacket<u32>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+        return None;
+    }
+    Some(serde_json::from_str(line.trim()).unwrap())
+}
+
+/// A client inside a game
+pub struct Player {
+    pub connection: ChannelConnection,
+    pub reader: BufReader<ChannelConnection>,
+    pub id: usize,
+    pub token: String,
+}
+
+/// Joins or resumes a game, panics if the game rejects the client
+pub fn enter(transport: &ChannelTransport, port: i32, packet: ClientPacket<u32>) -> Player {
+    let mut connection = connect(transport, port);
+    let mut reader = BufReader::new(connection.try_clone().unwrap());
+    send(&mut connection, &packet);
+    match receive(&mut reader) {
+        Some(ServerPacket::Session { player_id, token }) => Player {
+            connection,
+            reader,
+            id: player_id,
+            token,
+        },
+        other => panic!("Could not enter game: {:?}", other),
+    }
+}
+
+/// Joins a game as a new player, with a ticket from the lobby
+pub fn join(transport: &ChannelTransport, ticket: &JoinTicket) -> Player {
+    let packet = ClientPacket::Join {
+        ticket: ticket.id.clone(),
+    };
+    enter(transport, ticket.port, packet)
+}
+
+/// Reads states until one matches, returns the last state read
+pub fn wait_for_state(reader: &mut BufReader<ChannelConnection>, expected: u32) -> u32 {
+    let mut state = 0;
+    for _ in 0..200 {
+        match receive(reader) {
+            Some(ServerPacket::State {
+                state: received, ..
+            }) => state = received,
+            Some(_) => continue,
+            None => break,
+        }
+        if state == expected {
+            break;
+        }
+    }
+    state
+}
+
+/// Reads packets until the phase arrives, false if the connection closes first
+pub fn wait_for_phase(reader: &mut BufReader<ChannelConnection>, phase: MatchPhase) -> bool {
+    loop {
+        match receive(reader) {
+            Some(ServerPacket::Phase(received)) if received == phase => return true,
+            Some(_) => continue,
+            None => return false,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+extern crate blizzard_server;
+
+use blizzard_server::server::config::{AdminAddress, ConfigError, GamePorts, ServerConfig};
+use blizzard_server::server::error::{FloodPolicy, SlowClientPolicy};
+use blizzard_server::server::rate_limit::RateLimit;
+use blizzard_server::server::BanEntry;
+
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[test]
+fn default_config() {
+    let config = ServerConfig::builder().build().unwrap();
+    assert_eq!(config.lobby_port, 8888);
+    assert_eq!(config.game_ports.port(0), 7000);
+    assert_eq!(config.game_ports.port(3), 7003);
+}
+
+#[test]
+fn load_toml() {
+    let config = ServerConfig::from_toml_str(
+        r#"
+        bind_address = "::"
+        lobby_port = 9000
+        game_ports = "ephemeral"
+        max_games = 16
+        lobby_timeout_ms = 500
+        max_lobby_connections = 16
+        reservation_timeout_ms = 250
+        shutdown_timeout_ms = 2000
+        ready_check = true
+        message_limit = { per_second = 30, burst = 60 }
+        flood_policy = "kick"
+        history_size = 8
+        kick_suspicion = 50
+        ban_suspicion = 200
+        heartbeat_interval_ms = 500
+        client_timeout_ms = 3000
+        send_queue_limit = 8
+        slow_client = "disconnect"
+        public_host = "games.example.com"
+        banned = ["user:cheater", "addr:203.0.113.7"]
+        matchmaker = { rating_buckets = { bucket_size = 100 } }
+        authenticator = { allow_list = { alice = { secret = "s3cret", roles = ["admin"] } } }
+        admin = { tcp = "127.0.0.1:9999" }
+        metrics_address = "127.0.0.1:9100"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.bind_address, IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+    assert_eq!(config.lobby_port, 9000);
+    assert_eq!(config.game_ports, GamePorts::Ephemeral);
+    assert_eq!(config.game_ports.port(5), 0);
+    assert_eq!(config.max_games, 16);
+    assert_eq!(config.max_players, 2);
+    assert_eq!(config.lobby_timeout, Duration::from_millis(500));
+    assert_eq!(config.max_lobby_connections, 16);
+    assert_eq!(config.reservation_timeout, Duration::from_millis(250));
+    assert_eq!(config.shutdown_timeout, Duration::from_secs(2));
+    assert!(config.ready_check);
+    assert_eq!(config.message_limit, Some(RateLimit::new(30, 60)));
+    assert_eq!(config.byte_limit, None);
+    assert_eq!(config.flood_policy, FloodPolicy::Kick);
+    assert_eq!(config.history_size, 8);
+    assert_eq!(config.suspicion_decay, 1);
+    assert_eq!(config.kick_suspicion, Some(50));
+    assert_eq!(config.ban_suspicion, Some(200));
+    assert_eq!(config.heartbeat_interval, Duration::from_millis(500));
+    assert_eq!(config.client_timeout, Duration::from_secs(3));
+    assert_eq!(config.send_queue_limit, 8);
+    assert_eq!(config.slow_client, SlowClientPolicy::Disconnect);
+    assert_eq!(config.public_host, Some("games.example.com".to_string()));
+    assert_eq!(
+        config.banned,
+        vec![
+            BanEntry::Username("cheater".to_string()),
+            BanEntry::Address("203.0.113.7".to_string())
+        ]
+    );
+    assert!(format!("{:?}", config.matchmaker).contains("RatingBuckets"));
+    assert!(format!("{:?}", config.authenticator).contains("alice"));
+    assert!(!format!("{:?}", config.authenticator).contains("s3cret"));
+    let admin = AdminAddress::Tcp("127.0.0.1:9999".parse().unwrap());
+    assert_eq!(config.admin, Some(admin));
+    assert_eq!(
+        config.metrics_address,
+        Some("127.0.0.1:9100".parse().unwrap())
+    );
+}
+
+#[test]
+fn load_toml_range() {
+    let config =
+        ServerConfig::from_toml_str("game_ports = { range = { first = 6000, last = 6003 } }")
+            .unwrap();
+    assert_eq!(
+        config.game_ports,
+        GamePorts::Range {
+            first: 6000,
+            last: 6003
+        }
+    );
+}
+
+#[test]
+fn load_toml_suspicion_off() {
+    let config =
+        ServerConfig::from_toml_str("kick_suspicion = \"off\"\nban_suspicion = 300").unwrap();
+    assert_eq!(config.kick_suspicion, None);
+    assert_eq!(config.ban_suspicion, Some(300));
+
+    let unknown = ServerConfig::from_toml_str("kick_suspicion = \"never\"");
+    assert!(matches!(unknown, Err(ConfigError::Parse(_))));
+}
+
+#[test]
+fn reject_invalid_config() {
+    let too_many_games = ServerConfig::builder()
+        .game_port_range(7000, 7001)
+        .max_games(4)
+        .build();
+    assert!(matches!(too_many_games, Err(ConfigError::Invalid(_))));
+
+    let lobby_in_range = ServerConfig::builder()
+        .lobby_port(7002)
+        .game_port_range(7000, 7010)
+        .build();
+    assert!(matches!(lobby_in_range, Err(ConfigError::Invalid(_))));
+
+    let any_lobby_port = ServerConfig::builder().lobby_port(0).build();
+    assert!(matches!(any_lobby_port, Err(ConfigError::Invalid(_))));
+
+    let too_few_games = ServerConfig::builder().max_games(2).min_games(3).build();
+    assert!(matches!(too_few_games, Err(ConfigError::Invalid(_))));
+
+    let no_rate = ServerConfig::builder().send_data_rate(0).build();
+    assert!(matches!(no_rate, Err(ConfigError::Invalid(_))));
+
+    let no_heartbeat = ServerConfig::builder()
+        .heartbeat_interval(Duration::from_secs(2))
+        .client_timeout(Duration::from_secs(1))
+        .build();
+    assert!(matches!(no_heartbeat, Err(ConfigError::Invalid(_))));
+
+    let small_burst = ServerConfig::builder()
+        .max_message_size(1024)
+        .byte_limit(4096, 512)
+        .build();
+    assert!(matches!(small_burst, Err(ConfigError::Invalid(_))));
+
+    let public_admin = ServerConfig::builder()
+        .admin(AdminAddress::Tcp("0.0.0.0:9999".parse().unwrap()))
+        .build();
+    assert!(matches!(public_admin, Err(ConfigError::Invalid(_))));
+
+    let unknown_key = ServerConfig::from_toml_str("max_game = 3");
+    assert!(matches!(unknown_key, Err(ConfigError::Parse(_))));
+
+    let unprefixed_ban = ServerConfig::from_toml_str(r#"banned = ["cheater"]"#);
+    assert!(matches!(unprefixed_ban, Err(ConfigError::Parse(_))));
+}
+
+#[test]
+fn load_toml_tls() {
+    let config =
+        ServerConfig::from_toml_str(r#"tls = { certificate = "cert.pem", key = "key.pem" }"#);
+    if cfg!(feature = "tls") {
+        let tls = config.unwrap().tls.unwrap();
+        assert_eq!(tls.certificate, PathBuf::from("cert.pem"));
+        assert_eq!(tls.key, PathBuf::from("key.pem"));
+    } else {
+        assert!(matches!(config, Err(ConfigError::Invalid(_))));
+    }
+}
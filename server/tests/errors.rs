@@ -0,0 +1,125 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::game::{MatchPhase, PlayerEvent};
+use blizzard_server::protocol::ClientPacket;
+use blizzard_server::server::error::{ErrorSource, MalformedInputPolicy};
+use blizzard_server::server::transport::Connection;
+use blizzard_server::server::{ServerConfig, ServerError};
+
+use std::io::{BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn malformed_message_only_disconnects_sender() {
+    let (errors, reported) = mpsc::channel();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .on_error(move |error, source| {
+            if let ServerError::MalformedMessage(_) = error {
+                errors.send(source).unwrap_or(());
+            }
+        })
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+    let mut good = common::join(&transport, &common::find_game(&transport));
+    let mut bad = common::join(&transport, &common::find_game(&transport));
+
+    // Bad client is reported and disconnected
+    bad.connection.write_all(b"not json\n").unwrap();
+    let source = reported.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        source,
+        ErrorSource::Player {
+            port: 7000,
+            player_id: bad.id
+        }
+    );
+    let mut rest = Vec::new();
+    bad.reader.read_to_end(&mut rest).unwrap();
+
+    // Good client still plays
+    common::send(
+        &mut good.connection,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 3,
+        },
+    );
+    assert_eq!(common::wait_for_state(&mut good.reader, 3), 3);
+
+    // Freed slot can be joined again
+    let ticket = common::find_game(&transport);
+    assert_eq!(ticket.port, 7000);
+    common::join(&transport, &ticket);
+}
+
+#[test]
+fn malformed_messages_are_tolerated() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .malformed_input(MalformedInputPolicy::Tolerate(2))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+    let mut player = common::join(&transport, &common::find_game(&transport));
+    player
+        .connection
+        .write_all(b"{\n\xff\xfe\n{\"Input\":{\"message\":4}}\n")
+        .unwrap();
+    assert_eq!(common::wait_for_state(&mut player.reader, 4), 4);
+
+    // Third malformed message is one too many
+    common::send(
+        &mut player.connection,
+        &ClientPacket::Join {
+            ticket: String::new(),
+        },
+    );
+    let mut rest = Vec::new();
+    player.reader.read_to_end(&mut rest).unwrap();
+    player.connection.close().unwrap();
+}
+
+/// Stops taking events as soon as the match starts
+fn deaf_handler(_: Receiver<(PlayerEvent<u32>, usize)>, _: Arc<Mutex<u32>>) -> u32 {
+    0
+}
+
+#[test]
+fn players_joining_a_closed_app_give_back_their_slot() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .max_players(2)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .build()
+        .unwrap();
+    let transport = common::start_server_with(config, &deaf_handler);
+    let mut first = common::join(&transport, &common::find_game(&transport));
+    assert!(common::wait_for_phase(
+        &mut first.reader,
+        MatchPhase::Running
+    ));
+    thread::sleep(Duration::from_millis(100));
+
+    // The app can't hear of the second player, which is closed without a session
+    let ticket = common::find_game(&transport);
+    let mut second = common::connect(&transport, ticket.port);
+    let mut reader = BufReader::new(second.try_clone().unwrap());
+    common::send(&mut second, &ClientPacket::Join { ticket: ticket.id });
+    assert!(common::receive(&mut reader).is_none());
+
+    // Its slot is free again
+    common::find_game(&transport);
+}
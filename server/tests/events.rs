@@ -0,0 +1,158 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::game::{PlayerEvent, PlayerInfo};
+use blizzard_server::protocol::{ClientPacket, Hello, LobbyPacket, RejectReason, ServerPacket};
+use blizzard_server::server::transport::Connection;
+use blizzard_server::server::ServerConfig;
+
+use std::collections::HashMap;
+use std::io::BufReader;
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Info of players joining with `common::find_game`
+fn joined() -> PlayerInfo {
+    PlayerInfo {
+        username: "player".to_string(),
+        metadata: HashMap::new(),
+        identity: None,
+    }
+}
+
+#[test]
+fn server_sends_join_and_leave_events() {
+    let (handle_input, received) = common::recording_handler();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .reconnect_grace(Duration::from_secs(0))
+        .build()
+        .unwrap();
+    let transport = common::start_server_with(config, handle_input);
+    let mut player = common::join(&transport, &common::find_game(&transport));
+    let id = player.id;
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        (PlayerEvent::Joined(joined()), id)
+    );
+
+    common::send(
+        &mut player.connection,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 7,
+        },
+    );
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        (
+            PlayerEvent::Message {
+                tick: 0,
+                message: 7
+            },
+            id
+        )
+    );
+
+    // Dropped connection tells the game, without a message from the client
+    player.connection.close().unwrap();
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        (PlayerEvent::Left, id)
+    );
+}
+
+#[test]
+fn player_resumes_session_with_token() {
+    let (handle_input, received) = common::recording_handler();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .max_players(1)
+        .reconnect_grace(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    let transport = common::start_server_with(config, handle_input);
+    let ticket = common::find_game(&transport);
+    let port = ticket.port;
+
+    let player = common::join(&transport, &ticket);
+    let id = player.id;
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        (PlayerEvent::Joined(joined()), id)
+    );
+
+    player.connection.close().unwrap();
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        (PlayerEvent::Disconnected, id)
+    );
+
+    // Slot is held, the game is still full
+    assert_eq!(
+        common::hello(&transport, &Hello::new("stranger")),
+        LobbyPacket::Rejected(RejectReason::Full)
+    );
+
+    // Same player id after resuming
+    let resumed = common::enter(
+        &transport,
+        port,
+        ClientPacket::Resume {
+            token: player.token.clone(),
+        },
+    );
+    assert_eq!(resumed.id, id);
+    assert_eq!(
+        received.recv_timeout(TIMEOUT).unwrap(),
+        (PlayerEvent::Reconnected, id)
+    );
+}
+
+#[test]
+fn session_expires_after_grace_period() {
+    let (handle_input, received) = common::recording_handler();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .reconnect_grace(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let transport = common::start_server_with(config, handle_input);
+    let ticket = common::find_game(&transport);
+    let port = ticket.port;
+
+    let player = common::join(&transport, &ticket);
+    let id = player.id;
+    player.connection.close().unwrap();
+
+    let events: Vec<_> = (0..3)
+        .map(|_| received.recv_timeout(TIMEOUT).unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            (PlayerEvent::Joined(joined()), id),
+            (PlayerEvent::Disconnected, id),
+            (PlayerEvent::Left, id)
+        ]
+    );
+
+    // Expired token is rejected
+    thread::sleep(Duration::from_millis(10));
+    let mut late = common::connect(&transport, port);
+    let mut reader = BufReader::new(late.try_clone().unwrap());
+    common::send(
+        &mut late,
+        &ClientPacket::Resume {
+            token: player.token,
+        },
+    );
+    assert!(matches!(
+        common::receive(&mut reader),
+        Some(ServerPacket::Rejected { .. })
+    ));
+}
@@ -0,0 +1,132 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::protocol::ClientPacket;
+use blizzard_server::server::config::ServerConfigBuilder;
+use blizzard_server::server::error::{ErrorSource, FloodPolicy};
+use blizzard_server::server::{ServerConfig, ServerError};
+
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// Starts a server that reports rate limit and message size errors
+fn start_server(builder: ServerConfigBuilder) -> (common::Player, Receiver<String>) {
+    let (errors, reported) = mpsc::channel();
+    let config = builder
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .on_error(move |error, source| {
+            if let ErrorSource::Player { .. } = source {
+                errors.send(error.to_string()).unwrap_or(());
+            }
+        })
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+    let player = common::join(&transport, &common::find_game(&transport));
+    (player, reported)
+}
+
+fn send_inputs(player: &mut common::Player, count: usize) {
+    for _ in 0..count {
+        let input = ClientPacket::Input {
+            tick: 0,
+            message: 1,
+        };
+        common::send(&mut player.connection, &input);
+    }
+}
+
+#[test]
+fn flood_is_dropped() {
+    let limited = ServerConfig::builder().message_limit(1, 3);
+    let (mut player, reported) = start_server(limited);
+
+    // Burst gets through, the rest is dropped and reported once
+    send_inputs(&mut player, 10);
+    assert_eq!(common::wait_for_state(&mut player.reader, 3), 3);
+    assert_eq!(
+        reported.recv_timeout(Duration::from_secs(5)).unwrap(),
+        ServerError::RateLimited.to_string()
+    );
+    assert!(reported.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn flood_is_kicked() {
+    let limited = ServerConfig::builder()
+        .message_limit(1, 2)
+        .flood_policy(FloodPolicy::Kick);
+    let (mut player, reported) = start_server(limited);
+
+    send_inputs(&mut player, 5);
+    let mut rest = Vec::new();
+    player.reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(
+        reported.recv_timeout(Duration::from_secs(5)).unwrap(),
+        ServerError::RateLimited.to_string()
+    );
+}
+
+#[test]
+#[cfg(not(feature = "async-io"))]
+fn flood_is_throttled() {
+    use std::time::Instant;
+
+    let limited = ServerConfig::builder()
+        .message_limit(20, 1)
+        .flood_policy(FloodPolicy::Throttle);
+    let (mut player, reported) = start_server(limited);
+
+    // Every message arrives, one per 50ms
+    let started = Instant::now();
+    send_inputs(&mut player, 5);
+    assert_eq!(common::wait_for_state(&mut player.reader, 5), 5);
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    assert!(reported.try_recv().is_err());
+}
+
+#[test]
+#[cfg(feature = "async-io")]
+fn throttling_is_rejected_on_the_event_loop() {
+    use blizzard_server::server::config::ConfigError;
+
+    let throttled = ServerConfig::builder()
+        .message_limit(20, 1)
+        .flood_policy(FloodPolicy::Throttle)
+        .build();
+    assert!(matches!(throttled, Err(ConfigError::Invalid(_))));
+}
+
+#[test]
+fn acks_and_pongs_are_not_limited() {
+    let limited = ServerConfig::builder().message_limit(1, 1);
+    let (mut player, reported) = start_server(limited);
+
+    // The input still has the token of the burst
+    for tick in 0..5 {
+        common::send(&mut player.connection, &ClientPacket::Ack(tick));
+        common::send(&mut player.connection, &ClientPacket::Pong(tick));
+    }
+    send_inputs(&mut player, 1);
+    assert_eq!(common::wait_for_state(&mut player.reader, 1), 1);
+    assert!(reported.try_recv().is_err());
+}
+
+#[test]
+fn oversized_message_removes_client() {
+    let limited = ServerConfig::builder().max_message_size(64);
+    let (mut player, reported) = start_server(limited);
+
+    player.connection.write_all(&[b' '; 200]).unwrap();
+    let mut rest = Vec::new();
+    player.reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(
+        reported.recv_timeout(Duration::from_secs(5)).unwrap(),
+        ServerError::MessageTooLarge(64).to_string()
+    );
+}
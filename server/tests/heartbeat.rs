@@ -0,0 +1,77 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::game::PlayerEvent;
+use blizzard_server::protocol::{ClientPacket, ServerPacket};
+use blizzard_server::server::config::ServerConfigBuilder;
+use blizzard_server::server::error::ErrorSource;
+use blizzard_server::server::{ServerConfig, ServerError};
+
+use std::io::Read;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Pings every 20ms, players that stay silent for 200ms are gone
+fn heartbeat_config() -> ServerConfigBuilder {
+    ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .heartbeat_interval(Duration::from_millis(20))
+        .client_timeout(Duration::from_millis(200))
+}
+
+#[test]
+fn silent_player_times_out() {
+    let (errors, reported) = mpsc::channel();
+    let config = heartbeat_config()
+        .on_error(move |error, source| {
+            if let ServerError::ClientTimedOut = error {
+                errors.send(source).unwrap_or(());
+            }
+        })
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+    let mut player = common::join(&transport, &common::find_game(&transport));
+
+    let source = reported.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        source,
+        ErrorSource::Player {
+            port: 7000,
+            player_id: player.id
+        }
+    );
+    let mut rest = Vec::new();
+    player.reader.read_to_end(&mut rest).unwrap();
+}
+
+#[test]
+fn pongs_measure_latency() {
+    let (handle_input, received) = common::recording_handler();
+    let config = heartbeat_config().build().unwrap();
+    let transport = common::start_server_with(config, handle_input);
+    let mut player = common::join(&transport, &common::find_game(&transport));
+
+    // Answering pings keeps the player connected past the client timeout
+    let mut latencies = vec![];
+    while latencies.len() < 20 {
+        match common::receive(&mut player.reader) {
+            Some(ServerPacket::Ping(ping)) => {
+                common::send(&mut player.connection, &ClientPacket::Pong(ping))
+            }
+            Some(_) => {}
+            None => panic!("Player was disconnected"),
+        }
+        while let Ok((event, id)) = received.try_recv() {
+            if let PlayerEvent::Latency(latency) = event {
+                assert_eq!(id, player.id);
+                latencies.push(latency);
+            }
+        }
+    }
+    assert!(latencies.iter().all(|l| l.rtt < Duration::from_secs(1)));
+}
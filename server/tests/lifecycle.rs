@@ -0,0 +1,139 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_engine::game::Game;
+use blizzard_server::game::MatchPhase;
+use blizzard_server::protocol::{ClientPacket, ServerPacket};
+use blizzard_server::server::transport::ChannelTransport;
+use blizzard_server::server::{Server, ServerConfig};
+
+use std::io::Read;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Match ends once the players sent 10 in total
+#[derive(Clone)]
+struct FirstToTen {
+    total: u32,
+}
+
+impl Game<u32, u32> for FirstToTen {
+    fn world_config(&mut self) {}
+    fn update(&mut self, input: u32, shared_state: Arc<Mutex<u32>>) {
+        self.total += input;
+        *shared_state.lock().unwrap() += input;
+    }
+    fn reset_input(&mut self, input: Arc<Mutex<u32>>) {
+        *input.lock().unwrap() = 0;
+    }
+    fn render(&mut self) {}
+    fn end_game(&self) -> bool {
+        self.total >= 10
+    }
+}
+
+fn start_server(config: ServerConfig) -> ChannelTransport {
+    let transport = ChannelTransport::new();
+    let server_transport = transport.clone();
+    thread::spawn(move || {
+        Server::with_transport(
+            server_transport,
+            config,
+            FirstToTen { total: 0 },
+            0,
+            0,
+            &common::handle_input,
+        );
+    });
+    transport
+}
+
+#[test]
+fn finished_match_reports_and_resets() {
+    let (reports, reported) = mpsc::channel();
+    let reports = Mutex::new(reports);
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .on_match_end(move |report| reports.lock().unwrap().send(report.clone()).unwrap())
+        .build()
+        .unwrap();
+    let transport = start_server(config);
+
+    let mut player = common::join(&transport, &common::find_game(&transport));
+    assert!(common::wait_for_phase(
+        &mut player.reader,
+        MatchPhase::Running
+    ));
+    common::send(
+        &mut player.connection,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 10,
+        },
+    );
+
+    // Clients see the end and the final state, then are disconnected
+    assert!(common::wait_for_phase(
+        &mut player.reader,
+        MatchPhase::Finished
+    ));
+    assert!(matches!(
+        common::receive(&mut player.reader),
+        Some(ServerPacket::State { state: 10, .. })
+    ));
+    let mut rest = Vec::new();
+    player.reader.read_to_end(&mut rest).unwrap();
+
+    let report = reported.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(report.port, 7000);
+    assert_eq!(report.result, serde_json::json!(10));
+    assert_eq!(report.players.len(), 1);
+    assert_eq!(report.players[0].0, player.id);
+    assert!(!report.interrupted);
+
+    // Slot returns to the pool, with a new match from the prototype
+    let mut next = common::join(&transport, &common::find_game(&transport));
+    assert!(common::wait_for_phase(
+        &mut next.reader,
+        MatchPhase::Running
+    ));
+    assert!(matches!(
+        common::receive(&mut next.reader),
+        Some(ServerPacket::State { state: 0, .. })
+    ));
+}
+
+#[test]
+fn match_starts_after_min_players_and_countdown() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .min_players(2)
+        .countdown(Duration::from_millis(100))
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .build()
+        .unwrap();
+    let transport = start_server(config);
+
+    let mut first = common::join(&transport, &common::find_game(&transport));
+    assert_eq!(
+        common::receive(&mut first.reader),
+        Some(ServerPacket::Phase(MatchPhase::Waiting))
+    );
+
+    common::join(&transport, &common::find_game(&transport));
+    assert!(common::wait_for_phase(
+        &mut first.reader,
+        MatchPhase::Countdown { duration_ms: 100 }
+    ));
+    assert!(common::wait_for_phase(
+        &mut first.reader,
+        MatchPhase::Running
+    ));
+}
@@ -0,0 +1,265 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::game::PlayerEvent;
+use blizzard_server::protocol::{
+    ClientPacket, Hello, LobbyPacket, RejectReason, ServerPacket, PROTOCOL_VERSION,
+};
+use blizzard_server::server::transport::Connection;
+use blizzard_server::server::{BanEntry, ServerConfig};
+
+use std::io::{BufReader, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn game_receives_player_name_and_metadata() {
+    let (handle_input, received) = common::recording_handler();
+    let transport = common::start_server_with(common::test_config(), handle_input);
+
+    let mut hello = Hello::new("alice");
+    hello
+        .metadata
+        .insert("region".to_string(), "eu".to_string());
+    let ticket = match common::hello(&transport, &hello) {
+        LobbyPacket::Welcome(ticket) => ticket,
+        other => panic!("Lobby rejected the client: {:?}", other),
+    };
+    assert_eq!(ticket.host, None);
+
+    let player = common::join(&transport, &ticket);
+    match received.recv_timeout(Duration::from_secs(5)).unwrap() {
+        (PlayerEvent::Joined(info), id) => {
+            assert_eq!(id, player.id);
+            assert_eq!(info.username, "alice");
+            assert_eq!(info.metadata["region"], "eu");
+        }
+        other => panic!("Expected a join event: {:?}", other),
+    }
+}
+
+#[test]
+fn lobby_rejects_other_protocol_versions() {
+    let transport = common::start_server(common::test_config());
+
+    let mut hello = Hello::new("old");
+    hello.protocol_version = PROTOCOL_VERSION + 1;
+    assert_eq!(
+        common::hello(&transport, &hello),
+        LobbyPacket::Rejected(RejectReason::VersionMismatch {
+            server_version: PROTOCOL_VERSION
+        })
+    );
+}
+
+#[test]
+fn lobby_ignores_bytes_sent_after_the_hello() {
+    let transport = common::start_server(common::test_config());
+
+    let mut lobby = common::connect(&transport, common::LOBBY_PORT);
+    let json = serde_json::to_string(&Hello::new("eager")).unwrap();
+    lobby
+        .write_all(format!("{}\n{{\"ticket\":", json).as_bytes())
+        .unwrap();
+    let mut answer = String::new();
+    BufReader::new(lobby).read_to_string(&mut answer).unwrap();
+    match serde_json::from_str(answer.trim()).unwrap() {
+        LobbyPacket::Welcome(_) => {}
+        other => panic!("Lobby rejected the client: {:?}", other),
+    }
+}
+
+#[test]
+fn lobby_rejects_banned_players() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .ban(BanEntry::Username("cheater".to_string()))
+        .ban(BanEntry::Address("player".to_string()))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    assert_eq!(
+        common::hello(&transport, &Hello::new("cheater")),
+        LobbyPacket::Rejected(RejectReason::Banned)
+    );
+    // Bans of addresses don't apply to usernames
+    common::find_game(&transport);
+}
+
+#[test]
+fn lobby_rejects_when_games_are_full() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .max_players(1)
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    common::join(&transport, &common::find_game(&transport));
+    assert_eq!(
+        common::hello(&transport, &Hello::new("late")),
+        LobbyPacket::Rejected(RejectReason::Full)
+    );
+}
+
+#[test]
+fn game_rejects_unknown_tickets() {
+    let transport = common::start_server(common::test_config());
+    let ticket = common::find_game(&transport);
+
+    let mut connection = common::connect(&transport, ticket.port);
+    let mut reader = BufReader::new(connection.try_clone().unwrap());
+    common::send(
+        &mut connection,
+        &ClientPacket::Join {
+            ticket: "forged".to_string(),
+        },
+    );
+    assert!(matches!(
+        common::receive(&mut reader),
+        Some(ServerPacket::Rejected { .. })
+    ));
+
+    // Tickets work once
+    common::join(&transport, &ticket);
+    let mut connection = common::connect(&transport, ticket.port);
+    let mut reader = BufReader::new(connection.try_clone().unwrap());
+    common::send(&mut connection, &ClientPacket::Join { ticket: ticket.id });
+    assert!(matches!(
+        common::receive(&mut reader),
+        Some(ServerPacket::Rejected { .. })
+    ));
+}
+
+#[test]
+fn lobby_reserves_slots_for_tickets() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .max_players(1)
+        .reservation_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    // Second client is turned away before the first one reaches the game
+    let first = common::find_game(&transport);
+    assert_eq!(
+        common::hello(&transport, &Hello::new("second")),
+        LobbyPacket::Rejected(RejectReason::Full)
+    );
+
+    // Unused reservation expires and its slot goes to someone else
+    thread::sleep(Duration::from_millis(150));
+    let third = common::find_game(&transport);
+
+    let mut connection = common::connect(&transport, first.port);
+    let mut reader = BufReader::new(connection.try_clone().unwrap());
+    common::send(&mut connection, &ClientPacket::Join { ticket: first.id });
+    assert!(matches!(
+        common::receive(&mut reader),
+        Some(ServerPacket::Rejected { .. })
+    ));
+    common::join(&transport, &third);
+}
+
+#[test]
+fn idle_client_does_not_block_the_lobby() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .lobby_timeout(Duration::from_secs(30))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    // Connected, but never says hello
+    let _idle = common::connect(&transport, common::LOBBY_PORT);
+
+    let started = Instant::now();
+    common::find_game(&transport);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn lobby_drops_clients_that_miss_the_deadline() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .lobby_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    // Trickling bytes does not extend the deadline, late bytes may find the connection closed
+    let mut slow = common::connect(&transport, common::LOBBY_PORT);
+    for _ in 0..3 {
+        slow.write_all(b" ").unwrap_or(());
+        thread::sleep(Duration::from_millis(50));
+    }
+    let mut rest = Vec::new();
+    slow.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn busy_lobby_disconnects_extra_clients() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .lobby_timeout(Duration::from_secs(30))
+        .max_lobby_connections(1)
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    // The idle client holds the only place
+    let idle = common::connect(&transport, common::LOBBY_PORT);
+    let mut extra = common::connect(&transport, common::LOBBY_PORT);
+    let json = serde_json::to_string(&Hello::new("extra")).unwrap() + "\n";
+    extra.write_all(json.as_bytes()).unwrap_or(());
+    let mut rest = Vec::new();
+    extra.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    // Its place is free once it leaves
+    idle.close().unwrap();
+    thread::sleep(Duration::from_millis(100));
+    common::find_game(&transport);
+}
+
+#[test]
+fn game_ports_drop_clients_that_miss_the_deadline() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .lobby_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+    let ticket = common::find_game(&transport);
+
+    let mut slow = common::connect(&transport, ticket.port);
+    let started = Instant::now();
+    for _ in 0..4 {
+        slow.write_all(b" ").unwrap_or(());
+        thread::sleep(Duration::from_millis(50));
+    }
+    let mut rest = Vec::new();
+    slow.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn game_ports_reject_malformed_first_packets() {
+    let transport = common::start_server(common::test_config());
+    let ticket = common::find_game(&transport);
+
+    let mut connection = common::connect(&transport, ticket.port);
+    let mut reader = BufReader::new(connection.try_clone().unwrap());
+    connection.write_all(b"not json\n").unwrap();
+    assert!(matches!(
+        common::receive(&mut reader),
+        Some(ServerPacket::Rejected { .. })
+    ));
+    assert!(common::receive(&mut reader).is_none());
+}
@@ -0,0 +1,171 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::protocol::{Hello, JoinTicket, LobbyPacket, RejectReason};
+use blizzard_server::server::matchmaker::{
+    BalancedLoad, FillMostFull, Matchmaker, Parties, RatingBuckets, Regions,
+};
+use blizzard_server::server::transport::ChannelTransport;
+use blizzard_server::server::ServerConfig;
+
+use std::thread;
+use std::time::Duration;
+
+/// Server with two running games of three players
+fn start_server<M: Matchmaker + 'static>(matchmaker: M) -> ChannelTransport {
+    let config = ServerConfig::builder()
+        .max_games(2)
+        .min_games(2)
+        .max_players(3)
+        .matchmaker(matchmaker)
+        .build()
+        .unwrap();
+    common::start_server(config)
+}
+
+/// Port the lobby sends a player with the metadata to
+fn port_for(transport: &ChannelTransport, metadata: &[(&str, &str)]) -> i32 {
+    ticket_for(transport, metadata).port
+}
+
+/// Ticket the lobby gives a player with the metadata
+fn ticket_for(transport: &ChannelTransport, metadata: &[(&str, &str)]) -> JoinTicket {
+    let mut hello = Hello::new("player");
+    for (key, value) in metadata {
+        hello.metadata.insert(key.to_string(), value.to_string());
+    }
+    match common::hello(transport, &hello) {
+        LobbyPacket::Welcome(ticket) => ticket,
+        other => panic!("Lobby rejected the client: {:?}", other),
+    }
+}
+
+#[test]
+fn fill_most_full_fills_one_game_first() {
+    let transport = start_server(FillMostFull);
+    let ports: Vec<i32> = (0..4).map(|_| port_for(&transport, &[])).collect();
+    assert_eq!(ports, vec![7000, 7000, 7000, 7001]);
+}
+
+#[test]
+fn balanced_load_spreads_players() {
+    let transport = start_server(BalancedLoad);
+    let ports: Vec<i32> = (0..4).map(|_| port_for(&transport, &[])).collect();
+    assert_eq!(ports, vec![7000, 7001, 7000, 7001]);
+}
+
+#[test]
+fn rating_buckets_group_similar_players() {
+    let config = ServerConfig::builder()
+        .max_games(2)
+        .min_games(0)
+        .max_players(3)
+        .matchmaker(RatingBuckets::new(100))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    let low = port_for(&transport, &[("rating", "1510")]);
+    let high = port_for(&transport, &[("rating", "1890")]);
+    assert_ne!(low, high);
+    assert_eq!(port_for(&transport, &[("rating", "1550")]), low);
+    assert_eq!(port_for(&transport, &[("rating", "1800")]), high);
+}
+
+#[test]
+fn regions_keep_players_apart() {
+    let config = ServerConfig::builder()
+        .max_games(2)
+        .min_games(0)
+        .max_players(3)
+        .matchmaker(Regions::new())
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    let eu = port_for(&transport, &[("region", "eu")]);
+    let us = port_for(&transport, &[("region", "us")]);
+    assert_ne!(eu, us);
+    assert_eq!(port_for(&transport, &[("region", "eu")]), eu);
+}
+
+#[test]
+fn parties_stay_together() {
+    let transport = start_server(Parties::new());
+
+    // Game 7000 only has one slot left after these two
+    assert_eq!(port_for(&transport, &[]), 7000);
+    assert_eq!(port_for(&transport, &[]), 7000);
+
+    let party = [("party", "friends"), ("party_size", "2")];
+    assert_eq!(port_for(&transport, &party), 7001);
+    assert_eq!(port_for(&transport, &party), 7001);
+}
+
+#[test]
+fn tagging_matchmakers_use_games_started_with_the_pool() {
+    let config = ServerConfig::builder()
+        .max_games(2)
+        .min_games(2)
+        .max_players(3)
+        .matchmaker(RatingBuckets::new(100))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    let low = port_for(&transport, &[("rating", "1510")]);
+    let high = port_for(&transport, &[("rating", "1890")]);
+    assert_ne!(low, high);
+    assert_eq!(port_for(&transport, &[("rating", "1550")]), low);
+
+    // Both games are taken by a bucket
+    let mut hello = Hello::new("player");
+    hello.metadata.insert("rating".into(), "1200".into());
+    assert_eq!(
+        common::hello(&transport, &hello),
+        LobbyPacket::Rejected(RejectReason::Full)
+    );
+}
+
+#[test]
+fn parties_are_forgotten_after_the_timeout() {
+    let mut parties = Parties::new();
+    parties.timeout = Some(Duration::from_millis(50));
+    let transport = start_server(parties);
+
+    // Game 7000 only has one slot left after these two
+    let party = [("party", "friends"), ("party_size", "2")];
+    assert_eq!(port_for(&transport, &party), 7000);
+    assert_eq!(port_for(&transport, &[]), 7000);
+
+    // The rest of the party did not come in time, late members are matched like a new party
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(port_for(&transport, &party), 7001);
+}
+
+#[test]
+fn parties_are_waited_for_as_long_as_reservations() {
+    let config = ServerConfig::builder()
+        .max_games(2)
+        .min_games(2)
+        .max_players(3)
+        .matchmaker(Parties::new())
+        .reservation_timeout(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    // Players in game, so game 7000 keeps only one slot once the tickets expire
+    let party = [("party", "friends"), ("party_size", "2")];
+    let tickets = [ticket_for(&transport, &party), ticket_for(&transport, &[])];
+    assert!(tickets.iter().all(|ticket| ticket.port == 7000));
+    let _players: Vec<_> = tickets
+        .iter()
+        .map(|ticket| common::join(&transport, ticket))
+        .collect();
+
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(port_for(&transport, &party), 7001);
+}
@@ -0,0 +1,160 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_engine::core::network_application::UpdateTimes;
+use blizzard_server::protocol::ClientPacket;
+use blizzard_server::server::error::MalformedInputPolicy;
+use blizzard_server::server::metrics::{render, Counters, GameMetrics};
+use blizzard_server::server::ServerConfig;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const METRICS_ADDRESS: &str = "127.0.0.1:39201";
+
+/// Scrapes the metrics endpoint, retrying until the server opened it
+fn scrape() -> String {
+    for _ in 0..100 {
+        if let Ok(mut stream) = TcpStream::connect(METRICS_ADDRESS) {
+            stream
+                .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            return response;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Could not scrape {}", METRICS_ADDRESS);
+}
+
+/// Value of a sample of the response
+fn sample(response: &str, name: &str) -> f64 {
+    response
+        .lines()
+        .find(|line| line.starts_with(name) && line[name.len()..].starts_with(' '))
+        .and_then(|line| line[name.len() + 1..].parse().ok())
+        .unwrap_or_else(|| panic!("No sample {} in {}", name, response))
+}
+
+#[test]
+fn metrics_are_served_to_prometheus() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .malformed_input(MalformedInputPolicy::Ignore)
+        .metrics_address(METRICS_ADDRESS.parse().unwrap())
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+    let ticket = common::find_game(&transport);
+    let mut player = common::join(&transport, &ticket);
+
+    for _ in 0..3 {
+        let input = ClientPacket::Input {
+            tick: 0,
+            message: 1,
+        };
+        common::send(&mut player.connection, &input);
+    }
+    player.connection.write_all(b"not json\n").unwrap();
+    assert_eq!(common::wait_for_state(&mut player.reader, 3), 3);
+
+    let response = scrape();
+    assert_eq!(sample(&response, "blizzard_lobby_connections_total"), 1.0);
+    assert_eq!(sample(&response, "blizzard_lobby_rejections_total"), 0.0);
+    assert!(sample(&response, "blizzard_messages_received_total") >= 4.0);
+    assert_eq!(
+        sample(
+            &response,
+            "blizzard_messages_dropped_total{reason=\"malformed\"}"
+        ),
+        1.0
+    );
+    assert!(sample(&response, "blizzard_packets_sent_total") > 0.0);
+    assert!(sample(&response, "blizzard_sent_bytes_total") > 0.0);
+    assert_eq!(sample(&response, "blizzard_games"), 1.0);
+
+    let game = format!("{{port=\"{}\"}}", ticket.port);
+    assert_eq!(
+        sample(&response, &format!("blizzard_game_players{}", game)),
+        1.0
+    );
+    assert!(sample(&response, &format!("blizzard_game_updates_total{}", game)) > 0.0);
+}
+
+#[test]
+fn metrics_are_written_in_text_format() {
+    let counters = Counters {
+        lobby_connections: 3,
+        dropped_flood: 2,
+        ..Counters::default()
+    };
+    let mut updates = UpdateTimes::default();
+    updates.record(Duration::from_millis(2));
+    updates.record(Duration::from_millis(4));
+    let games = [GameMetrics {
+        port: 7000,
+        players: 2,
+        updates,
+    }];
+
+    let text = render(&counters, &games);
+    assert!(text.contains("# TYPE blizzard_lobby_connections_total counter\n"));
+    assert!(text.contains("\nblizzard_lobby_connections_total 3\n"));
+    assert!(text.contains("\nblizzard_messages_dropped_total{reason=\"flood\"} 2\n"));
+    assert!(text.contains("\nblizzard_game_players{port=\"7000\"} 2\n"));
+    assert!(text.contains("\nblizzard_game_updates_total{port=\"7000\"} 2\n"));
+    assert!(text.contains("\nblizzard_game_update_seconds{port=\"7000\"} 0.004\n"));
+    assert!(text.contains("\nblizzard_game_update_max_seconds{port=\"7000\"} 0.004\n"));
+}
+
+#[test]
+fn metrics_on_every_interface_stop_with_the_server() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .shutdown_timeout(Duration::from_millis(100))
+        .metrics_address("0.0.0.0:39202".parse().unwrap())
+        .build()
+        .unwrap();
+    let (_, server) = common::start_server_handle(config, &common::handle_input);
+
+    // The endpoint is woken up through loopback and closes its port
+    server.shutdown("Done");
+    assert!(TcpStream::connect("127.0.0.1:39202").is_err());
+}
+
+#[test]
+fn slow_scrapers_are_dropped_after_the_request_timeout() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .metrics_address("127.0.0.1:39203".parse().unwrap())
+        .build()
+        .unwrap();
+    common::start_server(config);
+
+    // One byte every second never finishes the request
+    let mut slow = loop {
+        if let Ok(stream) = TcpStream::connect("127.0.0.1:39203") {
+            break stream;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let started = Instant::now();
+    let mut response = Vec::new();
+    while slow.write_all(b"G").is_ok() && started.elapsed() < Duration::from_secs(10) {
+        thread::sleep(Duration::from_secs(1));
+        slow.set_nonblocking(true).unwrap();
+        if let Ok(0) = slow.read_to_end(&mut response) {
+            break;
+        }
+        slow.set_nonblocking(false).unwrap();
+    }
+    assert!(started.elapsed() < Duration::from_secs(8));
+}
@@ -0,0 +1,69 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::protocol::{Hello, LobbyPacket, RejectReason};
+use blizzard_server::server::transport::Transport;
+use blizzard_server::server::ServerConfig;
+
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn games_start_when_running_ones_are_full() {
+    let config = ServerConfig::builder()
+        .max_games(2)
+        .min_games(1)
+        .max_players(1)
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    // Only the first game runs until it is full
+    let first = common::find_game(&transport);
+    assert_eq!(first.port, 7000);
+    assert!(transport.connect(7001).is_err());
+
+    let second = common::find_game(&transport);
+    assert_eq!(second.port, 7001);
+    common::join(&transport, &second);
+
+    // Global cap
+    assert_eq!(
+        common::hello(&transport, &Hello::new("third")),
+        LobbyPacket::Rejected(RejectReason::Full)
+    );
+}
+
+#[test]
+fn idle_games_are_retired() {
+    let config = ServerConfig::builder()
+        .max_games(2)
+        .min_games(1)
+        .max_players(1)
+        .reservation_timeout(Duration::from_millis(50))
+        .idle_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let transport = common::start_server(config);
+
+    // Unused tickets start a second game
+    common::find_game(&transport);
+    assert_eq!(common::find_game(&transport).port, 7001);
+
+    // Both games go idle, one keeps running
+    thread::sleep(Duration::from_millis(500));
+    let open = [7000, 7001]
+        .iter()
+        .filter(|port| transport.connect(**port).is_ok())
+        .count();
+    assert_eq!(open, 1);
+
+    // Retired port can be used by a new game
+    let ports = [
+        common::find_game(&transport).port,
+        common::find_game(&transport).port,
+    ];
+    assert!(ports.contains(&7000) && ports.contains(&7001));
+}
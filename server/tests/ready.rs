@@ -0,0 +1,85 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::game::{MatchPhase, PlayerEvent};
+use blizzard_server::protocol::{ClientPacket, RosterEntry, ServerPacket};
+use blizzard_server::server::transport::ChannelConnection;
+use blizzard_server::server::ServerConfig;
+
+use std::io::BufReader;
+use std::time::Duration;
+
+/// Reads packets until a roster passes the check, panics if the connection closes first
+fn wait_for_roster<F>(reader: &mut BufReader<ChannelConnection>, check: F) -> Vec<RosterEntry>
+where
+    F: Fn(&[RosterEntry]) -> bool,
+{
+    loop {
+        match common::receive(reader) {
+            Some(ServerPacket::Roster(roster)) if check(&roster) => return roster,
+            Some(ServerPacket::Phase(MatchPhase::Running)) => panic!("Match started"),
+            Some(_) => continue,
+            None => panic!("Connection closed"),
+        }
+    }
+}
+
+#[test]
+fn match_starts_when_everyone_is_ready() {
+    let (handle_input, events) = common::recording_handler();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .max_players(2)
+        .min_players(2)
+        .ready_check(true)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .build()
+        .unwrap();
+    let transport = common::start_server_with(config, handle_input);
+
+    let mut first = common::join(&transport, &common::find_game(&transport));
+    let roster = wait_for_roster(&mut first.reader, |roster| roster.len() == 1);
+    assert_eq!(roster[0].player_id, first.id);
+    assert!(!roster[0].setup.ready);
+
+    // Picks show up in the roster
+    common::send(
+        &mut first.connection,
+        &ClientPacket::PickTeam("red".to_string()),
+    );
+    common::send(
+        &mut first.connection,
+        &ClientPacket::SetOption {
+            key: "character".to_string(),
+            value: "wizard".to_string(),
+        },
+    );
+    common::send(&mut first.connection, &ClientPacket::Ready(true));
+    let roster = wait_for_roster(&mut first.reader, |roster| roster[0].setup.ready);
+    assert_eq!(roster[0].setup.team, Some("red".to_string()));
+    assert_eq!(roster[0].setup.options["character"], "wizard");
+
+    // The second player is not ready yet, so the match keeps waiting for it
+    let mut second = common::join(&transport, &common::find_game(&transport));
+    wait_for_roster(&mut first.reader, |roster| roster.len() == 2);
+    common::send(&mut second.connection, &ClientPacket::Ready(true));
+    assert!(common::wait_for_phase(
+        &mut first.reader,
+        MatchPhase::Running
+    ));
+
+    // The game gets the picks before the match starts
+    loop {
+        match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+            (PlayerEvent::Setup(setup), id) if id == first.id => {
+                assert_eq!(setup.team, Some("red".to_string()));
+                assert!(setup.ready);
+                break;
+            }
+            _ => continue,
+        }
+    }
+}
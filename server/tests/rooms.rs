@@ -0,0 +1,131 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::game::MatchPhase;
+use blizzard_server::protocol::{
+    ClientPacket, Hello, JoinTicket, LobbyPacket, RejectReason, RoomRequest, ServerPacket,
+};
+use blizzard_server::server::transport::{ChannelTransport, Connection};
+use blizzard_server::server::ServerConfig;
+
+use std::time::Duration;
+
+fn start_server() -> ChannelTransport {
+    let config = ServerConfig::builder()
+        .max_games(2)
+        .min_games(1)
+        .max_players(4)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .build()
+        .unwrap();
+    common::start_server(config)
+}
+
+fn enter_room(transport: &ChannelTransport, room: RoomRequest) -> LobbyPacket {
+    let mut hello = Hello::new("friend");
+    hello.room = Some(room);
+    common::hello(transport, &hello)
+}
+
+fn welcome(packet: LobbyPacket) -> JoinTicket {
+    match packet {
+        LobbyPacket::Welcome(ticket) => ticket,
+        other => panic!("Lobby rejected the client: {:?}", other),
+    }
+}
+
+#[test]
+fn friends_join_room_by_code() {
+    let transport = start_server();
+
+    let host = welcome(enter_room(
+        &transport,
+        RoomRequest::Create {
+            password: Some("secret".to_string()),
+        },
+    ));
+    let code = host.room_code.clone().unwrap();
+    assert_eq!(code.len(), 6);
+
+    let friend = welcome(enter_room(
+        &transport,
+        RoomRequest::Join {
+            code: code.clone(),
+            password: Some("secret".to_string()),
+        },
+    ));
+    assert_eq!(friend.port, host.port);
+
+    assert_eq!(
+        enter_room(
+            &transport,
+            RoomRequest::Join {
+                code: code.clone(),
+                password: None,
+            },
+        ),
+        LobbyPacket::Rejected(RejectReason::WrongPassword)
+    );
+    assert_eq!(
+        enter_room(
+            &transport,
+            RoomRequest::Join {
+                code: "NOROOM".to_string(),
+                password: None,
+            },
+        ),
+        LobbyPacket::Rejected(RejectReason::RoomNotFound)
+    );
+
+    // Strangers are never matched into the room
+    assert_ne!(common::find_game(&transport).port, host.port);
+}
+
+#[test]
+fn host_starts_the_match() {
+    let transport = start_server();
+
+    let ticket = welcome(enter_room(
+        &transport,
+        RoomRequest::Create { password: None },
+    ));
+    let mut host = common::join(&transport, &ticket);
+    let code = ticket.room_code.unwrap();
+    let mut friend = common::join(
+        &transport,
+        &welcome(enter_room(
+            &transport,
+            RoomRequest::Join {
+                code,
+                password: None,
+            },
+        )),
+    );
+
+    // Only the host can start, the room waits for it
+    common::send(&mut friend.connection, &ClientPacket::StartMatch);
+    friend
+        .connection
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    while let Some(packet) = common::receive(&mut friend.reader) {
+        assert!(matches!(
+            packet,
+            ServerPacket::Phase(MatchPhase::Waiting) | ServerPacket::State { .. }
+        ));
+    }
+    friend.connection.set_read_timeout(None).unwrap();
+
+    common::send(&mut host.connection, &ClientPacket::StartMatch);
+    assert!(common::wait_for_phase(
+        &mut friend.reader,
+        MatchPhase::Running
+    ));
+    assert!(common::wait_for_phase(
+        &mut host.reader,
+        MatchPhase::Running
+    ));
+}
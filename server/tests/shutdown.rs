@@ -0,0 +1,159 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_engine::game::Game;
+use blizzard_server::game::MatchPhase;
+use blizzard_server::protocol::{ClientPacket, ServerPacket};
+use blizzard_server::server::transport::{ChannelTransport, Connection, Transport};
+use blizzard_server::server::{Server, ServerConfig, ServerError};
+
+use std::io::BufReader;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Match ends after a second of updates
+#[derive(Clone)]
+struct OneSecond {
+    updates: u32,
+}
+
+impl Game<u32, u32> for OneSecond {
+    fn world_config(&mut self) {}
+    fn update(&mut self, input: u32, shared_state: Arc<Mutex<u32>>) {
+        self.updates += 1;
+        *shared_state.lock().unwrap() += input;
+    }
+    fn reset_input(&mut self, input: Arc<Mutex<u32>>) {
+        *input.lock().unwrap() = 0;
+    }
+    fn render(&mut self) {}
+    fn end_game(&self) -> bool {
+        self.updates >= 50
+    }
+}
+
+/// Reads packets until the shutdown reason, panics if the connection closes first
+fn shutdown_reason(player: &mut common::Player) -> String {
+    loop {
+        match common::receive(&mut player.reader) {
+            Some(ServerPacket::Shutdown { reason }) => return reason,
+            Some(_) => continue,
+            None => panic!("Connection closed without a reason"),
+        }
+    }
+}
+
+#[test]
+fn players_get_the_reason_and_stopped_matches_are_reported() {
+    let (reports, reported) = mpsc::channel();
+    let reports = Mutex::new(reports);
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .shutdown_timeout(Duration::from_millis(100))
+        .on_match_end(move |report| reports.lock().unwrap().send(report.clone()).unwrap())
+        .build()
+        .unwrap();
+    let (transport, server) = common::start_server_handle(config, &common::handle_input);
+    let ticket = common::find_game(&transport);
+    let mut player = common::join(&transport, &ticket);
+
+    common::send(
+        &mut player.connection,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 2,
+        },
+    );
+    assert_eq!(common::wait_for_state(&mut player.reader, 2), 2);
+
+    let waiter = server.clone();
+    let waiting = thread::spawn(move || waiter.wait());
+    server.shutdown("Maintenance");
+    assert!(server.is_shutting_down());
+    waiting.join().unwrap();
+
+    // Later calls return once the server is down
+    server.shutdown("Again");
+
+    assert_eq!(shutdown_reason(&mut player), "Maintenance");
+    assert!(common::receive(&mut player.reader).is_none());
+    assert!(transport.connect(common::LOBBY_PORT).is_err());
+    assert!(transport.connect(ticket.port).is_err());
+
+    // The match never ended, games get its state to save it
+    let report = reported.recv_timeout(TIMEOUT).unwrap();
+    assert!(report.interrupted);
+    assert_eq!(report.result, serde_json::json!(2));
+    assert_eq!(report.players.len(), 1);
+    assert_eq!(report.players[0].0, player.id);
+}
+
+#[test]
+fn running_matches_finish_before_the_shutdown() {
+    let (reports, reported) = mpsc::channel();
+    let reports = Mutex::new(reports);
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .shutdown_timeout(TIMEOUT)
+        .on_match_end(move |report| reports.lock().unwrap().send(report.clone()).unwrap())
+        .build()
+        .unwrap();
+    let transport = ChannelTransport::new();
+    let server = Server::with_transport(
+        transport.clone(),
+        config,
+        OneSecond { updates: 0 },
+        0,
+        0,
+        &common::handle_input,
+    );
+    let mut player = common::join(&transport, &common::find_game(&transport));
+    let late_ticket = common::find_game(&transport);
+    assert!(common::wait_for_phase(
+        &mut player.reader,
+        MatchPhase::Running
+    ));
+
+    let started = Instant::now();
+    let stopping = server.clone();
+    let shutdown = thread::spawn(move || stopping.shutdown("Maintenance"));
+
+    // Closing games take no more players
+    thread::sleep(Duration::from_millis(100));
+    let mut connection = common::connect(&transport, late_ticket.port);
+    let mut reader = BufReader::new(connection.try_clone().unwrap());
+    common::send(
+        &mut connection,
+        &ClientPacket::Join {
+            ticket: late_ticket.id.clone(),
+        },
+    );
+    match common::receive(&mut reader) {
+        Some(ServerPacket::Rejected { reason }) => {
+            assert_eq!(reason, ServerError::ShuttingDown.to_string())
+        }
+        other => panic!("Joined a closing game: {:?}", other),
+    }
+
+    assert!(common::wait_for_phase(
+        &mut player.reader,
+        MatchPhase::Finished
+    ));
+    assert_eq!(shutdown_reason(&mut player), "Maintenance");
+    shutdown.join().unwrap();
+    assert!(started.elapsed() < TIMEOUT);
+
+    let report = reported.recv_timeout(TIMEOUT).unwrap();
+    assert!(!report.interrupted);
+    assert_eq!(report.players.len(), 1);
+}
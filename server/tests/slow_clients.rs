@@ -0,0 +1,86 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::protocol::ClientPacket;
+use blizzard_server::server::error::{ErrorSource, SlowClientPolicy};
+use blizzard_server::server::transport::ChannelTransport;
+use blizzard_server::server::{Server, ServerConfig, ServerError};
+
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+// Pipes hold a few packets, so a client that stops reading fills its queue fast
+fn start_server(policy: SlowClientPolicy) -> (ChannelTransport, Receiver<(usize, ErrorSource)>) {
+    let (errors, reported) = mpsc::channel();
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(100)
+        .game_update_rate(50)
+        .send_queue_limit(4)
+        .slow_client(policy)
+        .on_error(move |error, source| {
+            if let ServerError::SlowClient(queued) = error {
+                errors.send((*queued, source)).unwrap_or(());
+            }
+        })
+        .build()
+        .unwrap();
+
+    let transport = ChannelTransport::with_capacity(128);
+    let server_transport = transport.clone();
+    thread::spawn(move || {
+        Server::with_transport(
+            server_transport,
+            config,
+            common::CountGame {},
+            0,
+            0,
+            &common::handle_input,
+        );
+    });
+    (transport, reported)
+}
+
+#[test]
+fn slow_client_is_disconnected() {
+    let (transport, reported) = start_server(SlowClientPolicy::Disconnect);
+    let mut slow = common::join(&transport, &common::find_game(&transport));
+
+    // Client never reads, its queue fills up
+    let (queued, source) = reported.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(queued >= 4);
+    assert_eq!(
+        source,
+        ErrorSource::Player {
+            port: 7000,
+            player_id: slow.id
+        }
+    );
+
+    // Connection is closed once the client reads again
+    let mut rest = Vec::new();
+    slow.reader.read_to_end(&mut rest).unwrap();
+}
+
+#[test]
+fn slow_client_skips_states() {
+    let (transport, reported) = start_server(SlowClientPolicy::Drop);
+    let _slow = common::join(&transport, &common::find_game(&transport));
+    let mut good = common::join(&transport, &common::find_game(&transport));
+
+    // Other players keep up, the slow one is neither reported nor dropped
+    thread::sleep(Duration::from_millis(300));
+    common::send(
+        &mut good.connection,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 3,
+        },
+    );
+    assert_eq!(common::wait_for_state(&mut good.reader, 3), 3);
+    assert!(reported.try_recv().is_err());
+}
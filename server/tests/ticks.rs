@@ -0,0 +1,167 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_engine::game::Game;
+use blizzard_server::game::{MatchPhase, PlayerEvent};
+use blizzard_server::protocol::{ClientPacket, ServerPacket};
+use blizzard_server::server::transport::ChannelTransport;
+use blizzard_server::server::{Server, ServerConfig};
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn states_are_sent_once_per_tick() {
+    let transport = common::start_server(common::test_config());
+    let mut player = common::join(&transport, &common::find_game(&transport));
+
+    // Broadcast runs as fast as the app, ticks only repeat with a phase change
+    assert!(common::wait_for_phase(
+        &mut player.reader,
+        MatchPhase::Running
+    ));
+    let mut ticks = vec![];
+    while ticks.len() < 10 {
+        if let Some(ServerPacket::State { tick, .. }) = common::receive(&mut player.reader) {
+            ticks.push(tick);
+        }
+    }
+    for pair in ticks.windows(2) {
+        assert!(pair[0] < pair[1], "tick repeated: {:?}", ticks);
+    }
+}
+
+#[test]
+fn inputs_carry_their_tick() {
+    let (handle_input, received) = common::recording_handler();
+    let transport = common::start_server_with(common::test_config(), handle_input);
+    let mut player = common::join(&transport, &common::find_game(&transport));
+
+    // Acks are accepted, the player is not disconnected for them
+    let tick = loop {
+        if let Some(ServerPacket::State { tick, .. }) = common::receive(&mut player.reader) {
+            break tick;
+        }
+    };
+    common::send(&mut player.connection, &ClientPacket::Ack(tick));
+    common::send(
+        &mut player.connection,
+        &ClientPacket::Input { tick, message: 2 },
+    );
+
+    let message = loop {
+        match received.recv_timeout(Duration::from_secs(5)).unwrap() {
+            (PlayerEvent::Message { tick, message }, _) => break (tick, message),
+            _ => continue,
+        }
+    };
+    assert_eq!(message, (tick, 2));
+    assert!(common::receive(&mut player.reader).is_some());
+}
+
+// The state is the amount of updates, changed at the start of updates slower than the update rate
+#[derive(Clone)]
+struct SlowCounter {}
+
+impl Game<u32, u32> for SlowCounter {
+    fn world_config(&mut self) {}
+    fn update(&mut self, _input: u32, shared_state: Arc<Mutex<u32>>) {
+        *shared_state.lock().unwrap() += 1;
+        thread::sleep(Duration::from_millis(20));
+    }
+    fn reset_input(&mut self, _input: Arc<Mutex<u32>>) {}
+    fn render(&mut self) {}
+    fn end_game(&self) -> bool {
+        false
+    }
+}
+
+#[test]
+fn states_are_sent_with_the_tick_they_belong_to() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .send_data_rate(1000)
+        .game_update_rate(100)
+        .build()
+        .unwrap();
+    let transport = ChannelTransport::new();
+    Server::with_transport(
+        transport.clone(),
+        config,
+        SlowCounter {},
+        0,
+        0,
+        &common::handle_input,
+    );
+    let mut player = common::join(&transport, &common::find_game(&transport));
+
+    // A state read in the middle of an update would go out with the tick before it
+    let mut states = 0;
+    while states < 20 {
+        match common::receive(&mut player.reader) {
+            Some(ServerPacket::State { tick, state }) => {
+                assert_eq!(u64::from(state), tick, "state sent with another tick");
+                states += 1;
+            }
+            Some(_) => continue,
+            None => panic!("Connection closed"),
+        }
+    }
+}
+
+// Updates that take much longer than a frame
+#[derive(Clone)]
+struct StalledCounter {}
+
+impl Game<u32, u32> for StalledCounter {
+    fn world_config(&mut self) {}
+    fn update(&mut self, _input: u32, shared_state: Arc<Mutex<u32>>) {
+        *shared_state.lock().unwrap() += 1;
+        thread::sleep(Duration::from_secs(1));
+    }
+    fn reset_input(&mut self, _input: Arc<Mutex<u32>>) {}
+    fn render(&mut self) {}
+    fn end_game(&self) -> bool {
+        false
+    }
+}
+
+#[test]
+fn slow_updates_never_hold_up_players() {
+    let config = ServerConfig::builder()
+        .max_games(1)
+        .max_players(8)
+        .send_data_rate(50)
+        .game_update_rate(100)
+        .build()
+        .unwrap();
+    let transport = ChannelTransport::new();
+    Server::with_transport(
+        transport.clone(),
+        config,
+        StalledCounter {},
+        0,
+        0,
+        &common::handle_input,
+    );
+    let mut first = common::join(&transport, &common::find_game(&transport));
+    loop {
+        match common::receive(&mut first.reader) {
+            Some(ServerPacket::State { state, .. }) if state > 0 => break,
+            Some(_) => continue,
+            None => panic!("Connection closed"),
+        }
+    }
+
+    // Updates run back to back, joining needs the game, which the broadcast only holds to queue packets
+    for _ in 0..3 {
+        thread::sleep(Duration::from_millis(200));
+        let ticket = common::find_game(&transport);
+        let started = Instant::now();
+        common::join(&transport, &ticket);
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+}
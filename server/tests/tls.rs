@@ -0,0 +1,177 @@
+//! TLS transport, run with `cargo test --features tls`
+#![cfg(feature = "tls")]
+
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::protocol::{ClientPacket, Hello, LobbyPacket, ServerPacket};
+use blizzard_server::server::config::TlsFiles;
+use blizzard_server::server::transport::{
+    Connection, Listener, TcpTransport, TlsConnection, TlsTransport, Transport,
+};
+use blizzard_server::server::{Server, ServerConfig};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const LOBBY_PORT: i32 = 38889;
+
+/// Test certificate for `localhost`, signed by a test authority
+fn files() -> TlsFiles {
+    let certs = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/certs");
+    TlsFiles {
+        certificate: certs.join("cert.pem"),
+        key: certs.join("key.pem"),
+    }
+}
+
+fn localhost() -> TlsTransport {
+    let tcp = TcpTransport::bound_to(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    TlsTransport::new(tcp, &files()).unwrap()
+}
+
+#[test]
+fn tls_connections_read_and_write() {
+    let transport = localhost();
+    let listener = transport.bind(0).unwrap();
+    let client = transport.connect(listener.port()).unwrap();
+    let server = listener.accept().unwrap();
+
+    // Writing waits for the handshake, which the server reads
+    let mut hello = client.try_clone().unwrap();
+    thread::spawn(move || hello.write_all(b"hello\n").unwrap());
+    let mut reader = BufReader::new(server.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "hello\n");
+
+    // Writer and reader of the same connection on different threads
+    let mut writer = server.try_clone().unwrap();
+    thread::spawn(move || writer.write_all(b"welcome\n").unwrap());
+    let mut reply = String::new();
+    BufReader::new(client.try_clone().unwrap())
+        .read_line(&mut reply)
+        .unwrap();
+    assert_eq!(reply, "welcome\n");
+
+    // Close notify ends the connection
+    client.close().unwrap();
+    line.clear();
+    assert_eq!(reader.read_line(&mut line).unwrap(), 0);
+}
+
+#[test]
+fn closing_never_waits_for_a_client_that_stopped_reading() {
+    let transport = localhost();
+    let listener = transport.bind(0).unwrap();
+    let client = transport.connect(listener.port()).unwrap();
+    let server = listener.accept().unwrap();
+
+    // Finish the handshake, then the client reads nothing more
+    let mut hello = client.try_clone().unwrap();
+    thread::spawn(move || hello.write_all(b"hello\n").unwrap());
+    BufReader::new(server.try_clone().unwrap())
+        .read_line(&mut String::new())
+        .unwrap();
+
+    // The writer blocks once the socket buffers are full
+    let mut writer = server.try_clone().unwrap();
+    thread::spawn(move || while writer.write_all(&[0; 64 * 1024]).is_ok() {});
+    thread::sleep(Duration::from_millis(500));
+
+    let (closed, done) = mpsc::channel();
+    thread::spawn(move || closed.send(server.close()).unwrap());
+    assert!(done.recv_timeout(Duration::from_secs(5)).is_ok());
+    drop(client);
+}
+
+#[test]
+fn missing_key_fails() {
+    let mut files = files();
+    files.key = files.certificate.clone();
+    let tcp = TcpTransport::bound_to(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    assert!(TlsTransport::new(tcp, &files).is_err());
+}
+
+#[test]
+fn server_runs_over_tls() {
+    let files = files();
+    let config = ServerConfig::builder()
+        .bind_address(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        .lobby_port(LOBBY_PORT)
+        .ephemeral_game_ports()
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .tls(files.certificate, files.key)
+        .build()
+        .unwrap();
+    thread::spawn(move || {
+        Server::start(config, common::CountGame {}, 0, 0, &common::handle_input);
+    });
+
+    // Plain TCP clients get nothing readable from the lobby
+    let mut plain = connect_plain(LOBBY_PORT);
+    plain.write_all(b"{\"username\":\"player\"}\n").unwrap();
+    let mut answer = Vec::new();
+    plain.read_to_end(&mut answer).unwrap_or(0);
+    assert!(!String::from_utf8_lossy(&answer).contains("Rejected"));
+
+    let transport = localhost();
+    let mut lobby = transport.connect(LOBBY_PORT).unwrap();
+    send(&mut lobby, &Hello::new("player"));
+    let ticket = match receive::<LobbyPacket>(&mut BufReader::new(lobby)) {
+        LobbyPacket::Welcome(ticket) => ticket,
+        other => panic!("Lobby rejected the client: {:?}", other),
+    };
+
+    let mut game = transport.connect(ticket.port).unwrap();
+    send(&mut game, &ClientPacket::<u32>::Join { ticket: ticket.id });
+    let mut reader = BufReader::new(game.try_clone().unwrap());
+    match receive::<ServerPacket<u32>>(&mut reader) {
+        ServerPacket::Session { .. } => {}
+        other => panic!("Could not join: {:?}", other),
+    }
+
+    send(
+        &mut game,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 6,
+        },
+    );
+    loop {
+        if let ServerPacket::State { state: 6, .. } = receive::<ServerPacket<u32>>(&mut reader) {
+            break;
+        }
+    }
+}
+
+/// Retry until the server thread has opened the port
+fn connect_plain(port: i32) -> std::net::TcpStream {
+    let tcp = TcpTransport::bound_to(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    for _ in 0..100 {
+        if let Ok(connection) = tcp.connect(port) {
+            return connection;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Could not connect to port {}", port);
+}
+
+fn send<P: serde::Serialize>(connection: &mut TlsConnection, packet: &P) {
+    let json = serde_json::to_string(packet).unwrap() + "\n";
+    connection.write_all(json.as_bytes()).unwrap();
+}
+
+fn receive<P: serde::de::DeserializeOwned>(reader: &mut BufReader<TlsConnection>) -> P {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    serde_json::from_str(line.trim()).unwrap()
+}
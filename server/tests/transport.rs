@@ -0,0 +1,61 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::protocol::ClientPacket;
+use blizzard_server::server::transport::{ChannelTransport, Connection, Listener, Transport};
+
+use std::io::{BufRead, BufReader, Read, Write};
+
+#[test]
+fn channel_connection_round_trip() {
+    let transport = ChannelTransport::new();
+    let listener = transport.bind(0).unwrap();
+    let mut client = transport.connect(listener.port()).unwrap();
+    let mut server = listener.accept().unwrap();
+
+    client.write_all(b"ping\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(server.try_clone().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    assert_eq!(line, "ping\n");
+
+    server.write_all(b"pong").unwrap();
+    drop(server);
+    let mut reply = String::new();
+    client.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "pong");
+}
+
+#[test]
+fn channel_ports_are_exclusive() {
+    let transport = ChannelTransport::new();
+    let listener = transport.bind(7000).unwrap();
+    assert!(transport.bind(7000).is_err());
+
+    drop(listener);
+    assert!(transport.bind(7000).is_ok());
+    assert!(transport.connect(7001).is_err());
+}
+
+#[test]
+fn server_runs_over_channels() {
+    let transport = common::start_server(common::test_config());
+
+    // Lobby hands out a ticket for the game port
+    let ticket = common::find_game(&transport);
+    assert_eq!(ticket.port, 7000);
+
+    // Game receives messages and shares its state
+    let mut player = common::join(&transport, &ticket);
+    common::send(
+        &mut player.connection,
+        &ClientPacket::Input {
+            tick: 0,
+            message: 5,
+        },
+    );
+    assert_eq!(common::wait_for_state(&mut player.reader, 5), 5);
+}
@@ -0,0 +1,121 @@
+extern crate blizzard_engine;
+extern crate blizzard_server;
+
+mod common;
+
+use blizzard_server::protocol::{ClientPacket, Hello, LobbyPacket, RejectReason};
+use blizzard_server::server::config::ServerConfigBuilder;
+use blizzard_server::server::error::ErrorSource;
+use blizzard_server::server::transport::ChannelTransport;
+use blizzard_server::server::validation::{History, Verdict};
+use blizzard_server::server::{Server, ServerConfig, ServerError};
+
+use std::io::Read;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accepts steps of one, rejects anything bigger with 10 points
+fn small_steps(_player: usize, _tick: u64, step: &u32, _history: &History<u32>) -> Verdict {
+    if *step > 1 {
+        Verdict::Reject(10)
+    } else {
+        Verdict::Accept
+    }
+}
+
+/// Starts a validated count game, reporting the errors of players
+fn start_server(builder: ServerConfigBuilder) -> (ChannelTransport, Receiver<String>) {
+    let (errors, reported) = mpsc::channel();
+    let config = builder
+        .max_games(1)
+        .send_data_rate(50)
+        .game_update_rate(50)
+        .suspicion_decay(0)
+        .on_error(move |error, source| {
+            if let ErrorSource::Player { .. } = source {
+                errors.send(error.to_string()).unwrap_or(());
+            }
+        })
+        .build()
+        .unwrap();
+
+    let transport = ChannelTransport::new();
+    let server_transport = transport.clone();
+    thread::spawn(move || {
+        Server::with_validator(
+            server_transport,
+            config,
+            common::CountGame {},
+            0,
+            0,
+            &common::handle_input,
+            small_steps,
+        );
+    });
+    (transport, reported)
+}
+
+fn send_input(player: &mut common::Player, message: u32) {
+    let input = ClientPacket::Input { tick: 0, message };
+    common::send(&mut player.connection, &input);
+}
+
+#[test]
+fn rejected_messages_never_reach_the_game() {
+    let (transport, reported) = start_server(ServerConfig::builder());
+    let mut player = common::join(&transport, &common::find_game(&transport));
+
+    send_input(&mut player, 1);
+    send_input(&mut player, 5);
+    send_input(&mut player, 6);
+    send_input(&mut player, 1);
+    assert_eq!(common::wait_for_state(&mut player.reader, 2), 2);
+
+    // Rejections in a row are reported once
+    assert_eq!(
+        reported.recv_timeout(TIMEOUT).unwrap(),
+        ServerError::MessageRejected(10).to_string()
+    );
+    assert!(reported.recv_timeout(Duration::from_millis(200)).is_err());
+}
+
+#[test]
+fn suspicious_player_is_kicked() {
+    let (transport, reported) = start_server(ServerConfig::builder().kick_suspicion(Some(20)));
+    let mut player = common::join(&transport, &common::find_game(&transport));
+
+    send_input(&mut player, 5);
+    send_input(&mut player, 5);
+    let mut rest = Vec::new();
+    player.reader.read_to_end(&mut rest).unwrap();
+    let errors: Vec<_> = (0..2)
+        .map(|_| reported.recv_timeout(TIMEOUT).unwrap())
+        .collect();
+    assert_eq!(
+        errors,
+        vec![
+            ServerError::MessageRejected(10).to_string(),
+            ServerError::TooSuspicious(20).to_string()
+        ]
+    );
+}
+
+#[test]
+fn cheater_is_banned() {
+    let builder = ServerConfig::builder()
+        .kick_suspicion(None)
+        .ban_suspicion(Some(10));
+    let (transport, _reported) = start_server(builder);
+    let mut player = common::join(&transport, &common::find_game(&transport));
+
+    send_input(&mut player, 5);
+    let mut rest = Vec::new();
+    player.reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(
+        common::hello(&transport, &Hello::new("player")),
+        LobbyPacket::Rejected(RejectReason::Banned)
+    );
+}